@@ -0,0 +1,10 @@
+// The Postgres integration tests need a database to run against. They're built
+// ignored unless TEST_POSTGRES_URL names one, so a run without it doesn't report
+// them as passing.
+fn main() {
+    println!("cargo::rerun-if-env-changed=TEST_POSTGRES_URL");
+    println!("cargo::rustc-check-cfg=cfg(postgres_tests)");
+    if std::env::var_os("TEST_POSTGRES_URL").is_some() {
+        println!("cargo::rustc-cfg=postgres_tests");
+    }
+}
@@ -0,0 +1,273 @@
+use crate::db::Database;
+use crate::error::{AppError, ErrorCode};
+use crate::models::archive::{
+    ARCHIVE_VERSION, Archive, ArchivedEvent, ArchivedRoom, ConflictPolicy, ImportOutcome,
+    ImportReport, ImportedRoom,
+};
+use crate::models::audit::AuditFilter;
+use crate::models::room::RoomId;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+// Archive the given rooms, leaving out any that no longer exist
+pub async fn export_rooms(db: &dyn Database, room_ids: &[RoomId]) -> Result<Archive, AppError> {
+    let mut rooms = Vec::with_capacity(room_ids.len());
+    for room_id in room_ids {
+        if let Some(archived) = export_room(db, room_id).await? {
+            rooms.push(archived);
+        }
+    }
+
+    Ok(Archive {
+        version: ARCHIVE_VERSION,
+        exported_at: OffsetDateTime::now_utc(),
+        rooms,
+    })
+}
+
+async fn export_room(
+    db: &dyn Database,
+    room_id: &RoomId,
+) -> Result<Option<ArchivedRoom>, AppError> {
+    let Some(room) = db.get_room(room_id).await? else {
+        return Ok(None);
+    };
+
+    let votes = db.get_votes(room_id).await?;
+    let chat_messages = db.get_chat_messages(room_id).await?;
+
+    // The whole audit log, oldest first
+    let mut audit_events = db
+        .get_audit_events(
+            room_id,
+            &AuditFilter {
+                action: None,
+                actor_id: None,
+                since: None,
+                until: None,
+                before: None,
+                limit: i64::MAX,
+            },
+        )
+        .await?;
+    audit_events.reverse();
+
+    let mut events = Vec::new();
+    for logged in db.get_room_events(room_id, 0).await? {
+        events.push(ArchivedEvent {
+            seq: logged.seq,
+            event: serde_json::from_str(&logged.event).map_err(|e| {
+                AppError::DatabaseError(format!("Invalid logged event {}: {}", logged.seq, e))
+            })?,
+            vote: logged.vote,
+            created_at: logged.created_at,
+        });
+    }
+
+    Ok(Some(ArchivedRoom {
+        room,
+        votes,
+        chat_messages,
+        audit_events,
+        events,
+    }))
+}
+
+// Restore archived rooms in one transaction, so a failed import changes nothing
+pub async fn import_rooms(
+    db: &dyn Database,
+    archive: Archive,
+    on_conflict: ConflictPolicy,
+    remap_ids: bool,
+) -> Result<ImportReport, AppError> {
+    if archive.version != ARCHIVE_VERSION {
+        return Err(AppError::BadRequest(
+            ErrorCode::UnsupportedArchiveVersion,
+            format!(
+                "Unsupported archive version: {} (expected {})",
+                archive.version, ARCHIVE_VERSION
+            ),
+        ));
+    }
+
+    for archived in &archive.rooms {
+        validate(archived)?;
+    }
+
+    let mut tx = db.begin().await?;
+    let mut rooms = Vec::with_capacity(archive.rooms.len());
+
+    for archived in archive.rooms {
+        let original_id = archived.room.id.clone();
+
+        let (archived, outcome) = if remap_ids {
+            (remap(archived)?, ImportOutcome::Remapped)
+        } else if tx.ids_taken(&archived).await? {
+            match on_conflict {
+                ConflictPolicy::Fail => {
+                    return Err(AppError::Conflict(
+                        ErrorCode::RoomAlreadyExists,
+                        format!("Room {} or some of its users already exist", original_id),
+                    ));
+                }
+                ConflictPolicy::Skip => {
+                    rooms.push(ImportedRoom {
+                        original_id,
+                        room_id: None,
+                        outcome: ImportOutcome::Skipped,
+                    });
+                    continue;
+                }
+                ConflictPolicy::Remap => (remap(archived)?, ImportOutcome::Remapped),
+            }
+        } else {
+            (archived, ImportOutcome::Imported)
+        };
+
+        tx.restore_room(&archived).await?;
+
+        rooms.push(ImportedRoom {
+            original_id,
+            room_id: Some(archived.room.id),
+            outcome,
+        });
+    }
+
+    tx.commit().await?;
+
+    Ok(ImportReport { rooms })
+}
+
+// Reject archives that can't be stored, before anything is written
+fn validate(archived: &ArchivedRoom) -> Result<(), AppError> {
+    let room = &archived.room;
+    let invalid = |reason: String| {
+        Err(AppError::BadRequest(
+            ErrorCode::InvalidArchive,
+            format!("Invalid archived room {}: {}", room.id, reason),
+        ))
+    };
+
+    if let Some(owner_id) = &room.owner_id
+        && !room.users.contains_key(owner_id)
+    {
+        return invalid(format!("owner {} is not in the room", owner_id));
+    }
+
+    for (user_id, user) in &room.users {
+        if user.id != *user_id {
+            return invalid(format!("user {} is listed as {}", user.id, user_id));
+        }
+    }
+
+    // Every round's votes are restored from the list, which has to agree with
+    // the room on the current round
+    let mut rounds = HashSet::new();
+    for archived_vote in &archived.votes {
+        let (user_id, round_id) = (&archived_vote.user_id, archived_vote.round_id);
+        if !room.users.contains_key(user_id) {
+            return invalid(format!("vote of user {} who is not in the room", user_id));
+        }
+        if round_id < 1 || round_id > room.round_id {
+            return invalid(format!(
+                "vote of user {} in unknown round {}",
+                user_id, round_id
+            ));
+        }
+        if !rounds.insert((user_id, round_id)) {
+            return invalid(format!(
+                "user {} voted twice in round {}",
+                user_id, round_id
+            ));
+        }
+        if round_id == room.round_id
+            && room.votes.get(user_id).map(|cast| &cast.vote) != Some(&archived_vote.vote.vote)
+        {
+            return invalid(format!("vote of user {} doesn't match the room", user_id));
+        }
+    }
+    for user_id in room.votes.keys() {
+        if !rounds.contains(&(user_id, room.round_id)) {
+            return invalid(format!(
+                "vote of user {} is missing from the votes",
+                user_id
+            ));
+        }
+    }
+
+    let mut last_seq = 0;
+    for logged in &archived.events {
+        if logged.seq <= last_seq {
+            return invalid(format!("event {} is out of sequence", logged.seq));
+        }
+        last_seq = logged.seq;
+    }
+
+    Ok(())
+}
+
+// Fields holding the ID of the room, one of its users or a chat message,
+// wherever they appear in the room, its events or audit payloads
+const ID_FIELDS: &[&str] = &[
+    "id",
+    "roomId",
+    "userId",
+    "ownerId",
+    "actorId",
+    "previousOwnerId",
+    "newOwnerId",
+];
+
+// Maps keyed by user ID
+const USER_MAPS: &[&str] = &["users", "votes"];
+
+// Give the room, its users and chat messages fresh IDs. Each ID is replaced
+// consistently in the fields known to hold one, so names, chat text and other
+// strings that merely look like a UUID are left alone.
+fn remap(archived: ArchivedRoom) -> Result<ArchivedRoom, AppError> {
+    let mut value = serde_json::to_value(&archived)
+        .map_err(|e| AppError::BadRequest(ErrorCode::InvalidArchive, e.to_string()))?;
+    remap_value(&mut value, &mut HashMap::new());
+
+    serde_json::from_value(value)
+        .map_err(|e| AppError::BadRequest(ErrorCode::InvalidArchive, e.to_string()))
+}
+
+fn remap_value(value: &mut Value, ids: &mut HashMap<Uuid, Uuid>) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                remap_value(item, ids);
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                match item {
+                    Value::String(s) if ID_FIELDS.contains(&key.as_str()) => {
+                        if let Some(id) = remap_id(s, ids) {
+                            *s = id;
+                        }
+                    }
+                    Value::Object(entries) if USER_MAPS.contains(&key.as_str()) => {
+                        *entries = std::mem::take(entries)
+                            .into_iter()
+                            .map(|(user_id, mut entry)| {
+                                remap_value(&mut entry, ids);
+                                (remap_id(&user_id, ids).unwrap_or(user_id), entry)
+                            })
+                            .collect();
+                    }
+                    _ => remap_value(item, ids),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn remap_id(s: &str, ids: &mut HashMap<Uuid, Uuid>) -> Option<String> {
+    let id = Uuid::parse_str(s).ok()?;
+    Some(ids.entry(id).or_insert_with(Uuid::new_v4).to_string())
+}
@@ -0,0 +1,163 @@
+use crate::error::AppError;
+use std::time::Duration;
+
+// Longest archived room retention, 100 years, so the purge cutoff can always
+// be computed
+const MAX_ARCHIVED_ROOM_RETENTION_HOURS: u64 = 100 * 365 * 24;
+
+// Which event bus implementation fans out room events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventBusKind {
+    // In-process broadcast channels, for a single server instance
+    Memory,
+    // Events are also written to the shared database and polled by every instance
+    Database,
+}
+
+// How log lines are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    // Human-readable lines, for development
+    Text,
+    // One JSON object per line with the enclosing spans, for log collectors
+    Json,
+}
+
+// Runtime configuration, read from environment variables
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub port: u16,
+    pub database_url: String,
+    pub database_max_connections: u32,
+    pub event_bus: EventBusKind,
+    pub event_bus_poll_interval: Duration,
+    // How long archived rooms are kept before being purged
+    pub archived_room_retention: Duration,
+    // Bearer token for the admin endpoints, which are disabled without one
+    pub admin_token: Option<String>,
+    // How long requests are still accepted once shutdown starts, while
+    // readiness reports the instance as not ready
+    pub shutdown_readiness_delay: Duration,
+    // How long in-flight requests may take to finish once no more are accepted
+    pub shutdown_drain_period: Duration,
+    pub log_format: LogFormat,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, AppError> {
+        let port = env_or("PORT", "3000")
+            .parse()
+            .map_err(|e| AppError::ConfigError(format!("Invalid PORT: {}", e)))?;
+
+        let database_url = env_or("DATABASE_URL", "sqlite:pointing_poker.db");
+
+        let database_max_connections = match env_or("DATABASE_MAX_CONNECTIONS", "10").parse() {
+            Ok(0) => {
+                return Err(AppError::ConfigError(
+                    "Invalid DATABASE_MAX_CONNECTIONS: must be at least 1".to_string(),
+                ));
+            }
+            Ok(max) => max,
+            Err(e) => {
+                return Err(AppError::ConfigError(format!(
+                    "Invalid DATABASE_MAX_CONNECTIONS: {}",
+                    e
+                )));
+            }
+        };
+
+        let event_bus = match env_or("EVENT_BUS", "memory").to_lowercase().as_str() {
+            "memory" => EventBusKind::Memory,
+            "database" => EventBusKind::Database,
+            other => {
+                return Err(AppError::ConfigError(format!(
+                    "Invalid EVENT_BUS: {} (expected memory or database)",
+                    other
+                )));
+            }
+        };
+
+        let event_bus_poll_interval = match env_or("EVENT_BUS_POLL_INTERVAL_MS", "200").parse() {
+            Ok(0) => {
+                return Err(AppError::ConfigError(
+                    "Invalid EVENT_BUS_POLL_INTERVAL_MS: must be at least 1".to_string(),
+                ));
+            }
+            Ok(ms) => Duration::from_millis(ms),
+            Err(e) => {
+                return Err(AppError::ConfigError(format!(
+                    "Invalid EVENT_BUS_POLL_INTERVAL_MS: {}",
+                    e
+                )));
+            }
+        };
+
+        let archived_room_retention =
+            match env_or("ARCHIVED_ROOM_RETENTION_HOURS", "720").parse::<u64>() {
+                Ok(hours) if hours <= MAX_ARCHIVED_ROOM_RETENTION_HOURS => hours
+                    .checked_mul(60 * 60)
+                    .map(Duration::from_secs)
+                    .ok_or_else(|| {
+                        AppError::ConfigError("Invalid ARCHIVED_ROOM_RETENTION_HOURS".to_string())
+                    })?,
+                Ok(_) => {
+                    return Err(AppError::ConfigError(format!(
+                        "Invalid ARCHIVED_ROOM_RETENTION_HOURS: must be at most {}",
+                        MAX_ARCHIVED_ROOM_RETENTION_HOURS
+                    )));
+                }
+                Err(e) => {
+                    return Err(AppError::ConfigError(format!(
+                        "Invalid ARCHIVED_ROOM_RETENTION_HOURS: {}",
+                        e
+                    )));
+                }
+            };
+
+        let admin_token = std::env::var("ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+
+        let shutdown_readiness_delay = env_or("SHUTDOWN_READINESS_DELAY_SECS", "0")
+            .parse()
+            .map(Duration::from_secs)
+            .map_err(|e| {
+                AppError::ConfigError(format!("Invalid SHUTDOWN_READINESS_DELAY_SECS: {}", e))
+            })?;
+
+        let shutdown_drain_period = env_or("SHUTDOWN_DRAIN_PERIOD_SECS", "20")
+            .parse()
+            .map(Duration::from_secs)
+            .map_err(|e| {
+                AppError::ConfigError(format!("Invalid SHUTDOWN_DRAIN_PERIOD_SECS: {}", e))
+            })?;
+
+        let log_format = match env_or("LOG_FORMAT", "text").to_lowercase().as_str() {
+            "text" => LogFormat::Text,
+            "json" => LogFormat::Json,
+            other => {
+                return Err(AppError::ConfigError(format!(
+                    "Invalid LOG_FORMAT: {} (expected text or json)",
+                    other
+                )));
+            }
+        };
+
+        Ok(Self {
+            port,
+            database_url,
+            database_max_connections,
+            event_bus,
+            event_bus_poll_interval,
+            archived_room_retention,
+            admin_token,
+            shutdown_readiness_delay,
+            shutdown_drain_period,
+            log_format,
+        })
+    }
+}
+
+fn env_or(key: &str, default: &str) -> String {
+    std::env::var(key).unwrap_or_else(|_| default.to_string())
+}
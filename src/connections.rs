@@ -0,0 +1,114 @@
+use crate::models::room::{RoomId, SessionMode};
+use crate::models::user::UserId;
+use dashmap::DashMap;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+// Close code sent to a connection replaced by a newer one in single-session rooms
+pub const CLOSE_SUPERSEDED: u16 = 4001;
+
+// Standard close code for connections closed because the server is restarting
+pub const CLOSE_SERVICE_RESTART: u16 = 1012;
+
+// Why the server is closing a connection
+#[derive(Debug, Clone)]
+pub struct CloseReason {
+    pub code: u16,
+    pub reason: &'static str,
+}
+
+struct Connection {
+    id: Uuid,
+    close: oneshot::Sender<CloseReason>,
+}
+
+// A newly registered WebSocket connection
+pub struct Registration {
+    pub connection_id: Uuid,
+    // Fires when the server wants this connection closed
+    pub close_rx: oneshot::Receiver<CloseReason>,
+    // True if this is the user's only connection, i.e. they just came online
+    pub came_online: bool,
+}
+
+// Open WebSocket connections of every user, per room
+#[derive(Default)]
+pub struct ConnectionRegistry {
+    connections: DashMap<(RoomId, UserId), Vec<Connection>>,
+}
+
+impl ConnectionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Track a new connection, superseding the user's older ones in single-session rooms
+    pub fn register(&self, room_id: &RoomId, user_id: &UserId, mode: SessionMode) -> Registration {
+        let (close, close_rx) = oneshot::channel();
+        let connection_id = Uuid::new_v4();
+
+        let mut connections = self
+            .connections
+            .entry((room_id.clone(), user_id.clone()))
+            .or_default();
+
+        let came_online = connections.is_empty();
+
+        if mode == SessionMode::SingleSession {
+            for older in connections.drain(..) {
+                let _ = older.close.send(CloseReason {
+                    code: CLOSE_SUPERSEDED,
+                    reason: "Superseded by a newer connection",
+                });
+            }
+        }
+
+        connections.push(Connection {
+            id: connection_id,
+            close,
+        });
+
+        Registration {
+            connection_id,
+            close_rx,
+            came_online,
+        }
+    }
+
+    // Stop tracking a connection, returning true if it was the user's last one
+    pub fn unregister(&self, room_id: &RoomId, user_id: &UserId, connection_id: &Uuid) -> bool {
+        let key = (room_id.clone(), user_id.clone());
+
+        let Some(mut connections) = self.connections.get_mut(&key) else {
+            // Already superseded and removed
+            return false;
+        };
+
+        let before = connections.len();
+        connections.retain(|connection| connection.id != *connection_id);
+        let went_offline = before > 0 && connections.is_empty();
+        drop(connections);
+
+        self.connections
+            .remove_if(&key, |_, connections| connections.is_empty());
+
+        went_offline
+    }
+
+    // Number of open connections across all rooms
+    pub fn count(&self) -> usize {
+        self.connections
+            .iter()
+            .map(|connections| connections.len())
+            .sum()
+    }
+
+    // Users in the room with at least one open connection
+    pub fn online_users(&self, room_id: &RoomId) -> Vec<UserId> {
+        self.connections
+            .iter()
+            .filter(|entry| entry.key().0 == *room_id && !entry.value().is_empty())
+            .map(|entry| entry.key().1.clone())
+            .collect()
+    }
+}
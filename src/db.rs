@@ -1,487 +1,369 @@
-use crate::error::AppError;
-use crate::models::room::{Room, RoomId, RoomState};
+pub mod cache;
+pub mod memory;
+pub mod metrics;
+pub mod postgres;
+pub mod sqlite;
+
+use crate::error::{AppError, ErrorCode};
+use crate::events::{RoomEvent, SequencedEvent};
+use crate::models::archive::{ArchivedRoom, ArchivedVote};
+use crate::models::audit::{AuditAction, AuditEvent, AuditFilter};
+use crate::models::chat::ChatMessage;
+use crate::models::room::{Room, RoomId, RoomSettings, RoomState};
 use crate::models::user::{User, UserId};
 use crate::models::vote::Vote;
-#[allow(unused_imports)]
-use sqlx::{Pool, Row, Sqlite, migrate::MigrateDatabase as _, sqlite::SqlitePool};
-use std::collections::HashMap;
-use std::str::FromStr;
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
-pub struct Database {
-    pool: Pool<Sqlite>,
-}
-
-impl Database {
-    pub async fn new() -> Result<Self, AppError> {
-        use sqlx::migrate::MigrateDatabase;
+pub use cache::{CachedDatabase, RoomCache};
+pub use memory::MemoryDatabase;
+pub use metrics::MeteredDatabase;
+pub use postgres::PostgresDatabase;
+pub use sqlite::SqliteDatabase;
 
-        // Database URL for SQLx 0.8
-        let db_url = "sqlite:pointing_poker.db";
+// DATABASE_URL selecting the in-memory backend
+pub const MEMORY_DATABASE_URL: &str = "memory";
 
-        // Check if database exists, create it if not
-        if !Sqlite::database_exists(db_url).await.map_err(|e| {
-            AppError::DatabaseError(format!("Failed to check if database exists: {}", e))
-        })? {
-            // Database doesn't exist, create it
-            Sqlite::create_database(db_url).await.map_err(|e| {
-                AppError::DatabaseError(format!("Failed to create database: {}", e))
-            })?;
-
-            println!("Database created at {}", db_url);
-        }
+// Storage for rooms, users, votes and chat history
+#[async_trait]
+pub trait Database: Send + Sync {
+    // Room operations
+    async fn get_room(&self, room_id: &RoomId) -> Result<Option<Room>, AppError>;
 
-        // Create connection pool
-        let pool = SqlitePool::connect(db_url).await.map_err(|e| {
-            AppError::DatabaseError(format!("Failed to connect to database: {}", e))
-        })?;
+    async fn list_room_ids(&self) -> Result<Vec<RoomId>, AppError>;
 
-        // Create schema
-        Self::create_schema(&pool).await?;
+    // User operations
+    async fn user_in_room(&self, room_id: &RoomId, user_id: &UserId) -> Result<bool, AppError>;
 
-        Ok(Self { pool })
-    }
+    // Vote operations
+    // Votes of every round of the room, oldest round first
+    async fn get_votes(&self, room_id: &RoomId) -> Result<Vec<ArchivedVote>, AppError>;
 
-    async fn create_schema(pool: &Pool<Sqlite>) -> Result<(), AppError> {
-        // Enable foreign keys
-        sqlx::query("PRAGMA foreign_keys = ON")
-            .execute(pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        // Create rooms table
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS rooms (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                state TEXT NOT NULL,
-                owner_id TEXT
-            )
-            "#,
-        )
-        .execute(pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        // Create users table
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS users (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                is_observer INTEGER NOT NULL,
-                room_id TEXT NOT NULL,
-                FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE CASCADE
-            )
-            "#,
-        )
-        .execute(pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        // Create votes table
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS votes (
-                user_id TEXT PRIMARY KEY,
-                room_id TEXT NOT NULL,
-                vote TEXT NOT NULL,
-                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
-                FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE CASCADE
-            )
-            "#,
-        )
-        .execute(pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        Ok(())
-    }
+    // Start a transaction for composing several operations atomically
+    async fn begin(&self) -> Result<Box<dyn Transaction>, AppError>;
 
-    // Room operations
-    pub async fn create_room(&self, room: &Room) -> Result<(), AppError> {
-        let room_id = room.id.to_string();
-        let state_str = match room.state {
-            RoomState::Voting => "voting",
-            RoomState::Revealed => "revealed",
-        };
-        let owner_id = room.owner_id.as_ref().map(|id| id.to_string());
-
-        sqlx::query(
-            r#"
-            INSERT INTO rooms (id, name, state, owner_id)
-            VALUES (?, ?, ?, ?)
-            "#,
-        )
-        .bind(room_id)
-        .bind(&room.name)
-        .bind(state_str)
-        .bind(owner_id)
-        .execute(&self.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        // Add initial users if any
-        for user in room.users.values() {
-            self.add_user(user, &room.id).await?;
-        }
-
-        Ok(())
-    }
+    // Chat operations
+    // Chat history of the room, oldest first
+    async fn get_chat_messages(&self, room_id: &RoomId) -> Result<Vec<ChatMessage>, AppError>;
 
-    pub async fn get_room(&self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
-        let room_id_str = room_id.to_string();
-
-        // Get room data
-        let room_data = sqlx::query("SELECT name, state, owner_id FROM rooms WHERE id = ?")
-            .bind(&room_id_str)
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let Some(row) = room_data else {
-            return Ok(None);
-        };
-
-        // Extract room data - in SQLx 0.8, get() method remains the same
-        let name: String = row.get("name");
-        let state_str: String = row.get("state");
-        let owner_id_str: Option<String> = row.get("owner_id");
-
-        // Get users for this room
-        let users = self.get_users_for_room(room_id).await?;
-
-        // Get votes for this room
-        let votes = self.get_votes_for_room(room_id).await?;
-
-        // Convert to Room model
-        let state = match state_str.as_str() {
-            "voting" => RoomState::Voting,
-            "revealed" => RoomState::Revealed,
-            _ => return Err(AppError::DatabaseError("Invalid room state".to_string())),
-        };
-
-        let owner_id = if let Some(id_str) = owner_id_str {
-            Some(UserId(Uuid::from_str(&id_str).map_err(|e| {
-                AppError::DatabaseError(format!("Invalid UUID: {}", e))
-            })?))
-        } else {
-            None
-        };
-
-        Ok(Some(Room {
-            id: room_id.clone(),
-            name,
-            state,
-            users,
-            votes,
-            owner_id,
-        }))
-    }
+    // Audit operations
+    // Audit events of the room matching the filter, newest first
+    async fn get_audit_events(
+        &self,
+        room_id: &RoomId,
+        filter: &AuditFilter,
+    ) -> Result<Vec<AuditEvent>, AppError>;
 
-    pub async fn get_users_for_room(
+    // Event log operations
+    // Logged events of the room after the given sequence number, oldest first
+    async fn get_room_events(
         &self,
         room_id: &RoomId,
-    ) -> Result<HashMap<UserId, User>, AppError> {
-        let room_id_str = room_id.to_string();
-
-        // Get users
-        let rows = sqlx::query("SELECT id, name, is_observer FROM users WHERE room_id = ?")
-            .bind(&room_id_str)
-            .fetch_all(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        // Convert to HashMap<UserId, User>
-        let mut user_map = HashMap::new();
-        for row in rows {
-            let id_str: String = row.get("id");
-            let name: String = row.get("name");
-            let is_observer: i64 = row.get("is_observer");
-
-            let user_id = UserId(
-                Uuid::from_str(&id_str)
-                    .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
-            );
-
-            let user = User {
-                id: user_id.clone(),
-                name,
-                is_observer: is_observer != 0,
-            };
-
-            user_map.insert(user_id, user);
-        }
-
-        Ok(user_map)
-    }
+        after_seq: i64,
+    ) -> Result<Vec<LoggedEvent>, AppError>;
 
-    pub async fn get_votes_for_room(
+    // The stored room, bypassing any cache, and its whole event log, read from
+    // one snapshot without blocking writers
+    async fn get_room_with_events(
         &self,
         room_id: &RoomId,
-    ) -> Result<HashMap<UserId, Vote>, AppError> {
-        let room_id_str = room_id.to_string();
+    ) -> Result<(Option<Room>, Vec<LoggedEvent>), AppError>;
 
-        // Get votes
-        let rows = sqlx::query("SELECT user_id, vote FROM votes WHERE room_id = ?")
-            .bind(&room_id_str)
-            .fetch_all(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    // Delete rooms archived before the cutoff with everything in them,
+    // returning their IDs
+    async fn purge_archived_rooms(
+        &self,
+        archived_before: OffsetDateTime,
+    ) -> Result<Vec<RoomId>, AppError>;
+
+    // Write a consistent copy of the whole database to a new file
+    async fn backup(&self, _path: &str) -> Result<(), AppError> {
+        Err(AppError::BadRequest(
+            ErrorCode::BackupNotSupported,
+            "Backups are only supported with SQLite storage".to_string(),
+        ))
+    }
 
-        // Convert to HashMap<UserId, Vote>
-        let mut vote_map = HashMap::new();
-        for row in rows {
-            let user_id_str: String = row.get("user_id");
-            let vote_str: String = row.get("vote");
+    // Number of schema migrations applied, read from the database so it also
+    // shows the storage is usable. None for storage without migrations.
+    async fn schema_version(&self) -> Result<Option<i64>, AppError> {
+        Ok(None)
+    }
 
-            let user_id = UserId(
-                Uuid::from_str(&user_id_str)
-                    .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
-            );
+    // Wait for pooled connections to be released and close them, on shutdown
+    async fn close(&self) {}
 
-            let vote = Vote::from_string(&vote_str).map_err(AppError::DatabaseError)?;
+    // The event outbox of this storage, if it can be shared between server instances
+    fn as_outbox(self: Arc<Self>) -> Option<Arc<dyn EventOutbox>> {
+        None
+    }
+}
 
-            vote_map.insert(user_id, vote);
-        }
+// Changes to rooms and everything in them, including their logs, applied all
+// together on commit. Dropping the transaction without committing rolls
+// everything back. A room read through a transaction stays locked until it
+// ends, so decisions based on it can't race with other requests.
+#[async_trait]
+pub trait Transaction: Send {
+    // Room operations
+    async fn create_room(&mut self, room: &Room) -> Result<(), AppError>;
 
-        Ok(vote_map)
-    }
+    async fn get_room(&mut self, room_id: &RoomId) -> Result<Option<Room>, AppError>;
 
-    pub async fn update_room_state(
-        &self,
+    async fn update_room_state(
+        &mut self,
         room_id: &RoomId,
         state: &RoomState,
-    ) -> Result<(), AppError> {
-        let room_id_str = room_id.to_string();
-        let state_str = match state {
-            RoomState::Voting => "voting",
-            RoomState::Revealed => "revealed",
-        };
-
-        sqlx::query("UPDATE rooms SET state = ? WHERE id = ?")
-            .bind(state_str)
-            .bind(&room_id_str)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        Ok(())
-    }
+    ) -> Result<(), AppError>;
 
-    pub async fn delete_room(&self, room_id: &RoomId) -> Result<bool, AppError> {
-        let room_id_str = room_id.to_string();
+    async fn update_room_settings(
+        &mut self,
+        room_id: &RoomId,
+        settings: &RoomSettings,
+    ) -> Result<(), AppError>;
 
-        let result = sqlx::query("DELETE FROM rooms WHERE id = ?")
-            .bind(&room_id_str)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    // Archive the room at the given time, or reopen it with None
+    async fn update_room_archived_at(
+        &mut self,
+        room_id: &RoomId,
+        archived_at: Option<OffsetDateTime>,
+    ) -> Result<(), AppError>;
 
-        Ok(result.rows_affected() > 0)
-    }
+    async fn update_room_owner(
+        &mut self,
+        room_id: &RoomId,
+        owner_id: Option<&UserId>,
+    ) -> Result<(), AppError>;
 
     // User operations
-    pub async fn add_user(&self, user: &User, room_id: &RoomId) -> Result<(), AppError> {
-        let user_id = user.id.to_string();
-        let room_id_str = room_id.to_string();
-        let is_observer = user.is_observer as i64;
-
-        sqlx::query(
-            r#"
-            INSERT INTO users (id, name, is_observer, room_id)
-            VALUES (?, ?, ?, ?)
-            "#,
-        )
-        .bind(&user_id)
-        .bind(&user.name)
-        .bind(is_observer)
-        .bind(&room_id_str)
-        .execute(&self.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        Ok(())
-    }
+    async fn add_user(&mut self, user: &User, room_id: &RoomId) -> Result<(), AppError>;
 
-    pub async fn remove_user(&self, user_id: &UserId) -> Result<Option<(User, RoomId)>, AppError> {
-        let user_id_str = user_id.to_string();
-
-        // First get user data
-        let row = sqlx::query("SELECT name, is_observer, room_id FROM users WHERE id = ?")
-            .bind(&user_id_str)
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let Some(row) = row else {
-            return Ok(None);
-        };
-
-        let name: String = row.get("name");
-        let is_observer: i64 = row.get("is_observer");
-        let room_id_str: String = row.get("room_id");
-
-        // Now delete the user
-        sqlx::query("DELETE FROM users WHERE id = ?")
-            .bind(&user_id_str)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        // Remove their vote too
-        self.remove_vote(user_id).await?;
-
-        // Create User and RoomId objects
-        let room_id = RoomId::from_string(&room_id_str)
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let user = User {
-            id: user_id.clone(),
-            name,
-            is_observer: is_observer != 0,
-        };
-
-        Ok(Some((user, room_id)))
-    }
+    async fn remove_user(&mut self, user_id: &UserId) -> Result<Option<(User, RoomId)>, AppError>;
+
+    // Vote operations
+    async fn add_vote(
+        &mut self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        vote: &Vote,
+    ) -> Result<(), AppError>;
+
+    async fn reset_votes_for_room(&mut self, room_id: &RoomId) -> Result<(), AppError>;
+
+    // Chat operations
+    // Store a message, keeping only the most recent ones per room
+    async fn add_chat_message(
+        &mut self,
+        room_id: &RoomId,
+        message: &ChatMessage,
+    ) -> Result<(), AppError>;
+
+    // Audit operations
+    async fn add_audit_event(
+        &mut self,
+        room_id: &RoomId,
+        actor_id: Option<&UserId>,
+        action: AuditAction,
+        payload: &Value,
+    ) -> Result<(), AppError>;
+
+    // Event log operations
+    // Append a serialized event to the room's log, returning its sequence number.
+    // `vote` is the value behind a VoteSubmitted event.
+    async fn append_room_event(
+        &mut self,
+        room_id: &RoomId,
+        event: &str,
+        vote: Option<&Vote>,
+    ) -> Result<i64, AppError>;
 
-    pub async fn count_users_in_room(&self, room_id: &RoomId) -> Result<i64, AppError> {
-        let room_id_str = room_id.to_string();
+    // Archive operations
+    // Whether the room, any of its users or chat messages already exist
+    async fn ids_taken(&mut self, archived: &ArchivedRoom) -> Result<bool, AppError>;
 
-        let row = sqlx::query("SELECT COUNT(*) as count FROM users WHERE room_id = ?")
-            .bind(&room_id_str)
-            .fetch_one(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    // Store an archived room with everything in it, keeping its IDs,
+    // timestamps and event sequence numbers
+    async fn restore_room(&mut self, archived: &ArchivedRoom) -> Result<(), AppError>;
 
-        let count: i64 = row.get("count");
+    async fn commit(self: Box<Self>) -> Result<(), AppError>;
+}
 
-        Ok(count)
+// Archived rooms are read-only until their owner reopens them
+pub fn check_open(room: &Room) -> Result<(), AppError> {
+    if room.is_archived() {
+        return Err(AppError::Conflict(
+            ErrorCode::RoomArchived,
+            "Room is archived".to_string(),
+        ));
     }
 
-    pub async fn update_room_owner(
-        &self,
-        room_id: &RoomId,
-        owner_id: Option<&UserId>,
-    ) -> Result<(), AppError> {
-        let room_id_str = room_id.to_string();
-        let owner_id_str = owner_id.map(|id| id.to_string());
-
-        sqlx::query("UPDATE rooms SET owner_id = ? WHERE id = ?")
-            .bind(owner_id_str)
-            .bind(&room_id_str)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        Ok(())
+    Ok(())
+}
+
+// Reveal the votes of a room, for its owner. Returns the room as it was
+// before.
+pub async fn reveal_votes(
+    tx: &mut dyn Transaction,
+    room_id: &RoomId,
+    user_id: &UserId,
+) -> Result<Room, AppError> {
+    // Get the room first to check if the user is the owner, in the
+    // transaction so ownership can't change before the update
+    let room = tx
+        .get_room(room_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?;
+
+    // Check if the user is the room owner
+    if room.owner_id.as_ref() != Some(user_id) {
+        return Err(AppError::Forbidden(
+            ErrorCode::NotRoomOwner,
+            "Only the room owner can reveal votes".to_string(),
+        ));
     }
+    check_open(&room)?;
+
+    // Update the room state to revealed
+    tx.update_room_state(room_id, &RoomState::Revealed).await?;
+    tx.add_audit_event(
+        room_id,
+        Some(user_id),
+        AuditAction::VotesRevealed,
+        &json!({ "voteCount": room.votes.len() }),
+    )
+    .await?;
+
+    Ok(room)
+}
 
-    // Vote operations
-    pub async fn add_vote(
-        &self,
-        room_id: &RoomId,
-        user_id: &UserId,
-        vote: &Vote,
-    ) -> Result<(), AppError> {
-        // Fetch the room first to use its model functionality
-        let _room = self
-            .get_room(room_id)
-            .await?
-            .ok_or_else(|| AppError::NotFound("Room not found".to_string()))?;
-
-        // Now save the vote to the database
-        let room_id_str = room_id.to_string();
-        let user_id_str = user_id.to_string();
-        let vote_val = vote
-            .value()
-            .ok_or_else(|| AppError::DatabaseError("Invalid vote value".to_string()))?;
-
-        sqlx::query(
-            r#"
-            INSERT INTO votes (user_id, room_id, vote)
-            VALUES (?, ?, ?)
-            ON CONFLICT(user_id) DO UPDATE SET vote = excluded.vote
-            "#,
-        )
-        .bind(&user_id_str)
-        .bind(&room_id_str)
-        .bind(&vote_val)
-        .execute(&self.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        Ok(())
+// Discard the votes of a room and start a new round, for its owner. Returns
+// the room as it was before.
+pub async fn reset_votes(
+    tx: &mut dyn Transaction,
+    room_id: &RoomId,
+    user_id: &UserId,
+) -> Result<Room, AppError> {
+    // Get the room first to check if the user is the owner, in the
+    // transaction so ownership can't change before the update
+    let room = tx
+        .get_room(room_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?;
+
+    // Check if the user is the room owner
+    if room.owner_id.as_ref() != Some(user_id) {
+        return Err(AppError::Forbidden(
+            ErrorCode::NotRoomOwner,
+            "Only the room owner can reset votes".to_string(),
+        ));
     }
+    check_open(&room)?;
+
+    // Reset votes and state
+    tx.reset_votes_for_room(room_id).await?;
+    tx.add_audit_event(
+        room_id,
+        Some(user_id),
+        AuditAction::VotesReset,
+        &json!({
+            "previousState": room.state,
+            "discardedVoteCount": room.votes.len(),
+        }),
+    )
+    .await?;
+
+    Ok(room)
+}
+
+// Append an event to the room's log in the transaction making the change it
+// describes, so the log can't miss a change or record one that was rolled
+// back. Returns the event numbered for publishing once committed.
+pub async fn log_event(
+    tx: &mut dyn Transaction,
+    room_id: &RoomId,
+    event: RoomEvent,
+    vote: Option<&Vote>,
+) -> Result<SequencedEvent, AppError> {
+    let serialized_event =
+        serde_json::to_string(&event).map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    let seq = tx
+        .append_room_event(room_id, &serialized_event, vote)
+        .await?;
+
+    Ok(SequencedEvent {
+        seq: Some(seq),
+        event,
+    })
+}
 
-    pub async fn remove_vote(&self, user_id: &UserId) -> Result<(), AppError> {
-        let user_id_str = user_id.to_string();
+// An event from a room's event log, as it was sent to clients
+#[derive(Debug, Clone)]
+pub struct LoggedEvent {
+    pub seq: i64,
+    pub event: String,
+    // Value behind a VoteSubmitted event, kept for rebuilding the room but
+    // never sent to clients
+    pub vote: Option<Vote>,
+    pub created_at: OffsetDateTime,
+}
 
-        sqlx::query("DELETE FROM votes WHERE user_id = ?")
-            .bind(&user_id_str)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+// A room event written by one server instance for the others to pick up
+pub struct OutboxEvent {
+    pub id: i64,
+    pub node_id: Uuid,
+    pub room_id: RoomId,
+    pub event: String,
+}
 
-        Ok(())
-    }
+// Shared table of published room events, polled by the database event bus
+#[async_trait]
+pub trait EventOutbox: Send + Sync {
+    async fn add_outbox_event(
+        &self,
+        node_id: &Uuid,
+        room_id: &RoomId,
+        event: &str,
+    ) -> Result<(), AppError>;
 
-    pub async fn reset_votes_for_room(&self, room_id: &RoomId) -> Result<(), AppError> {
-        let room_id_str = room_id.to_string();
+    async fn latest_outbox_event_id(&self) -> Result<i64, AppError>;
 
-        sqlx::query("DELETE FROM votes WHERE room_id = ?")
-            .bind(&room_id_str)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    async fn get_outbox_events_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<OutboxEvent>, AppError>;
 
-        // Also reset room state to voting
-        self.update_room_state(room_id, &RoomState::Voting).await?;
+    async fn prune_outbox_events(&self, older_than: OffsetDateTime) -> Result<(), AppError>;
+}
 
-        Ok(())
+// Open the storage backend named by DATABASE_URL, with at most
+// `max_connections` pooled connections for the SQL backends
+pub async fn connect(
+    database_url: &str,
+    max_connections: u32,
+) -> Result<Arc<dyn Database>, AppError> {
+    if database_url == MEMORY_DATABASE_URL {
+        tracing::info!("Using in-memory storage, data is lost on restart");
+        return Ok(Arc::new(MemoryDatabase::new()));
     }
 
-    // Method to reveal votes in a room (changes room state to revealed)
-    pub async fn reveal_votes(&self, room_id: &RoomId, user_id: &UserId) -> Result<(), AppError> {
-        // Get the room first to check if the user is the owner
-        let room = self
-            .get_room(room_id)
-            .await?
-            .ok_or_else(|| AppError::NotFound("Room not found".to_string()))?;
-
-        // Check if the user is the room owner
-        if room.owner_id.as_ref() != Some(user_id) {
-            return Err(AppError::Forbidden(
-                "Only the room owner can reveal votes".to_string(),
-            ));
-        }
-
-        // Update the room state to revealed
-        self.update_room_state(room_id, &RoomState::Revealed)
-            .await?;
-
-        Ok(())
+    if database_url.starts_with("sqlite:") {
+        return Ok(Arc::new(
+            SqliteDatabase::new(database_url, max_connections).await?,
+        ));
     }
 
-    // Method to reset votes in a room
-    pub async fn reset_votes(&self, room_id: &RoomId, user_id: &UserId) -> Result<(), AppError> {
-        // Get the room first to check if the user is the owner
-        let room = self
-            .get_room(room_id)
-            .await?
-            .ok_or_else(|| AppError::NotFound("Room not found".to_string()))?;
-
-        // Check if the user is the room owner
-        if room.owner_id.as_ref() != Some(user_id) {
-            return Err(AppError::Forbidden(
-                "Only the room owner can reset votes".to_string(),
-            ));
-        }
-
-        // Reset votes and state
-        self.reset_votes_for_room(room_id).await?;
-
-        Ok(())
+    if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        return Ok(Arc::new(
+            PostgresDatabase::new(database_url, max_connections).await?,
+        ));
     }
+
+    Err(AppError::ConfigError(format!(
+        "Unsupported DATABASE_URL: {} (expected sqlite:<path>, postgres://... or memory)",
+        database_url
+    )))
 }
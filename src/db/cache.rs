@@ -0,0 +1,418 @@
+use crate::db::{Database, EventOutbox, LoggedEvent, Transaction};
+use crate::error::AppError;
+use crate::models::archive::{ArchivedRoom, ArchivedVote};
+use crate::models::audit::{AuditAction, AuditEvent, AuditFilter};
+use crate::models::chat::ChatMessage;
+use crate::models::room::{Room, RoomId, RoomSettings, RoomState};
+use crate::models::user::{User, UserId};
+use crate::models::vote::Vote;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde_json::Value;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+// Most rooms kept in the cache; the least recently used one makes way for a
+// newly loaded room
+const ROOM_CACHE_CAPACITY: usize = 1000;
+
+// How long a loaded room is served from the cache. This also bounds how stale
+// a room can get if a change made by another instance goes unreported.
+const ROOM_CACHE_TTL: Duration = Duration::from_secs(600);
+
+struct CachedRoom {
+    room: Room,
+    loaded_at: Instant,
+    last_used: Instant,
+}
+
+// Recently loaded rooms, kept until something changes them, they expire or
+// the cache is full
+pub struct RoomCache {
+    rooms: DashMap<RoomId, CachedRoom>,
+    capacity: usize,
+    ttl: Duration,
+    // Bumped on every change, so a room loaded before a change is never cached
+    generation: AtomicU64,
+}
+
+impl Default for RoomCache {
+    fn default() -> Self {
+        Self::with_limits(ROOM_CACHE_CAPACITY, ROOM_CACHE_TTL)
+    }
+}
+
+impl RoomCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_limits(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            rooms: DashMap::new(),
+            capacity,
+            ttl,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self, room_id: &RoomId) -> Option<Room> {
+        let now = Instant::now();
+        let mut cached = self.rooms.get_mut(room_id)?;
+        if now.duration_since(cached.loaded_at) >= self.ttl {
+            drop(cached);
+            self.rooms.remove_if(room_id, |_, cached| {
+                now.duration_since(cached.loaded_at) >= self.ttl
+            });
+            return None;
+        }
+
+        cached.last_used = now;
+        Some(cached.room.clone())
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    // Cache a room loaded at the given generation, unless it changed meanwhile
+    fn insert_if_unchanged(&self, room: &Room, generation: u64) {
+        let now = Instant::now();
+        if self.rooms.len() >= self.capacity && !self.rooms.contains_key(&room.id) {
+            self.evict(now);
+        }
+
+        // Holding the entry keeps a concurrent invalidation from removing the
+        // room before it has been inserted
+        let entry = self.rooms.entry(room.id.clone());
+        if self.generation() == generation {
+            entry.insert(CachedRoom {
+                room: room.clone(),
+                loaded_at: now,
+                last_used: now,
+            });
+        }
+    }
+
+    // Make space for one more room: drop the expired ones, or failing that the
+    // least recently used one
+    fn evict(&self, now: Instant) {
+        self.rooms
+            .retain(|_, cached| now.duration_since(cached.loaded_at) < self.ttl);
+        if self.rooms.len() < self.capacity {
+            return;
+        }
+
+        let least_recently_used = self
+            .rooms
+            .iter()
+            .min_by_key(|cached| cached.last_used)
+            .map(|cached| cached.key().clone());
+        if let Some(room_id) = least_recently_used {
+            self.rooms.remove(&room_id);
+        }
+    }
+
+    // Forget a room that has changed, e.g. through another server instance
+    pub fn invalidate(&self, room_id: &RoomId) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.rooms.remove(room_id);
+    }
+}
+
+// Write-through room cache in front of another storage backend. Rooms are
+// served from memory after the first load; changes made through this instance
+// drop the cached copy, and changes made by other instances must be
+// reported through `RoomCache::invalidate`.
+pub struct CachedDatabase {
+    inner: Arc<dyn Database>,
+    cache: Arc<RoomCache>,
+}
+
+impl CachedDatabase {
+    pub fn new(inner: Arc<dyn Database>, cache: Arc<RoomCache>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait]
+impl Database for CachedDatabase {
+    // Room operations
+    async fn get_room(&self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+        if let Some(room) = self.cache.get(room_id) {
+            return Ok(Some(room));
+        }
+
+        let generation = self.cache.generation();
+        let room = self.inner.get_room(room_id).await?;
+        if let Some(room) = &room {
+            self.cache.insert_if_unchanged(room, generation);
+        }
+
+        Ok(room)
+    }
+
+    async fn list_room_ids(&self) -> Result<Vec<RoomId>, AppError> {
+        self.inner.list_room_ids().await
+    }
+
+    // User operations
+    async fn user_in_room(&self, room_id: &RoomId, user_id: &UserId) -> Result<bool, AppError> {
+        match self.cache.get(room_id) {
+            Some(room) => Ok(room.users.contains_key(user_id)),
+            None => self.inner.user_in_room(room_id, user_id).await,
+        }
+    }
+
+    // Vote operations
+    async fn get_votes(&self, room_id: &RoomId) -> Result<Vec<ArchivedVote>, AppError> {
+        self.inner.get_votes(room_id).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>, AppError> {
+        Ok(Box::new(CachedTransaction {
+            inner: self.inner.begin().await?,
+            cache: self.cache.clone(),
+            changed: Vec::new(),
+        }))
+    }
+
+    // Chat operations
+    async fn get_chat_messages(&self, room_id: &RoomId) -> Result<Vec<ChatMessage>, AppError> {
+        self.inner.get_chat_messages(room_id).await
+    }
+
+    // Audit operations
+    async fn get_audit_events(
+        &self,
+        room_id: &RoomId,
+        filter: &AuditFilter,
+    ) -> Result<Vec<AuditEvent>, AppError> {
+        self.inner.get_audit_events(room_id, filter).await
+    }
+
+    // Event log operations
+    async fn get_room_events(
+        &self,
+        room_id: &RoomId,
+        after_seq: i64,
+    ) -> Result<Vec<LoggedEvent>, AppError> {
+        self.inner.get_room_events(room_id, after_seq).await
+    }
+
+    async fn get_room_with_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<(Option<Room>, Vec<LoggedEvent>), AppError> {
+        self.inner.get_room_with_events(room_id).await
+    }
+
+    async fn purge_archived_rooms(
+        &self,
+        archived_before: OffsetDateTime,
+    ) -> Result<Vec<RoomId>, AppError> {
+        let room_ids = self.inner.purge_archived_rooms(archived_before).await?;
+        for room_id in &room_ids {
+            self.cache.invalidate(room_id);
+        }
+
+        Ok(room_ids)
+    }
+
+    async fn backup(&self, path: &str) -> Result<(), AppError> {
+        self.inner.backup(path).await
+    }
+
+    async fn schema_version(&self) -> Result<Option<i64>, AppError> {
+        self.inner.schema_version().await
+    }
+
+    async fn close(&self) {
+        self.inner.close().await
+    }
+
+    fn as_outbox(self: Arc<Self>) -> Option<Arc<dyn EventOutbox>> {
+        self.inner.clone().as_outbox()
+    }
+}
+
+// Transaction that drops every room it changed from the cache once committed
+pub struct CachedTransaction {
+    inner: Box<dyn Transaction>,
+    cache: Arc<RoomCache>,
+    changed: Vec<RoomId>,
+}
+
+#[async_trait]
+impl Transaction for CachedTransaction {
+    // Room operations
+    async fn create_room(&mut self, room: &Room) -> Result<(), AppError> {
+        self.changed.push(room.id.clone());
+        self.inner.create_room(room).await
+    }
+
+    // Always read from the database, which is what locks the room
+    async fn get_room(&mut self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+        self.inner.get_room(room_id).await
+    }
+
+    async fn update_room_state(
+        &mut self,
+        room_id: &RoomId,
+        state: &RoomState,
+    ) -> Result<(), AppError> {
+        self.changed.push(room_id.clone());
+        self.inner.update_room_state(room_id, state).await
+    }
+
+    async fn update_room_settings(
+        &mut self,
+        room_id: &RoomId,
+        settings: &RoomSettings,
+    ) -> Result<(), AppError> {
+        self.changed.push(room_id.clone());
+        self.inner.update_room_settings(room_id, settings).await
+    }
+
+    async fn update_room_archived_at(
+        &mut self,
+        room_id: &RoomId,
+        archived_at: Option<OffsetDateTime>,
+    ) -> Result<(), AppError> {
+        self.changed.push(room_id.clone());
+        self.inner
+            .update_room_archived_at(room_id, archived_at)
+            .await
+    }
+
+    async fn update_room_owner(
+        &mut self,
+        room_id: &RoomId,
+        owner_id: Option<&UserId>,
+    ) -> Result<(), AppError> {
+        self.changed.push(room_id.clone());
+        self.inner.update_room_owner(room_id, owner_id).await
+    }
+
+    // User operations
+    async fn add_user(&mut self, user: &User, room_id: &RoomId) -> Result<(), AppError> {
+        self.changed.push(room_id.clone());
+        self.inner.add_user(user, room_id).await
+    }
+
+    async fn remove_user(&mut self, user_id: &UserId) -> Result<Option<(User, RoomId)>, AppError> {
+        let removed = self.inner.remove_user(user_id).await?;
+        if let Some((_, room_id)) = &removed {
+            self.changed.push(room_id.clone());
+        }
+
+        Ok(removed)
+    }
+
+    // Vote operations
+    async fn add_vote(
+        &mut self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        vote: &Vote,
+    ) -> Result<(), AppError> {
+        self.changed.push(room_id.clone());
+        self.inner.add_vote(room_id, user_id, vote).await
+    }
+
+    async fn reset_votes_for_room(&mut self, room_id: &RoomId) -> Result<(), AppError> {
+        self.changed.push(room_id.clone());
+        self.inner.reset_votes_for_room(room_id).await
+    }
+
+    // Chat operations
+    async fn add_chat_message(
+        &mut self,
+        room_id: &RoomId,
+        message: &ChatMessage,
+    ) -> Result<(), AppError> {
+        self.inner.add_chat_message(room_id, message).await
+    }
+
+    // Audit operations
+    async fn add_audit_event(
+        &mut self,
+        room_id: &RoomId,
+        actor_id: Option<&UserId>,
+        action: AuditAction,
+        payload: &Value,
+    ) -> Result<(), AppError> {
+        self.inner
+            .add_audit_event(room_id, actor_id, action, payload)
+            .await
+    }
+
+    // Event log operations
+    async fn append_room_event(
+        &mut self,
+        room_id: &RoomId,
+        event: &str,
+        vote: Option<&Vote>,
+    ) -> Result<i64, AppError> {
+        self.inner.append_room_event(room_id, event, vote).await
+    }
+
+    // Archive operations
+    async fn ids_taken(&mut self, archived: &ArchivedRoom) -> Result<bool, AppError> {
+        self.inner.ids_taken(archived).await
+    }
+
+    async fn restore_room(&mut self, archived: &ArchivedRoom) -> Result<(), AppError> {
+        self.changed.push(archived.room.id.clone());
+        self.inner.restore_room(archived).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), AppError> {
+        self.inner.commit().await?;
+
+        for room_id in &self.changed {
+            self.cache.invalidate(room_id);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RoomCache;
+    use crate::models::room::{Room, RoomSettings};
+    use std::time::Duration;
+
+    fn room(name: &str) -> Room {
+        Room::new(name.to_string(), None, RoomSettings::default())
+    }
+
+    #[test]
+    fn full_cache_drops_least_recently_used_room() {
+        let cache = RoomCache::with_limits(2, Duration::from_secs(60));
+        let (first, second, third) = (room("first"), room("second"), room("third"));
+
+        cache.insert_if_unchanged(&first, cache.generation());
+        cache.insert_if_unchanged(&second, cache.generation());
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(cache.get(&first.id).is_some());
+
+        cache.insert_if_unchanged(&third, cache.generation());
+        assert!(cache.get(&first.id).is_some());
+        assert!(cache.get(&second.id).is_none());
+        assert!(cache.get(&third.id).is_some());
+    }
+
+    #[test]
+    fn expired_rooms_are_reloaded() {
+        let cache = RoomCache::with_limits(2, Duration::ZERO);
+        let room = room("expired");
+
+        cache.insert_if_unchanged(&room, cache.generation());
+        assert!(cache.get(&room.id).is_none());
+        assert!(cache.rooms.is_empty());
+    }
+}
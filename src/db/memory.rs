@@ -0,0 +1,890 @@
+use crate::db::{Database, LoggedEvent, Transaction};
+use crate::error::{AppError, ErrorCode};
+use crate::models::archive::{ArchivedRoom, ArchivedVote};
+use crate::models::audit::{AuditAction, AuditEvent, AuditFilter};
+use crate::models::chat::{CHAT_HISTORY_LIMIT, ChatMessage};
+use crate::models::room::{Room, RoomId, RoomSettings, RoomState};
+use crate::models::user::{User, UserId};
+use crate::models::vote::{CastVote, Vote};
+use async_trait::async_trait;
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use time::OffsetDateTime;
+use tokio::sync::{OwnedRwLockWriteGuard, RwLock};
+
+type ChatHistory = HashMap<RoomId, Vec<ChatMessage>>;
+
+// Event log per room, numbered from 1
+type EventLog = HashMap<RoomId, Vec<LoggedEvent>>;
+
+// Audit events per room, oldest first
+#[derive(Default)]
+struct AuditLog {
+    rooms: HashMap<RoomId, Vec<AuditEvent>>,
+    last_id: i64,
+}
+
+impl AuditLog {
+    // Append an event, numbering it after the last one
+    fn add(&mut self, room_id: &RoomId, mut event: AuditEvent) {
+        self.last_id += 1;
+        event.id = self.last_id;
+        self.rooms.entry(room_id.clone()).or_default().push(event);
+    }
+}
+
+// An audit event happening now, numbered once it is added to the log
+fn audit_event(actor_id: Option<&UserId>, action: AuditAction, payload: &Value) -> AuditEvent {
+    AuditEvent {
+        id: 0,
+        actor_id: actor_id.cloned(),
+        action,
+        payload: payload.clone(),
+        created_at: OffsetDateTime::now_utc(),
+    }
+}
+
+// The rooms table, without the rows that live in other tables
+#[derive(Clone)]
+struct StoredRoom {
+    name: String,
+    state: RoomState,
+    owner_id: Option<UserId>,
+    settings: RoomSettings,
+    round_id: i64,
+    created_at: OffsetDateTime,
+    updated_at: OffsetDateTime,
+    archived_at: Option<OffsetDateTime>,
+}
+
+#[derive(Clone)]
+struct StoredUser {
+    user: User,
+    room_id: RoomId,
+}
+
+#[derive(Clone)]
+struct StoredVote {
+    room_id: RoomId,
+    vote: CastVote,
+}
+
+// A row as it was before a transaction changed it, None if it didn't exist
+enum Undo {
+    Room(RoomId, Option<StoredRoom>),
+    User(UserId, Option<StoredUser>),
+    Vote((UserId, i64), Option<StoredVote>),
+}
+
+#[derive(Default)]
+struct Tables {
+    rooms: HashMap<RoomId, StoredRoom>,
+    users: HashMap<UserId, StoredUser>,
+    // Keyed by user and round, keeping the votes of earlier rounds
+    votes: HashMap<(UserId, i64), StoredVote>,
+    // Rows changed since the last commit, oldest first
+    undo_log: Vec<Undo>,
+}
+
+// Every operation checks before it changes anything, so a failed one leaves
+// the tables untouched. Rows are only changed through the methods below, which
+// note what they replace in the undo log.
+impl Tables {
+    fn set_room(&mut self, room_id: &RoomId, room: Option<StoredRoom>) {
+        let old = match room {
+            Some(room) => self.rooms.insert(room_id.clone(), room),
+            None => self.rooms.remove(room_id),
+        };
+        self.undo_log.push(Undo::Room(room_id.clone(), old));
+    }
+
+    fn set_user(&mut self, user_id: &UserId, user: Option<StoredUser>) {
+        let old = match user {
+            Some(user) => self.users.insert(user_id.clone(), user),
+            None => self.users.remove(user_id),
+        };
+        self.undo_log.push(Undo::User(user_id.clone(), old));
+    }
+
+    fn set_vote(&mut self, key: &(UserId, i64), vote: Option<StoredVote>) {
+        let old = match vote {
+            Some(vote) => self.votes.insert(key.clone(), vote),
+            None => self.votes.remove(key),
+        };
+        self.undo_log.push(Undo::Vote(key.clone(), old));
+    }
+
+    // Change the room's row in place
+    fn update_room(&mut self, room_id: &RoomId, change: impl FnOnce(&mut StoredRoom)) {
+        if let Some(mut room) = self.rooms.get(room_id).cloned() {
+            change(&mut room);
+            self.set_room(room_id, Some(room));
+        }
+    }
+
+    // Keep the changes made so far
+    fn commit(&mut self) {
+        self.undo_log.clear();
+    }
+
+    // Put back every row changed since the last commit, newest change first
+    fn roll_back(&mut self) {
+        while let Some(undo) = self.undo_log.pop() {
+            match undo {
+                Undo::Room(room_id, Some(room)) => {
+                    self.rooms.insert(room_id, room);
+                }
+                Undo::Room(room_id, None) => {
+                    self.rooms.remove(&room_id);
+                }
+                Undo::User(user_id, Some(user)) => {
+                    self.users.insert(user_id, user);
+                }
+                Undo::User(user_id, None) => {
+                    self.users.remove(&user_id);
+                }
+                Undo::Vote(key, Some(vote)) => {
+                    self.votes.insert(key, vote);
+                }
+                Undo::Vote(key, None) => {
+                    self.votes.remove(&key);
+                }
+            }
+        }
+    }
+
+    // Room operations
+    fn create_room(&mut self, room: &Room) -> Result<(), AppError> {
+        if self.rooms.contains_key(&room.id) {
+            return Err(AppError::DatabaseError(
+                "UNIQUE constraint failed: rooms.id".to_string(),
+            ));
+        }
+        if room.users.keys().any(|id| self.users.contains_key(id)) {
+            return Err(AppError::DatabaseError(
+                "UNIQUE constraint failed: users.id".to_string(),
+            ));
+        }
+
+        self.set_room(
+            &room.id,
+            Some(StoredRoom {
+                name: room.name.clone(),
+                state: room.state.clone(),
+                owner_id: room.owner_id.clone(),
+                settings: room.settings.clone(),
+                round_id: room.round_id,
+                created_at: room.created_at,
+                updated_at: room.updated_at,
+                archived_at: room.archived_at,
+            }),
+        );
+
+        // Add initial users if any, keeping the room's own updated_at
+        for user in room.users.values() {
+            self.add_user(user, &room.id)?;
+        }
+        self.touch_room(&room.id, room.updated_at);
+
+        Ok(())
+    }
+
+    fn get_room(&self, room_id: &RoomId) -> Option<Room> {
+        let stored = self.rooms.get(room_id)?;
+
+        let mut room = Room {
+            id: room_id.clone(),
+            name: stored.name.clone(),
+            state: stored.state.clone(),
+            users: self.users_for_room(room_id),
+            votes: self.votes_for_room(room_id, stored.round_id),
+            round_id: stored.round_id,
+            owner_id: stored.owner_id.clone(),
+            settings: stored.settings.clone(),
+            created_at: stored.created_at,
+            updated_at: stored.updated_at,
+            archived_at: stored.archived_at,
+        };
+        room.sort_members();
+
+        Some(room)
+    }
+
+    fn users_for_room(&self, room_id: &RoomId) -> IndexMap<UserId, User> {
+        self.users
+            .values()
+            .filter(|stored| stored.room_id == *room_id)
+            .map(|stored| (stored.user.id.clone(), stored.user.clone()))
+            .collect()
+    }
+
+    fn votes_for_room(&self, room_id: &RoomId, round_id: i64) -> IndexMap<UserId, CastVote> {
+        self.votes
+            .iter()
+            .filter(|((_, round), stored)| stored.room_id == *room_id && *round == round_id)
+            .map(|((user_id, _), stored)| (user_id.clone(), stored.vote.clone()))
+            .collect()
+    }
+
+    fn update_room_state(&mut self, room_id: &RoomId, state: &RoomState) {
+        self.update_room(room_id, |room| {
+            room.state = state.clone();
+            room.updated_at = OffsetDateTime::now_utc();
+        });
+    }
+
+    fn update_room_settings(&mut self, room_id: &RoomId, settings: &RoomSettings) {
+        self.update_room(room_id, |room| {
+            room.settings = settings.clone();
+            room.updated_at = OffsetDateTime::now_utc();
+        });
+    }
+
+    fn update_room_archived_at(&mut self, room_id: &RoomId, archived_at: Option<OffsetDateTime>) {
+        self.update_room(room_id, |room| {
+            room.archived_at = archived_at;
+            room.updated_at = OffsetDateTime::now_utc();
+        });
+    }
+
+    // Record a change to the room, its users or votes
+    fn touch_room(&mut self, room_id: &RoomId, at: OffsetDateTime) {
+        self.update_room(room_id, |room| room.updated_at = at);
+    }
+
+    fn delete_room(&mut self, room_id: &RoomId) {
+        self.set_room(room_id, None);
+
+        // Cascade like the foreign keys do
+        let user_ids: Vec<UserId> = self
+            .users
+            .iter()
+            .filter(|(_, stored)| stored.room_id == *room_id)
+            .map(|(user_id, _)| user_id.clone())
+            .collect();
+        for user_id in &user_ids {
+            self.set_user(user_id, None);
+        }
+
+        let vote_keys: Vec<(UserId, i64)> = self
+            .votes
+            .iter()
+            .filter(|(_, stored)| stored.room_id == *room_id)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &vote_keys {
+            self.set_vote(key, None);
+        }
+    }
+
+    fn update_room_owner(&mut self, room_id: &RoomId, owner_id: Option<&UserId>) {
+        self.update_room(room_id, |room| {
+            room.owner_id = owner_id.cloned();
+            room.updated_at = OffsetDateTime::now_utc();
+        });
+    }
+
+    // User operations
+    fn add_user(&mut self, user: &User, room_id: &RoomId) -> Result<(), AppError> {
+        // Same constraints the SQL schemas enforce
+        if !self.rooms.contains_key(room_id) {
+            return Err(AppError::DatabaseError(
+                "FOREIGN KEY constraint failed".to_string(),
+            ));
+        }
+        if self.users.contains_key(&user.id) {
+            return Err(AppError::DatabaseError(
+                "UNIQUE constraint failed: users.id".to_string(),
+            ));
+        }
+
+        self.set_user(
+            &user.id,
+            Some(StoredUser {
+                user: user.clone(),
+                room_id: room_id.clone(),
+            }),
+        );
+        self.touch_room(room_id, user.joined_at);
+
+        Ok(())
+    }
+
+    fn remove_user(&mut self, user_id: &UserId) -> Option<(User, RoomId)> {
+        let stored = self.users.get(user_id)?.clone();
+        self.set_user(user_id, None);
+
+        // Remove their votes too
+        let vote_keys: Vec<(UserId, i64)> = self
+            .votes
+            .keys()
+            .filter(|(id, _)| id == user_id)
+            .cloned()
+            .collect();
+        for key in &vote_keys {
+            self.set_vote(key, None);
+        }
+        self.touch_room(&stored.room_id, OffsetDateTime::now_utc());
+
+        Some((stored.user, stored.room_id))
+    }
+
+    // Vote operations
+    // Store the vote, keeping when the user first voted if they change it
+    fn add_vote(
+        &mut self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        vote: &CastVote,
+    ) -> Result<(), AppError> {
+        let Some(room) = self.rooms.get(room_id) else {
+            return Err(AppError::NotFound(
+                ErrorCode::RoomNotFound,
+                "Room not found".to_string(),
+            ));
+        };
+        let round_id = room.round_id;
+        if !self.users.contains_key(user_id) {
+            return Err(AppError::DatabaseError(
+                "FOREIGN KEY constraint failed".to_string(),
+            ));
+        }
+
+        let key = (user_id.clone(), round_id);
+        let cast_at = match self.votes.get(&key) {
+            Some(stored) => stored.vote.cast_at,
+            None => vote.cast_at,
+        };
+        self.set_vote(
+            &key,
+            Some(StoredVote {
+                room_id: room_id.clone(),
+                vote: CastVote {
+                    cast_at,
+                    ..vote.clone()
+                },
+            }),
+        );
+        self.touch_room(room_id, vote.updated_at);
+
+        Ok(())
+    }
+
+    fn reset_votes_for_room(&mut self, room_id: &RoomId) {
+        // Start the next round, keeping the votes of this one
+        self.update_room(room_id, |room| room.round_id += 1);
+
+        // Also reset room state to voting
+        self.update_room_state(room_id, &RoomState::Voting);
+    }
+}
+
+// Storage kept entirely in process memory, for tests and throwaway deployments.
+// Everything is lost when the server stops.
+#[derive(Default)]
+pub struct MemoryDatabase {
+    tables: Arc<RwLock<Tables>>,
+    // Kept apart from the tables, since transactions only ever append to them
+    // and can do so on commit
+    chat_messages: Arc<Mutex<ChatHistory>>,
+    audit_log: Arc<Mutex<AuditLog>>,
+    event_log: Arc<Mutex<EventLog>>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn chat_messages(&self) -> MutexGuard<'_, ChatHistory> {
+        lock(&self.chat_messages)
+    }
+
+    fn audit_log(&self) -> MutexGuard<'_, AuditLog> {
+        lock(&self.audit_log)
+    }
+
+    fn event_log(&self) -> MutexGuard<'_, EventLog> {
+        lock(&self.event_log)
+    }
+}
+
+// No await happens while these locks are held, so a poisoned lock can only mean
+// a panic mid-update and the data is still usable
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[async_trait]
+impl Database for MemoryDatabase {
+    // Room operations
+    async fn get_room(&self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+        Ok(self.tables.read().await.get_room(room_id))
+    }
+
+    async fn list_room_ids(&self) -> Result<Vec<RoomId>, AppError> {
+        let mut room_ids: Vec<RoomId> = self.tables.read().await.rooms.keys().cloned().collect();
+        room_ids.sort_by_key(|room_id| room_id.0);
+
+        Ok(room_ids)
+    }
+
+    // User operations
+    async fn user_in_room(&self, room_id: &RoomId, user_id: &UserId) -> Result<bool, AppError> {
+        Ok(self
+            .tables
+            .read()
+            .await
+            .users
+            .get(user_id)
+            .is_some_and(|stored| stored.room_id == *room_id))
+    }
+
+    // Vote operations
+    async fn get_votes(&self, room_id: &RoomId) -> Result<Vec<ArchivedVote>, AppError> {
+        let mut votes: Vec<_> = self
+            .tables
+            .read()
+            .await
+            .votes
+            .iter()
+            .filter(|(_, stored)| stored.room_id == *room_id)
+            .map(|((user_id, round_id), stored)| ArchivedVote {
+                user_id: user_id.clone(),
+                round_id: *round_id,
+                vote: stored.vote.clone(),
+            })
+            .collect();
+        votes
+            .sort_by_key(|archived| (archived.round_id, archived.vote.cast_at, archived.user_id.0));
+
+        Ok(votes)
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>, AppError> {
+        // Hold the write lock for the whole transaction and change the tables
+        // in place, undoing the changes unless it commits
+        let tables = self.tables.clone().write_owned().await;
+
+        Ok(Box::new(MemoryTransaction {
+            tables,
+            chat_messages: self.chat_messages.clone(),
+            audit_log: self.audit_log.clone(),
+            event_log: self.event_log.clone(),
+            chat_messages_added: Vec::new(),
+            audit_events: Vec::new(),
+            room_events: Vec::new(),
+            restored: Vec::new(),
+        }))
+    }
+
+    // Chat operations
+    async fn get_chat_messages(&self, room_id: &RoomId) -> Result<Vec<ChatMessage>, AppError> {
+        Ok(self
+            .chat_messages()
+            .get(room_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    // Audit operations
+    async fn get_audit_events(
+        &self,
+        room_id: &RoomId,
+        filter: &AuditFilter,
+    ) -> Result<Vec<AuditEvent>, AppError> {
+        Ok(self
+            .audit_log()
+            .rooms
+            .get(room_id)
+            .map(|events| {
+                events
+                    .iter()
+                    .rev()
+                    .filter(|event| filter.matches(event))
+                    .take(filter.limit as usize)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    // Event log operations
+    async fn get_room_events(
+        &self,
+        room_id: &RoomId,
+        after_seq: i64,
+    ) -> Result<Vec<LoggedEvent>, AppError> {
+        Ok(self
+            .event_log()
+            .get(room_id)
+            .map(|events| {
+                events
+                    .iter()
+                    .filter(|event| event.seq > after_seq)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn get_room_with_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<(Option<Room>, Vec<LoggedEvent>), AppError> {
+        // Events are only appended while the write lock is held, so the read
+        // lock keeps the room and its log as of the same moment
+        let tables = self.tables.read().await;
+        let events = self.event_log().get(room_id).cloned().unwrap_or_default();
+
+        Ok((tables.get_room(room_id), events))
+    }
+
+    async fn purge_archived_rooms(
+        &self,
+        archived_before: OffsetDateTime,
+    ) -> Result<Vec<RoomId>, AppError> {
+        let mut tables = self.tables.write().await;
+
+        let mut room_ids: Vec<RoomId> = tables
+            .rooms
+            .iter()
+            .filter(|(_, stored)| stored.archived_at.is_some_and(|at| at < archived_before))
+            .map(|(room_id, _)| room_id.clone())
+            .collect();
+        room_ids.sort_by_key(|room_id| room_id.0);
+
+        for room_id in &room_ids {
+            tables.delete_room(room_id);
+            self.chat_messages().remove(room_id);
+            self.audit_log().rooms.remove(room_id);
+            self.event_log().remove(room_id);
+        }
+        tables.commit();
+
+        Ok(room_ids)
+    }
+}
+
+// An open in-memory transaction. Dropping it undoes its changes and releases
+// the lock.
+pub struct MemoryTransaction {
+    tables: OwnedRwLockWriteGuard<Tables>,
+    chat_messages: Arc<Mutex<ChatHistory>>,
+    audit_log: Arc<Mutex<AuditLog>>,
+    event_log: Arc<Mutex<EventLog>>,
+    // Chat messages, log entries and restored rooms' histories, written on commit
+    chat_messages_added: Vec<(RoomId, ChatMessage)>,
+    audit_events: Vec<(RoomId, AuditEvent)>,
+    room_events: Vec<(RoomId, LoggedEvent)>,
+    restored: Vec<ArchivedRoom>,
+}
+
+#[async_trait]
+impl Transaction for MemoryTransaction {
+    // Room operations
+    async fn create_room(&mut self, room: &Room) -> Result<(), AppError> {
+        self.tables.create_room(room)
+    }
+
+    async fn get_room(&mut self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+        Ok(self.tables.get_room(room_id))
+    }
+
+    async fn update_room_state(
+        &mut self,
+        room_id: &RoomId,
+        state: &RoomState,
+    ) -> Result<(), AppError> {
+        self.tables.update_room_state(room_id, state);
+
+        Ok(())
+    }
+
+    async fn update_room_settings(
+        &mut self,
+        room_id: &RoomId,
+        settings: &RoomSettings,
+    ) -> Result<(), AppError> {
+        self.tables.update_room_settings(room_id, settings);
+
+        Ok(())
+    }
+
+    async fn update_room_archived_at(
+        &mut self,
+        room_id: &RoomId,
+        archived_at: Option<OffsetDateTime>,
+    ) -> Result<(), AppError> {
+        self.tables.update_room_archived_at(room_id, archived_at);
+
+        Ok(())
+    }
+
+    async fn update_room_owner(
+        &mut self,
+        room_id: &RoomId,
+        owner_id: Option<&UserId>,
+    ) -> Result<(), AppError> {
+        self.tables.update_room_owner(room_id, owner_id);
+
+        Ok(())
+    }
+
+    // User operations
+    async fn add_user(&mut self, user: &User, room_id: &RoomId) -> Result<(), AppError> {
+        self.tables.add_user(user, room_id)
+    }
+
+    async fn remove_user(&mut self, user_id: &UserId) -> Result<Option<(User, RoomId)>, AppError> {
+        Ok(self.tables.remove_user(user_id))
+    }
+
+    // Vote operations
+    async fn add_vote(
+        &mut self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        vote: &Vote,
+    ) -> Result<(), AppError> {
+        self.tables
+            .add_vote(room_id, user_id, &CastVote::new(vote.clone()))
+    }
+
+    async fn reset_votes_for_room(&mut self, room_id: &RoomId) -> Result<(), AppError> {
+        self.tables.reset_votes_for_room(room_id);
+
+        Ok(())
+    }
+
+    // Chat operations
+    async fn add_chat_message(
+        &mut self,
+        room_id: &RoomId,
+        message: &ChatMessage,
+    ) -> Result<(), AppError> {
+        if !self.tables.rooms.contains_key(room_id) {
+            return Err(AppError::DatabaseError(
+                "FOREIGN KEY constraint failed".to_string(),
+            ));
+        }
+
+        self.chat_messages_added
+            .push((room_id.clone(), message.clone()));
+
+        Ok(())
+    }
+
+    // Audit operations
+    async fn add_audit_event(
+        &mut self,
+        room_id: &RoomId,
+        actor_id: Option<&UserId>,
+        action: AuditAction,
+        payload: &Value,
+    ) -> Result<(), AppError> {
+        if !self.tables.rooms.contains_key(room_id) {
+            return Err(AppError::DatabaseError(
+                "FOREIGN KEY constraint failed".to_string(),
+            ));
+        }
+
+        self.audit_events
+            .push((room_id.clone(), audit_event(actor_id, action, payload)));
+
+        Ok(())
+    }
+
+    // Event log operations
+    async fn append_room_event(
+        &mut self,
+        room_id: &RoomId,
+        event: &str,
+        vote: Option<&Vote>,
+    ) -> Result<i64, AppError> {
+        if !self.tables.rooms.contains_key(room_id) {
+            return Err(AppError::NotFound(
+                ErrorCode::RoomNotFound,
+                "Room not found".to_string(),
+            ));
+        }
+
+        // Number the event after the last one logged, by this transaction or
+        // before it. Every append holds the write lock, so none can interleave.
+        let last_seq = match self
+            .room_events
+            .iter()
+            .rev()
+            .find(|(logged_room_id, _)| logged_room_id == room_id)
+        {
+            Some((_, logged)) => logged.seq,
+            None => lock(&self.event_log)
+                .get(room_id)
+                .and_then(|events| events.last())
+                .map_or(0, |last| last.seq),
+        };
+        let seq = last_seq + 1;
+
+        self.room_events.push((
+            room_id.clone(),
+            LoggedEvent {
+                seq,
+                event: event.to_string(),
+                vote: vote.cloned(),
+                created_at: OffsetDateTime::now_utc(),
+            },
+        ));
+
+        Ok(seq)
+    }
+
+    // Archive operations
+    async fn ids_taken(&mut self, archived: &ArchivedRoom) -> Result<bool, AppError> {
+        let room = &archived.room;
+
+        if self.tables.rooms.contains_key(&room.id)
+            || room
+                .users
+                .keys()
+                .any(|id| self.tables.users.contains_key(id))
+        {
+            return Ok(true);
+        }
+
+        let chat_messages = lock(&self.chat_messages);
+        Ok(archived.chat_messages.iter().any(|message| {
+            chat_messages
+                .values()
+                .flatten()
+                .any(|existing| existing.id == message.id)
+        }))
+    }
+
+    async fn restore_room(&mut self, archived: &ArchivedRoom) -> Result<(), AppError> {
+        let room = &archived.room;
+
+        self.tables.create_room(room)?;
+        for archived_vote in &archived.votes {
+            self.tables.set_vote(
+                &(archived_vote.user_id.clone(), archived_vote.round_id),
+                Some(StoredVote {
+                    room_id: room.id.clone(),
+                    vote: archived_vote.vote.clone(),
+                }),
+            );
+        }
+        self.tables.touch_room(&room.id, room.updated_at);
+
+        // The histories are kept outside the tables, so they are written on commit
+        self.restored.push(archived.clone());
+
+        Ok(())
+    }
+
+    async fn commit(mut self: Box<Self>) -> Result<(), AppError> {
+        let mut chat_messages = lock(&self.chat_messages);
+        let mut audit_log = lock(&self.audit_log);
+        let mut event_log = lock(&self.event_log);
+
+        for (room_id, message) in self.chat_messages_added.drain(..) {
+            let messages = chat_messages.entry(room_id).or_default();
+            messages.push(message);
+
+            // Keep only the most recent messages for the room
+            let excess = messages.len().saturating_sub(CHAT_HISTORY_LIMIT as usize);
+            messages.drain(..excess);
+        }
+
+        for (room_id, event) in self.audit_events.drain(..) {
+            audit_log.add(&room_id, event);
+        }
+
+        for (room_id, event) in self.room_events.drain(..) {
+            event_log.entry(room_id).or_default().push(event);
+        }
+
+        for archived in self.restored.drain(..) {
+            let room_id = archived.room.id;
+            chat_messages.insert(room_id.clone(), archived.chat_messages);
+            for event in archived.audit_events {
+                audit_log.add(&room_id, event);
+            }
+
+            let events = archived
+                .events
+                .into_iter()
+                .map(|logged| {
+                    Ok(LoggedEvent {
+                        seq: logged.seq,
+                        event: serde_json::to_string(&logged.event)
+                            .map_err(|e| AppError::DatabaseError(e.to_string()))?,
+                        vote: logged.vote,
+                        created_at: logged.created_at,
+                    })
+                })
+                .collect::<Result<_, AppError>>()?;
+            event_log.insert(room_id, events);
+        }
+
+        drop((chat_messages, audit_log, event_log));
+
+        self.tables.commit();
+
+        Ok(())
+    }
+}
+
+impl Drop for MemoryTransaction {
+    fn drop(&mut self) {
+        // Nothing is left to undo after a commit
+        self.tables.roll_back();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryDatabase;
+    use crate::db::Database;
+    use crate::models::room::{Room, RoomSettings, RoomState};
+    use crate::models::user::User;
+    use crate::models::vote::Vote;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn dropped_transaction_undoes_its_changes() {
+        let db = MemoryDatabase::new();
+        let owner = User::new("Owner".to_string(), false);
+        let room = Room::new(
+            "Undo".to_string(),
+            Some(owner.clone()),
+            RoomSettings::default(),
+        );
+
+        let mut tx = db.begin().await.unwrap();
+        tx.create_room(&room).await.unwrap();
+        tx.add_vote(&room.id, &owner.id, &Vote::Five).await.unwrap();
+        tx.commit().await.unwrap();
+        let committed = db.get_room(&room.id).await.unwrap().unwrap();
+
+        let guest = User::new("Guest".to_string(), false);
+        let mut tx = db.begin().await.unwrap();
+        tx.add_user(&guest, &room.id).await.unwrap();
+        tx.add_vote(&room.id, &guest.id, &Vote::Three)
+            .await
+            .unwrap();
+        tx.add_vote(&room.id, &owner.id, &Vote::Eight)
+            .await
+            .unwrap();
+        tx.update_room_state(&room.id, &RoomState::Revealed)
+            .await
+            .unwrap();
+        tx.reset_votes_for_room(&room.id).await.unwrap();
+        tx.remove_user(&owner.id).await.unwrap();
+        drop(tx);
+
+        let room = db.get_room(&room.id).await.unwrap().unwrap();
+        assert_eq!(json!(room), json!(committed));
+    }
+}
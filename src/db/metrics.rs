@@ -0,0 +1,326 @@
+use crate::db::{Database, EventOutbox, LoggedEvent, Transaction};
+use crate::error::AppError;
+use crate::metrics::Metrics;
+use crate::models::archive::{ArchivedRoom, ArchivedVote};
+use crate::models::audit::{AuditAction, AuditEvent, AuditFilter};
+use crate::models::chat::ChatMessage;
+use crate::models::room::{Room, RoomId, RoomSettings, RoomState};
+use crate::models::user::{User, UserId};
+use crate::models::vote::Vote;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+// Record how long a storage backend call took, labelled with its method name
+async fn timed<T>(metrics: &Metrics, method: &str, call: impl Future<Output = T>) -> T {
+    let _timer = metrics
+        .db_query_duration
+        .with_label_values(&[method])
+        .start_timer();
+
+    call.await
+}
+
+// Storage backend wrapper timing every call for the /metrics endpoint. It sits
+// below the room cache, so only calls that reach the backend are measured.
+pub struct MeteredDatabase {
+    inner: Arc<dyn Database>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredDatabase {
+    pub fn new(inner: Arc<dyn Database>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+#[async_trait]
+impl Database for MeteredDatabase {
+    // Room operations
+    async fn get_room(&self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+        timed(&self.metrics, "get_room", self.inner.get_room(room_id)).await
+    }
+
+    async fn list_room_ids(&self) -> Result<Vec<RoomId>, AppError> {
+        timed(&self.metrics, "list_room_ids", self.inner.list_room_ids()).await
+    }
+
+    // User operations
+    async fn user_in_room(&self, room_id: &RoomId, user_id: &UserId) -> Result<bool, AppError> {
+        timed(
+            &self.metrics,
+            "user_in_room",
+            self.inner.user_in_room(room_id, user_id),
+        )
+        .await
+    }
+
+    // Vote operations
+    async fn get_votes(&self, room_id: &RoomId) -> Result<Vec<ArchivedVote>, AppError> {
+        timed(&self.metrics, "get_votes", self.inner.get_votes(room_id)).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>, AppError> {
+        Ok(Box::new(MeteredTransaction {
+            inner: timed(&self.metrics, "begin", self.inner.begin()).await?,
+            metrics: self.metrics.clone(),
+        }))
+    }
+
+    // Chat operations
+    async fn get_chat_messages(&self, room_id: &RoomId) -> Result<Vec<ChatMessage>, AppError> {
+        timed(
+            &self.metrics,
+            "get_chat_messages",
+            self.inner.get_chat_messages(room_id),
+        )
+        .await
+    }
+
+    // Audit operations
+    async fn get_audit_events(
+        &self,
+        room_id: &RoomId,
+        filter: &AuditFilter,
+    ) -> Result<Vec<AuditEvent>, AppError> {
+        timed(
+            &self.metrics,
+            "get_audit_events",
+            self.inner.get_audit_events(room_id, filter),
+        )
+        .await
+    }
+
+    // Event log operations
+    async fn get_room_events(
+        &self,
+        room_id: &RoomId,
+        after_seq: i64,
+    ) -> Result<Vec<LoggedEvent>, AppError> {
+        timed(
+            &self.metrics,
+            "get_room_events",
+            self.inner.get_room_events(room_id, after_seq),
+        )
+        .await
+    }
+
+    async fn get_room_with_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<(Option<Room>, Vec<LoggedEvent>), AppError> {
+        timed(
+            &self.metrics,
+            "get_room_with_events",
+            self.inner.get_room_with_events(room_id),
+        )
+        .await
+    }
+
+    async fn purge_archived_rooms(
+        &self,
+        archived_before: OffsetDateTime,
+    ) -> Result<Vec<RoomId>, AppError> {
+        timed(
+            &self.metrics,
+            "purge_archived_rooms",
+            self.inner.purge_archived_rooms(archived_before),
+        )
+        .await
+    }
+
+    async fn backup(&self, path: &str) -> Result<(), AppError> {
+        timed(&self.metrics, "backup", self.inner.backup(path)).await
+    }
+
+    async fn schema_version(&self) -> Result<Option<i64>, AppError> {
+        timed(&self.metrics, "schema_version", self.inner.schema_version()).await
+    }
+
+    async fn close(&self) {
+        self.inner.close().await
+    }
+
+    fn as_outbox(self: Arc<Self>) -> Option<Arc<dyn EventOutbox>> {
+        self.inner.clone().as_outbox()
+    }
+}
+
+// Transaction timing every call, including the commit
+pub struct MeteredTransaction {
+    inner: Box<dyn Transaction>,
+    metrics: Arc<Metrics>,
+}
+
+#[async_trait]
+impl Transaction for MeteredTransaction {
+    // Room operations
+    async fn create_room(&mut self, room: &Room) -> Result<(), AppError> {
+        timed(&self.metrics, "create_room", self.inner.create_room(room)).await
+    }
+
+    async fn get_room(&mut self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+        timed(&self.metrics, "get_room", self.inner.get_room(room_id)).await
+    }
+
+    async fn update_room_state(
+        &mut self,
+        room_id: &RoomId,
+        state: &RoomState,
+    ) -> Result<(), AppError> {
+        timed(
+            &self.metrics,
+            "update_room_state",
+            self.inner.update_room_state(room_id, state),
+        )
+        .await
+    }
+
+    async fn update_room_settings(
+        &mut self,
+        room_id: &RoomId,
+        settings: &RoomSettings,
+    ) -> Result<(), AppError> {
+        timed(
+            &self.metrics,
+            "update_room_settings",
+            self.inner.update_room_settings(room_id, settings),
+        )
+        .await
+    }
+
+    async fn update_room_archived_at(
+        &mut self,
+        room_id: &RoomId,
+        archived_at: Option<OffsetDateTime>,
+    ) -> Result<(), AppError> {
+        timed(
+            &self.metrics,
+            "update_room_archived_at",
+            self.inner.update_room_archived_at(room_id, archived_at),
+        )
+        .await
+    }
+
+    async fn update_room_owner(
+        &mut self,
+        room_id: &RoomId,
+        owner_id: Option<&UserId>,
+    ) -> Result<(), AppError> {
+        timed(
+            &self.metrics,
+            "update_room_owner",
+            self.inner.update_room_owner(room_id, owner_id),
+        )
+        .await
+    }
+
+    // User operations
+    async fn add_user(&mut self, user: &User, room_id: &RoomId) -> Result<(), AppError> {
+        timed(
+            &self.metrics,
+            "add_user",
+            self.inner.add_user(user, room_id),
+        )
+        .await
+    }
+
+    async fn remove_user(&mut self, user_id: &UserId) -> Result<Option<(User, RoomId)>, AppError> {
+        timed(
+            &self.metrics,
+            "remove_user",
+            self.inner.remove_user(user_id),
+        )
+        .await
+    }
+
+    // Vote operations
+    async fn add_vote(
+        &mut self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        vote: &Vote,
+    ) -> Result<(), AppError> {
+        timed(
+            &self.metrics,
+            "add_vote",
+            self.inner.add_vote(room_id, user_id, vote),
+        )
+        .await
+    }
+
+    async fn reset_votes_for_room(&mut self, room_id: &RoomId) -> Result<(), AppError> {
+        timed(
+            &self.metrics,
+            "reset_votes_for_room",
+            self.inner.reset_votes_for_room(room_id),
+        )
+        .await
+    }
+
+    // Chat operations
+    async fn add_chat_message(
+        &mut self,
+        room_id: &RoomId,
+        message: &ChatMessage,
+    ) -> Result<(), AppError> {
+        timed(
+            &self.metrics,
+            "add_chat_message",
+            self.inner.add_chat_message(room_id, message),
+        )
+        .await
+    }
+
+    // Audit operations
+    async fn add_audit_event(
+        &mut self,
+        room_id: &RoomId,
+        actor_id: Option<&UserId>,
+        action: AuditAction,
+        payload: &Value,
+    ) -> Result<(), AppError> {
+        timed(
+            &self.metrics,
+            "add_audit_event",
+            self.inner
+                .add_audit_event(room_id, actor_id, action, payload),
+        )
+        .await
+    }
+
+    // Event log operations
+    async fn append_room_event(
+        &mut self,
+        room_id: &RoomId,
+        event: &str,
+        vote: Option<&Vote>,
+    ) -> Result<i64, AppError> {
+        timed(
+            &self.metrics,
+            "append_room_event",
+            self.inner.append_room_event(room_id, event, vote),
+        )
+        .await
+    }
+
+    // Archive operations
+    async fn ids_taken(&mut self, archived: &ArchivedRoom) -> Result<bool, AppError> {
+        timed(&self.metrics, "ids_taken", self.inner.ids_taken(archived)).await
+    }
+
+    async fn restore_room(&mut self, archived: &ArchivedRoom) -> Result<(), AppError> {
+        timed(
+            &self.metrics,
+            "restore_room",
+            self.inner.restore_room(archived),
+        )
+        .await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), AppError> {
+        timed(&self.metrics, "commit", self.inner.commit()).await
+    }
+}
@@ -0,0 +1,1348 @@
+use crate::db::{Database, EventOutbox, LoggedEvent, OutboxEvent, Transaction};
+use crate::error::{AppError, ErrorCode};
+use crate::models::archive::{ArchivedRoom, ArchivedVote};
+use crate::models::audit::{AuditAction, AuditEvent, AuditFilter};
+use crate::models::chat::{CHAT_HISTORY_LIMIT, ChatMessage};
+use crate::models::room::{Room, RoomId, RoomSettings, RoomState, SessionMode};
+use crate::models::user::{User, UserId};
+use crate::models::vote::{CastVote, Vote};
+use async_trait::async_trait;
+use indexmap::IndexMap;
+use serde_json::Value;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::{PgConnection, PgPoolOptions};
+use sqlx::types::Json;
+use sqlx::{Pool, Postgres, QueryBuilder, Row};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+// Schema changes applied on top of the base tables, in order, mirroring the
+// SQLite migrations. The number of applied migrations is kept in the
+// `schema_version` table, so entries must never be edited or reordered once released.
+const MIGRATIONS: &[&str] = &[
+    // 1: per-room policy for multiple WebSocket connections of one user
+    "ALTER TABLE rooms ADD COLUMN session_mode TEXT NOT NULL DEFAULT 'multi_device'",
+    // 2, 3: room lookups of users and votes, which foreign keys don't index
+    "CREATE INDEX IF NOT EXISTS idx_users_room_id ON users (room_id)",
+    "CREATE INDEX IF NOT EXISTS idx_votes_room_id ON votes (room_id)",
+    // 4: audit log of changes to each room
+    r#"
+    CREATE TABLE audit_events (
+        id BIGSERIAL PRIMARY KEY,
+        room_id UUID NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+        actor_id UUID,
+        action TEXT NOT NULL,
+        payload JSONB NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL
+    );
+    CREATE INDEX idx_audit_events_room_id ON audit_events (room_id, id);
+    "#,
+    // 5: event log of each room, numbered by a per-room sequence. Events are
+    // kept as TEXT rather than JSONB so they replay byte for byte.
+    r#"
+    ALTER TABLE rooms ADD COLUMN last_event_seq BIGINT NOT NULL DEFAULT 0;
+    CREATE TABLE room_events (
+        room_id UUID NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+        seq BIGINT NOT NULL,
+        event TEXT NOT NULL,
+        vote TEXT,
+        created_at TIMESTAMPTZ NOT NULL,
+        PRIMARY KEY (room_id, seq)
+    );
+    "#,
+    // 6: archived rooms, kept read-only until purged
+    r#"
+    ALTER TABLE rooms ADD COLUMN archived_at TIMESTAMPTZ;
+    CREATE INDEX idx_rooms_archived_at ON rooms (archived_at);
+    "#,
+    // 7: when rooms were created and last changed, users joined and votes
+    // were cast. Existing rows get the time of the migration.
+    r#"
+    ALTER TABLE rooms ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+    ALTER TABLE rooms ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+    ALTER TABLE users ADD COLUMN joined_at TIMESTAMPTZ NOT NULL DEFAULT now();
+    ALTER TABLE votes ADD COLUMN cast_at TIMESTAMPTZ NOT NULL DEFAULT now();
+    ALTER TABLE votes ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+    "#,
+    // 8: votes stored by card ID and round rather than by display label, so
+    // relabelling a card can't break stored votes. Existing rooms count their
+    // rounds from the resets in their event log.
+    r#"
+    ALTER TABLE rooms ADD COLUMN round_id BIGINT NOT NULL DEFAULT 1;
+    UPDATE rooms SET round_id = 1 + (
+        SELECT COUNT(*) FROM room_events e
+        WHERE e.room_id = rooms.id AND e.event::jsonb ->> 'eventType' = 'votesReset'
+    );
+    CREATE TEMP TABLE card_ids (label TEXT PRIMARY KEY, card_id TEXT NOT NULL);
+    INSERT INTO card_ids (label, card_id) VALUES
+        ('0', 'zero'), ('1', 'one'), ('2', 'two'), ('3', 'three'), ('5', 'five'),
+        ('8', 'eight'), ('13', 'thirteen'), ('21', 'twenty_one'),
+        ('?', 'question_mark'), ('coffee', 'coffee');
+    -- Stop at any label without a card ID rather than store one that can't
+    -- be read back
+    DO $$
+    DECLARE
+        unmapped TEXT;
+    BEGIN
+        SELECT label INTO unmapped FROM (
+            SELECT vote AS label FROM votes
+            UNION ALL
+            SELECT vote FROM room_events WHERE vote IS NOT NULL
+        ) labels
+        WHERE lower(label) NOT IN (SELECT label FROM card_ids)
+        LIMIT 1;
+        IF FOUND THEN
+            RAISE EXCEPTION 'Vote label % has no card ID', unmapped;
+        END IF;
+    END
+    $$;
+    ALTER TABLE votes ADD COLUMN round_id BIGINT NOT NULL DEFAULT 1;
+    ALTER TABLE votes ADD COLUMN card_id TEXT NOT NULL DEFAULT '';
+    UPDATE votes SET
+        round_id = (SELECT round_id FROM rooms WHERE rooms.id = votes.room_id),
+        card_id = (SELECT c.card_id FROM card_ids c WHERE c.label = lower(votes.vote));
+    ALTER TABLE votes DROP COLUMN vote;
+    ALTER TABLE room_events RENAME COLUMN vote TO card_id;
+    UPDATE room_events
+    SET card_id = (SELECT c.card_id FROM card_ids c WHERE c.label = lower(room_events.card_id))
+    WHERE card_id IS NOT NULL;
+    DROP TABLE card_ids;
+    "#,
+    // 9: votes keyed by user and round, so resets keep earlier rounds
+    r#"
+    ALTER TABLE votes DROP CONSTRAINT votes_pkey;
+    ALTER TABLE votes ADD PRIMARY KEY (user_id, round_id);
+    DROP INDEX IF EXISTS idx_votes_room_id;
+    CREATE INDEX idx_votes_room_id ON votes (room_id, round_id);
+    "#,
+    // 10: events logged before users and votes had timestamps get them from
+    // the log. A join takes the time it was logged, and a revealed vote the
+    // times its user first and last voted in that round.
+    r#"
+    UPDATE room_events SET event = jsonb_set(
+        event::jsonb, '{payload,joinedAt}',
+        to_jsonb(to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.US"Z"'))
+    )::text
+    WHERE event::jsonb ->> 'eventType' = 'userJoined'
+        AND event::jsonb -> 'payload' -> 'joinedAt' IS NULL;
+    CREATE TEMP TABLE vote_times AS
+        SELECT reveal.room_id, reveal.seq,
+            submitted.event::jsonb -> 'payload' ->> 'userId' AS user_id,
+            MIN(submitted.created_at) AS cast_at, MAX(submitted.created_at) AS updated_at
+        FROM room_events reveal
+        JOIN room_events submitted
+            ON submitted.room_id = reveal.room_id AND submitted.seq < reveal.seq
+        WHERE reveal.event::jsonb ->> 'eventType' = 'votesRevealed'
+            AND submitted.event::jsonb ->> 'eventType' = 'voteSubmitted'
+            AND NOT EXISTS (
+                SELECT 1 FROM room_events reset_event
+                WHERE reset_event.room_id = reveal.room_id
+                    AND reset_event.seq > submitted.seq AND reset_event.seq < reveal.seq
+                    AND reset_event.event::jsonb ->> 'eventType' = 'votesReset'
+            )
+        GROUP BY reveal.room_id, reveal.seq, 3;
+    UPDATE room_events e SET event = jsonb_set(e.event::jsonb, '{payload,votes}', COALESCE((
+        SELECT jsonb_agg(v.vote || jsonb_build_object(
+            'castAt', to_char(COALESCE(t.cast_at, e.created_at) AT TIME ZONE 'UTC',
+                              'YYYY-MM-DD"T"HH24:MI:SS.US"Z"'),
+            'updatedAt', to_char(COALESCE(t.updated_at, e.created_at) AT TIME ZONE 'UTC',
+                                 'YYYY-MM-DD"T"HH24:MI:SS.US"Z"')
+        ) ORDER BY v.index)
+        FROM jsonb_array_elements(e.event::jsonb -> 'payload' -> 'votes')
+            WITH ORDINALITY AS v(vote, index)
+        LEFT JOIN vote_times t
+            ON t.room_id = e.room_id AND t.seq = e.seq AND t.user_id = v.vote ->> 'userId'
+    ), '[]'::jsonb))::text
+    WHERE e.event::jsonb ->> 'eventType' = 'votesRevealed'
+        AND e.event::jsonb -> 'payload' -> 'votes' -> 0 -> 'castAt' IS NULL;
+    DROP TABLE vote_times;
+    "#,
+    // 11: room states in logged events and audit payloads in camelCase, like
+    // the API sends them
+    r#"
+    UPDATE room_events
+    SET event = jsonb_set(
+        event::jsonb, '{payload,state}', to_jsonb(lower(event::jsonb -> 'payload' ->> 'state'))
+    )::text
+    WHERE event::jsonb ->> 'eventType' = 'roomUpdated'
+        AND event::jsonb -> 'payload' ->> 'state' <> lower(event::jsonb -> 'payload' ->> 'state');
+    UPDATE audit_events
+    SET payload = jsonb_set(payload, '{previousState}', to_jsonb(lower(payload ->> 'previousState')))
+    WHERE action = 'votes_reset'
+        AND payload ->> 'previousState' <> lower(payload ->> 'previousState');
+    "#,
+];
+
+// Advisory lock key held while the schema is created and migrated, so
+// concurrently starting instances don't apply the same migration twice
+const MIGRATION_LOCK_KEY: i64 = 0x0070_6f6b_6572;
+
+// Storage backed by a PostgreSQL database
+pub struct PostgresDatabase {
+    pool: Pool<Postgres>,
+}
+
+impl PostgresDatabase {
+    pub async fn new(db_url: &str, max_connections: u32) -> Result<Self, AppError> {
+        // Unlike SQLite the database itself must already exist
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(db_url)
+            .await
+            .map_err(|e| {
+                AppError::DatabaseError(format!("Failed to connect to database: {}", e))
+            })?;
+
+        Self::migrate(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    async fn migrate(pool: &Pool<Postgres>) -> Result<(), AppError> {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let result = async {
+            sqlx::query("SELECT pg_advisory_xact_lock($1)")
+                .bind(MIGRATION_LOCK_KEY)
+                .execute(&mut *tx)
+                .await?;
+
+            Self::create_schema(&mut tx).await?;
+
+            let version: i32 = sqlx::query("SELECT version FROM schema_version")
+                .fetch_one(&mut *tx)
+                .await?
+                .get("version");
+
+            for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+                sqlx::raw_sql(migration).execute(&mut *tx).await?;
+                sqlx::query("UPDATE schema_version SET version = $1")
+                    .bind(index as i32 + 1)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            Ok::<_, sqlx::Error>(())
+        }
+        .await;
+
+        match result {
+            Ok(()) => tx
+                .commit()
+                .await
+                .map_err(|e| AppError::DatabaseError(e.to_string())),
+            Err(e) => Err(AppError::DatabaseError(format!("Migration failed: {}", e))),
+        }
+    }
+
+    async fn create_schema(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+        // Create rooms table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS rooms (
+                id UUID PRIMARY KEY,
+                name TEXT NOT NULL,
+                state TEXT NOT NULL,
+                owner_id UUID
+            )
+            "#,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        // Create users table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS users (
+                id UUID PRIMARY KEY,
+                name TEXT NOT NULL,
+                is_observer BOOLEAN NOT NULL,
+                room_id UUID NOT NULL REFERENCES rooms (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        // Create votes table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS votes (
+                user_id UUID PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+                room_id UUID NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+                vote TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        // Create chat messages table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS chat_messages (
+                id UUID PRIMARY KEY,
+                room_id UUID NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+                user_id UUID NOT NULL,
+                text TEXT NOT NULL,
+                sent_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        // Create event outbox table, shared by all server instances. Ids are only
+        // roughly in commit order, a late commit below the last polled id is missed.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS event_outbox (
+                id BIGSERIAL PRIMARY KEY,
+                node_id UUID NOT NULL,
+                room_id UUID NOT NULL,
+                event TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        // Postgres has no user_version, track applied migrations in a one-row table
+        sqlx::query("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO schema_version (version)
+            SELECT 0 WHERE NOT EXISTS (SELECT 1 FROM schema_version)
+            "#,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    // Pooled connection for a single operation
+    async fn conn(&self) -> Result<PoolConnection<Postgres>, AppError> {
+        self.pool
+            .acquire()
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl Database for PostgresDatabase {
+    // Room operations
+    async fn get_room(&self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+        get_room(&mut *self.conn().await?, room_id).await
+    }
+
+    async fn list_room_ids(&self) -> Result<Vec<RoomId>, AppError> {
+        let rows = sqlx::query("SELECT id FROM rooms ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.into_iter().map(|row| RoomId(row.get("id"))).collect())
+    }
+
+    // User operations
+    async fn user_in_room(&self, room_id: &RoomId, user_id: &UserId) -> Result<bool, AppError> {
+        user_in_room(&mut *self.conn().await?, room_id, user_id).await
+    }
+
+    // Vote operations
+    async fn get_votes(&self, room_id: &RoomId) -> Result<Vec<ArchivedVote>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT user_id, round_id, card_id, cast_at, updated_at FROM votes
+            WHERE room_id = $1
+            ORDER BY round_id ASC, cast_at ASC, user_id ASC
+            "#,
+        )
+        .bind(room_id.0)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut votes = Vec::with_capacity(rows.len());
+        for row in rows {
+            let card_id: String = row.get("card_id");
+
+            votes.push(ArchivedVote {
+                user_id: UserId(row.get("user_id")),
+                round_id: row.get("round_id"),
+                vote: CastVote {
+                    vote: Vote::from_card_id(&card_id).map_err(AppError::DatabaseError)?,
+                    cast_at: row.get("cast_at"),
+                    updated_at: row.get("updated_at"),
+                },
+            });
+        }
+
+        Ok(votes)
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>, AppError> {
+        let tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(Box::new(PostgresTransaction { tx }))
+    }
+
+    // Chat operations
+    async fn get_chat_messages(&self, room_id: &RoomId) -> Result<Vec<ChatMessage>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, user_id, text, sent_at FROM chat_messages
+            WHERE room_id = $1
+            ORDER BY sent_at ASC, id ASC
+            "#,
+        )
+        .bind(room_id.0)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ChatMessage {
+                id: row.get("id"),
+                user_id: UserId(row.get("user_id")),
+                text: row.get("text"),
+                sent_at: row.get("sent_at"),
+            })
+            .collect())
+    }
+
+    // Audit operations
+    async fn get_audit_events(
+        &self,
+        room_id: &RoomId,
+        filter: &AuditFilter,
+    ) -> Result<Vec<AuditEvent>, AppError> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "SELECT id, actor_id, action, payload, created_at FROM audit_events WHERE room_id = ",
+        );
+        query.push_bind(room_id.0);
+
+        if let Some(action) = filter.action {
+            query.push(" AND action = ").push_bind(action.as_str());
+        }
+        if let Some(actor_id) = &filter.actor_id {
+            query.push(" AND actor_id = ").push_bind(actor_id.0);
+        }
+        if let Some(since) = filter.since {
+            query.push(" AND created_at >= ").push_bind(since);
+        }
+        if let Some(until) = filter.until {
+            query.push(" AND created_at < ").push_bind(until);
+        }
+        if let Some(before) = filter.before {
+            query.push(" AND id < ").push_bind(before);
+        }
+        query
+            .push(" ORDER BY id DESC LIMIT ")
+            .push_bind(filter.limit);
+
+        let rows = query
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut events = Vec::with_capacity(rows.len());
+        for row in rows {
+            let action_str: String = row.get("action");
+            let actor_id: Option<Uuid> = row.get("actor_id");
+            let Json(payload) = row.get("payload");
+
+            events.push(AuditEvent {
+                id: row.get("id"),
+                actor_id: actor_id.map(UserId),
+                action: AuditAction::from_string(&action_str).map_err(AppError::DatabaseError)?,
+                payload,
+                created_at: row.get("created_at"),
+            });
+        }
+
+        Ok(events)
+    }
+
+    // Event log operations
+    async fn get_room_events(
+        &self,
+        room_id: &RoomId,
+        after_seq: i64,
+    ) -> Result<Vec<LoggedEvent>, AppError> {
+        get_room_events(&mut *self.conn().await?, room_id, after_seq).await
+    }
+
+    async fn get_room_with_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<(Option<Room>, Vec<LoggedEvent>), AppError> {
+        // A repeatable read transaction sees one snapshot of the database,
+        // and being read only it locks nothing writers need
+        let mut tx = self
+            .pool
+            .begin_with("BEGIN ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let room = get_room(&mut tx, room_id).await?;
+        let events = get_room_events(&mut tx, room_id, 0).await?;
+
+        tx.rollback()
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok((room, events))
+    }
+
+    async fn purge_archived_rooms(
+        &self,
+        archived_before: OffsetDateTime,
+    ) -> Result<Vec<RoomId>, AppError> {
+        // Users, votes and the room's history go with it through the foreign keys
+        let rows = sqlx::query("DELETE FROM rooms WHERE archived_at < $1 RETURNING id")
+            .bind(archived_before)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.into_iter().map(|row| RoomId(row.get("id"))).collect())
+    }
+
+    async fn schema_version(&self) -> Result<Option<i64>, AppError> {
+        let version: i32 = sqlx::query("SELECT version FROM schema_version")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?
+            .get("version");
+
+        Ok(Some(version.into()))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    fn as_outbox(self: Arc<Self>) -> Option<Arc<dyn EventOutbox>> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl EventOutbox for PostgresDatabase {
+    async fn add_outbox_event(
+        &self,
+        node_id: &Uuid,
+        room_id: &RoomId,
+        event: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO event_outbox (node_id, room_id, event, created_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(node_id)
+        .bind(room_id.0)
+        .bind(event)
+        .bind(OffsetDateTime::now_utc())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn latest_outbox_event_id(&self) -> Result<i64, AppError> {
+        let row = sqlx::query("SELECT COALESCE(MAX(id), 0) as id FROM event_outbox")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(row.get("id"))
+    }
+
+    async fn get_outbox_events_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<OutboxEvent>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, node_id, room_id, event FROM event_outbox
+            WHERE id > $1
+            ORDER BY id ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| OutboxEvent {
+                id: row.get("id"),
+                node_id: row.get("node_id"),
+                room_id: RoomId(row.get("room_id")),
+                event: row.get("event"),
+            })
+            .collect())
+    }
+
+    async fn prune_outbox_events(&self, older_than: OffsetDateTime) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM event_outbox WHERE created_at < $1")
+            .bind(older_than)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+// An open Postgres transaction, rolled back by sqlx if dropped before commit
+pub struct PostgresTransaction {
+    tx: sqlx::Transaction<'static, Postgres>,
+}
+
+#[async_trait]
+impl Transaction for PostgresTransaction {
+    // Room operations
+    async fn create_room(&mut self, room: &Room) -> Result<(), AppError> {
+        create_room(&mut self.tx, room).await
+    }
+
+    async fn get_room(&mut self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+        // Lock the row until the end of the transaction, as SQLite would
+        sqlx::query("SELECT 1 FROM rooms WHERE id = $1 FOR UPDATE")
+            .bind(room_id.0)
+            .execute(&mut *self.tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        get_room(&mut self.tx, room_id).await
+    }
+
+    async fn update_room_state(
+        &mut self,
+        room_id: &RoomId,
+        state: &RoomState,
+    ) -> Result<(), AppError> {
+        update_room_state(&mut self.tx, room_id, state).await
+    }
+
+    async fn update_room_settings(
+        &mut self,
+        room_id: &RoomId,
+        settings: &RoomSettings,
+    ) -> Result<(), AppError> {
+        update_room_settings(&mut self.tx, room_id, settings).await
+    }
+
+    async fn update_room_archived_at(
+        &mut self,
+        room_id: &RoomId,
+        archived_at: Option<OffsetDateTime>,
+    ) -> Result<(), AppError> {
+        update_room_archived_at(&mut self.tx, room_id, archived_at).await
+    }
+
+    async fn update_room_owner(
+        &mut self,
+        room_id: &RoomId,
+        owner_id: Option<&UserId>,
+    ) -> Result<(), AppError> {
+        update_room_owner(&mut self.tx, room_id, owner_id).await
+    }
+
+    // User operations
+    async fn add_user(&mut self, user: &User, room_id: &RoomId) -> Result<(), AppError> {
+        add_user(&mut self.tx, user, room_id).await
+    }
+
+    async fn remove_user(&mut self, user_id: &UserId) -> Result<Option<(User, RoomId)>, AppError> {
+        remove_user(&mut self.tx, user_id).await
+    }
+
+    // Vote operations
+    async fn add_vote(
+        &mut self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        vote: &Vote,
+    ) -> Result<(), AppError> {
+        add_vote(&mut self.tx, room_id, user_id, &CastVote::new(vote.clone())).await
+    }
+
+    async fn reset_votes_for_room(&mut self, room_id: &RoomId) -> Result<(), AppError> {
+        reset_votes_for_room(&mut self.tx, room_id).await
+    }
+
+    // Chat operations
+    async fn add_chat_message(
+        &mut self,
+        room_id: &RoomId,
+        message: &ChatMessage,
+    ) -> Result<(), AppError> {
+        add_chat_message(&mut self.tx, room_id, message).await
+    }
+
+    // Audit operations
+    async fn add_audit_event(
+        &mut self,
+        room_id: &RoomId,
+        actor_id: Option<&UserId>,
+        action: AuditAction,
+        payload: &Value,
+    ) -> Result<(), AppError> {
+        add_audit_event(&mut self.tx, room_id, actor_id, action, payload).await
+    }
+
+    // Event log operations
+    async fn append_room_event(
+        &mut self,
+        room_id: &RoomId,
+        event: &str,
+        vote: Option<&Vote>,
+    ) -> Result<i64, AppError> {
+        append_room_event(&mut self.tx, room_id, event, vote).await
+    }
+
+    // Archive operations
+    async fn ids_taken(&mut self, archived: &ArchivedRoom) -> Result<bool, AppError> {
+        ids_taken(&mut self.tx, archived).await
+    }
+
+    async fn restore_room(&mut self, archived: &ArchivedRoom) -> Result<(), AppError> {
+        restore_room(&mut self.tx, archived).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), AppError> {
+        self.tx
+            .commit()
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))
+    }
+}
+
+// Queries shared by pooled connections and transactions
+
+// Room operations
+async fn create_room(conn: &mut PgConnection, room: &Room) -> Result<(), AppError> {
+    let state_str = match room.state {
+        RoomState::Voting => "voting",
+        RoomState::Revealed => "revealed",
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO rooms (id, name, state, owner_id, session_mode, archived_at,
+                           created_at, updated_at, round_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+    )
+    .bind(room.id.0)
+    .bind(&room.name)
+    .bind(state_str)
+    .bind(room.owner_id.as_ref().map(|id| id.0))
+    .bind(room.settings.session_mode.as_str())
+    .bind(room.archived_at)
+    .bind(room.created_at)
+    .bind(room.updated_at)
+    .bind(room.round_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    // Add initial users if any, keeping the room's own updated_at
+    for user in room.users.values() {
+        add_user(conn, user, &room.id).await?;
+    }
+    touch_room(conn, &room.id, room.updated_at).await?;
+
+    Ok(())
+}
+
+async fn get_room(conn: &mut PgConnection, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+    // Load the room with its users and their votes in the current round in one
+    // go, one row per user or a single row with NULL user columns for an empty room
+    let rows = sqlx::query(
+        r#"
+        SELECT r.name, r.state, r.owner_id, r.session_mode, r.archived_at,
+               r.created_at, r.updated_at, r.round_id,
+               u.id AS user_id, u.name AS user_name, u.is_observer, u.joined_at,
+               v.card_id, v.cast_at, v.updated_at AS vote_updated_at
+        FROM rooms r
+        LEFT JOIN users u ON u.room_id = r.id
+        LEFT JOIN votes v ON v.user_id = u.id AND v.room_id = r.id AND v.round_id = r.round_id
+        WHERE r.id = $1
+        "#,
+    )
+    .bind(room_id.0)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let Some(row) = rows.first() else {
+        return Ok(None);
+    };
+
+    let name: String = row.get("name");
+    let state_str: String = row.get("state");
+    let owner_id: Option<Uuid> = row.get("owner_id");
+    let session_mode_str: String = row.get("session_mode");
+    let archived_at: Option<OffsetDateTime> = row.get("archived_at");
+    let created_at: OffsetDateTime = row.get("created_at");
+    let updated_at: OffsetDateTime = row.get("updated_at");
+    let round_id: i64 = row.get("round_id");
+
+    let state = match state_str.as_str() {
+        "voting" => RoomState::Voting,
+        "revealed" => RoomState::Revealed,
+        _ => return Err(AppError::DatabaseError("Invalid room state".to_string())),
+    };
+
+    let settings = RoomSettings {
+        session_mode: SessionMode::from_string(&session_mode_str)
+            .map_err(AppError::DatabaseError)?,
+    };
+
+    let mut users = IndexMap::new();
+    let mut votes = IndexMap::new();
+    for row in &rows {
+        let Some(user_id) = row.get::<Option<Uuid>, _>("user_id").map(UserId) else {
+            continue;
+        };
+
+        if let Some(card_id) = row.get::<Option<String>, _>("card_id") {
+            let vote = Vote::from_card_id(&card_id).map_err(AppError::DatabaseError)?;
+            votes.insert(
+                user_id.clone(),
+                CastVote {
+                    vote,
+                    cast_at: row.get("cast_at"),
+                    updated_at: row.get("vote_updated_at"),
+                },
+            );
+        }
+
+        users.insert(
+            user_id.clone(),
+            User {
+                id: user_id,
+                name: row.get("user_name"),
+                is_observer: row.get("is_observer"),
+                joined_at: row.get("joined_at"),
+            },
+        );
+    }
+
+    let mut room = Room {
+        id: room_id.clone(),
+        name,
+        state,
+        users,
+        votes,
+        round_id,
+        owner_id: owner_id.map(UserId),
+        settings,
+        created_at,
+        updated_at,
+        archived_at,
+    };
+    room.sort_members();
+
+    Ok(Some(room))
+}
+
+async fn update_room_state(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    state: &RoomState,
+) -> Result<(), AppError> {
+    let state_str = match state {
+        RoomState::Voting => "voting",
+        RoomState::Revealed => "revealed",
+    };
+
+    sqlx::query("UPDATE rooms SET state = $1, updated_at = $2 WHERE id = $3")
+        .bind(state_str)
+        .bind(OffsetDateTime::now_utc())
+        .bind(room_id.0)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+async fn update_room_settings(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    settings: &RoomSettings,
+) -> Result<(), AppError> {
+    sqlx::query("UPDATE rooms SET session_mode = $1, updated_at = $2 WHERE id = $3")
+        .bind(settings.session_mode.as_str())
+        .bind(OffsetDateTime::now_utc())
+        .bind(room_id.0)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+async fn update_room_archived_at(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    archived_at: Option<OffsetDateTime>,
+) -> Result<(), AppError> {
+    sqlx::query("UPDATE rooms SET archived_at = $1, updated_at = $2 WHERE id = $3")
+        .bind(archived_at)
+        .bind(OffsetDateTime::now_utc())
+        .bind(room_id.0)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+async fn update_room_owner(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    owner_id: Option<&UserId>,
+) -> Result<(), AppError> {
+    sqlx::query("UPDATE rooms SET owner_id = $1, updated_at = $2 WHERE id = $3")
+        .bind(owner_id.map(|id| id.0))
+        .bind(OffsetDateTime::now_utc())
+        .bind(room_id.0)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+// Record a change to the room, its users or votes
+async fn touch_room(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    at: OffsetDateTime,
+) -> Result<(), AppError> {
+    sqlx::query("UPDATE rooms SET updated_at = $1 WHERE id = $2")
+        .bind(at)
+        .bind(room_id.0)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+// User operations
+async fn add_user(conn: &mut PgConnection, user: &User, room_id: &RoomId) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO users (id, name, is_observer, room_id, joined_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(user.id.0)
+    .bind(&user.name)
+    .bind(user.is_observer)
+    .bind(room_id.0)
+    .bind(user.joined_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    touch_room(conn, room_id, user.joined_at).await?;
+
+    Ok(())
+}
+
+async fn remove_user(
+    conn: &mut PgConnection,
+    user_id: &UserId,
+) -> Result<Option<(User, RoomId)>, AppError> {
+    // The vote goes with the user through its foreign key
+    let row = sqlx::query(
+        "DELETE FROM users WHERE id = $1 RETURNING name, is_observer, room_id, joined_at",
+    )
+    .bind(user_id.0)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let user = User {
+        id: user_id.clone(),
+        name: row.get("name"),
+        is_observer: row.get("is_observer"),
+        joined_at: row.get("joined_at"),
+    };
+    let room_id = RoomId(row.get("room_id"));
+    touch_room(conn, &room_id, OffsetDateTime::now_utc()).await?;
+
+    Ok(Some((user, room_id)))
+}
+
+async fn user_in_room(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    user_id: &UserId,
+) -> Result<bool, AppError> {
+    let row = sqlx::query("SELECT 1 FROM users WHERE id = $1 AND room_id = $2")
+        .bind(user_id.0)
+        .bind(room_id.0)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(row.is_some())
+}
+
+// Vote operations
+// Store the vote, keeping when the user first voted if they change it
+async fn add_vote(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    user_id: &UserId,
+    vote: &CastVote,
+) -> Result<(), AppError> {
+    let round_id: i64 =
+        sqlx::query("UPDATE rooms SET updated_at = $1 WHERE id = $2 RETURNING round_id")
+            .bind(vote.updated_at)
+            .bind(room_id.0)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?
+            .ok_or_else(|| {
+                AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string())
+            })?
+            .get("round_id");
+
+    sqlx::query(
+        r#"
+        INSERT INTO votes (user_id, room_id, round_id, card_id, cast_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (user_id, round_id) DO UPDATE SET
+            card_id = excluded.card_id,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(user_id.0)
+    .bind(room_id.0)
+    .bind(round_id)
+    .bind(vote.vote.card_id())
+    .bind(vote.cast_at)
+    .bind(vote.updated_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+async fn reset_votes_for_room(conn: &mut PgConnection, room_id: &RoomId) -> Result<(), AppError> {
+    // Start the next round, keeping the votes of this one
+    sqlx::query("UPDATE rooms SET round_id = round_id + 1 WHERE id = $1")
+        .bind(room_id.0)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    // Also reset room state to voting
+    update_room_state(conn, room_id, &RoomState::Voting).await?;
+
+    Ok(())
+}
+
+// Chat operations
+// Store a message, keeping only the most recent ones per room
+async fn add_chat_message(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    message: &ChatMessage,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO chat_messages (id, room_id, user_id, text, sent_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(message.id)
+    .bind(room_id.0)
+    .bind(message.user_id.0)
+    .bind(&message.text)
+    .bind(message.sent_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    // Keep only the most recent messages for the room
+    sqlx::query(
+        r#"
+        DELETE FROM chat_messages
+        WHERE room_id = $1 AND id NOT IN (
+            SELECT id FROM chat_messages
+            WHERE room_id = $1
+            ORDER BY sent_at DESC, id DESC
+            LIMIT $2
+        )
+        "#,
+    )
+    .bind(room_id.0)
+    .bind(CHAT_HISTORY_LIMIT)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+// Audit operations
+async fn add_audit_event(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    actor_id: Option<&UserId>,
+    action: AuditAction,
+    payload: &Value,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_events (room_id, actor_id, action, payload, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(room_id.0)
+    .bind(actor_id.map(|id| id.0))
+    .bind(action.as_str())
+    .bind(Json(payload))
+    .bind(OffsetDateTime::now_utc())
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+// Event log operations
+async fn append_room_event(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    event: &str,
+    vote: Option<&Vote>,
+) -> Result<i64, AppError> {
+    // The room hands out sequence numbers, and updating its row keeps
+    // concurrent appends from taking the same one
+    let seq: i64 = sqlx::query(
+        r#"
+        UPDATE rooms SET last_event_seq = last_event_seq + 1
+        WHERE id = $1
+        RETURNING last_event_seq
+        "#,
+    )
+    .bind(room_id.0)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?
+    .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?
+    .get("last_event_seq");
+
+    sqlx::query(
+        r#"
+        INSERT INTO room_events (room_id, seq, event, card_id, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(room_id.0)
+    .bind(seq)
+    .bind(event)
+    .bind(vote.map(Vote::card_id))
+    .bind(OffsetDateTime::now_utc())
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(seq)
+}
+
+async fn get_room_events(
+    conn: &mut PgConnection,
+    room_id: &RoomId,
+    after_seq: i64,
+) -> Result<Vec<LoggedEvent>, AppError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT seq, event, card_id, created_at FROM room_events
+        WHERE room_id = $1 AND seq > $2
+        ORDER BY seq ASC
+        "#,
+    )
+    .bind(room_id.0)
+    .bind(after_seq)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let mut events = Vec::with_capacity(rows.len());
+    for row in rows {
+        let card_id: Option<String> = row.get("card_id");
+
+        events.push(LoggedEvent {
+            seq: row.get("seq"),
+            event: row.get("event"),
+            vote: card_id
+                .map(|card_id| Vote::from_card_id(&card_id))
+                .transpose()
+                .map_err(AppError::DatabaseError)?,
+            created_at: row.get("created_at"),
+        });
+    }
+
+    Ok(events)
+}
+
+// Archive operations
+async fn ids_taken(conn: &mut PgConnection, archived: &ArchivedRoom) -> Result<bool, AppError> {
+    let room = &archived.room;
+
+    let ids = std::iter::once(("rooms", room.id.0))
+        .chain(room.users.keys().map(|user_id| ("users", user_id.0)))
+        .chain(
+            archived
+                .chat_messages
+                .iter()
+                .map(|message| ("chat_messages", message.id)),
+        );
+
+    for (table, id) in ids {
+        let taken = sqlx::query(&format!("SELECT 1 FROM {} WHERE id = $1", table))
+            .bind(id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?
+            .is_some();
+
+        if taken {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+async fn restore_room(conn: &mut PgConnection, archived: &ArchivedRoom) -> Result<(), AppError> {
+    let room = &archived.room;
+
+    create_room(conn, room).await?;
+
+    for archived_vote in &archived.votes {
+        sqlx::query(
+            r#"
+            INSERT INTO votes (user_id, room_id, round_id, card_id, cast_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(archived_vote.user_id.0)
+        .bind(room.id.0)
+        .bind(archived_vote.round_id)
+        .bind(archived_vote.vote.vote.card_id())
+        .bind(archived_vote.vote.cast_at)
+        .bind(archived_vote.vote.updated_at)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    }
+
+    for message in &archived.chat_messages {
+        sqlx::query(
+            r#"
+            INSERT INTO chat_messages (id, room_id, user_id, text, sent_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(message.id)
+        .bind(room.id.0)
+        .bind(message.user_id.0)
+        .bind(&message.text)
+        .bind(message.sent_at)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    }
+
+    for audit_event in &archived.audit_events {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_events (room_id, actor_id, action, payload, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(room.id.0)
+        .bind(audit_event.actor_id.as_ref().map(|id| id.0))
+        .bind(audit_event.action.as_str())
+        .bind(Json(&audit_event.payload))
+        .bind(audit_event.created_at)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    }
+
+    for logged in &archived.events {
+        let event = serde_json::to_string(&logged.event)
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO room_events (room_id, seq, event, card_id, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(room.id.0)
+        .bind(logged.seq)
+        .bind(event)
+        .bind(logged.vote.as_ref().map(Vote::card_id))
+        .bind(logged.created_at)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    }
+
+    // Carry on numbering after the restored events, and keep the room's own
+    // updated_at rather than when its votes were restored
+    let last_seq = archived.events.iter().map(|logged| logged.seq).max();
+    sqlx::query("UPDATE rooms SET last_event_seq = $1, updated_at = $2 WHERE id = $3")
+        .bind(last_seq.unwrap_or(0))
+        .bind(room.updated_at)
+        .bind(room.id.0)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
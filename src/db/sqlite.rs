@@ -0,0 +1,1512 @@
+use crate::db::{Database, EventOutbox, LoggedEvent, OutboxEvent, Transaction};
+use crate::error::{AppError, ErrorCode};
+use crate::models::archive::{ArchivedRoom, ArchivedVote};
+use crate::models::audit::{AuditAction, AuditEvent, AuditFilter};
+use crate::models::chat::{CHAT_HISTORY_LIMIT, ChatMessage};
+use crate::models::room::{Room, RoomId, RoomSettings, RoomState, SessionMode};
+use crate::models::user::{User, UserId};
+use crate::models::vote::{CastVote, Vote};
+use async_trait::async_trait;
+use indexmap::IndexMap;
+use serde_json::Value;
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous,
+};
+use sqlx::types::Json;
+use sqlx::{Pool, QueryBuilder, Row, Sqlite};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+// Schema changes applied on top of the base tables, in order. The number of
+// applied migrations is kept in the database's `user_version`, so entries must
+// never be edited or reordered once released.
+const MIGRATIONS: &[&str] = &[
+    // 1: per-room policy for multiple WebSocket connections of one user
+    "ALTER TABLE rooms ADD COLUMN session_mode TEXT NOT NULL DEFAULT 'multi_device'",
+    // 2, 3: room lookups of users and votes, which foreign keys don't index
+    "CREATE INDEX IF NOT EXISTS idx_users_room_id ON users (room_id)",
+    "CREATE INDEX IF NOT EXISTS idx_votes_room_id ON votes (room_id)",
+    // 4: audit log of changes to each room
+    r#"
+    CREATE TABLE audit_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        room_id TEXT NOT NULL,
+        actor_id TEXT,
+        action TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE CASCADE
+    );
+    CREATE INDEX idx_audit_events_room_id ON audit_events (room_id, id);
+    "#,
+    // 5: event log of each room, numbered by a per-room sequence
+    r#"
+    ALTER TABLE rooms ADD COLUMN last_event_seq INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE room_events (
+        room_id TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        event TEXT NOT NULL,
+        vote TEXT,
+        created_at TEXT NOT NULL,
+        PRIMARY KEY (room_id, seq),
+        FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE CASCADE
+    );
+    "#,
+    // 6: archived rooms, kept read-only until purged
+    r#"
+    ALTER TABLE rooms ADD COLUMN archived_at TEXT;
+    CREATE INDEX idx_rooms_archived_at ON rooms (archived_at);
+    "#,
+    // 7: when rooms were created and last changed, users joined and votes
+    // were cast. Existing rows get the time of the migration.
+    r#"
+    ALTER TABLE rooms ADD COLUMN created_at TEXT NOT NULL DEFAULT '';
+    ALTER TABLE rooms ADD COLUMN updated_at TEXT NOT NULL DEFAULT '';
+    ALTER TABLE users ADD COLUMN joined_at TEXT NOT NULL DEFAULT '';
+    ALTER TABLE votes ADD COLUMN cast_at TEXT NOT NULL DEFAULT '';
+    ALTER TABLE votes ADD COLUMN updated_at TEXT NOT NULL DEFAULT '';
+    UPDATE rooms SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+                     updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+    UPDATE users SET joined_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+    UPDATE votes SET cast_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+                     updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+    "#,
+    // 8: votes stored by card ID and round rather than by display label, so
+    // relabelling a card can't break stored votes. Existing rooms count their
+    // rounds from the resets in their event log.
+    r#"
+    ALTER TABLE rooms ADD COLUMN round_id INTEGER NOT NULL DEFAULT 1;
+    UPDATE rooms SET round_id = 1 + (
+        SELECT COUNT(*) FROM room_events e
+        WHERE e.room_id = rooms.id AND json_extract(e.event, '$.eventType') = 'votesReset'
+    );
+    CREATE TEMP TABLE card_ids (label TEXT PRIMARY KEY, card_id TEXT NOT NULL);
+    INSERT INTO card_ids (label, card_id) VALUES
+        ('0', 'zero'), ('1', 'one'), ('2', 'two'), ('3', 'three'), ('5', 'five'),
+        ('8', 'eight'), ('13', 'thirteen'), ('21', 'twenty_one'),
+        ('?', 'question_mark'), ('coffee', 'coffee');
+    -- Stop at any label without a card ID rather than store one that can't
+    -- be read back
+    CREATE TEMP TABLE unmapped_votes (
+        label TEXT,
+        CONSTRAINT vote_label_without_card_id CHECK (label IS NULL)
+    );
+    INSERT INTO unmapped_votes (label)
+        SELECT vote FROM votes WHERE lower(vote) NOT IN (SELECT label FROM card_ids)
+        UNION ALL
+        SELECT vote FROM room_events
+        WHERE vote IS NOT NULL AND lower(vote) NOT IN (SELECT label FROM card_ids);
+    DROP TABLE unmapped_votes;
+    ALTER TABLE votes ADD COLUMN round_id INTEGER NOT NULL DEFAULT 1;
+    ALTER TABLE votes ADD COLUMN card_id TEXT NOT NULL DEFAULT '';
+    UPDATE votes SET
+        round_id = (SELECT round_id FROM rooms WHERE rooms.id = votes.room_id),
+        card_id = (SELECT c.card_id FROM card_ids c WHERE c.label = lower(votes.vote));
+    ALTER TABLE votes DROP COLUMN vote;
+    ALTER TABLE room_events RENAME COLUMN vote TO card_id;
+    UPDATE room_events
+    SET card_id = (SELECT c.card_id FROM card_ids c WHERE c.label = lower(room_events.card_id))
+    WHERE card_id IS NOT NULL;
+    DROP TABLE card_ids;
+    "#,
+    // 9: votes keyed by user and round, so resets keep earlier rounds. SQLite
+    // can't change a primary key, so the table is copied.
+    r#"
+    CREATE TABLE votes_by_round (
+        user_id TEXT NOT NULL,
+        room_id TEXT NOT NULL,
+        round_id INTEGER NOT NULL,
+        card_id TEXT NOT NULL,
+        cast_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (user_id, round_id),
+        FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+        FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE CASCADE
+    );
+    INSERT INTO votes_by_round (user_id, room_id, round_id, card_id, cast_at, updated_at)
+        SELECT user_id, room_id, round_id, card_id, cast_at, updated_at FROM votes;
+    DROP TABLE votes;
+    ALTER TABLE votes_by_round RENAME TO votes;
+    CREATE INDEX idx_votes_room_id ON votes (room_id, round_id);
+    "#,
+    // 10: events logged before users and votes had timestamps get them from
+    // the log. A join takes the time it was logged, and a revealed vote the
+    // times its user first and last voted in that round.
+    r#"
+    UPDATE room_events SET event = json_set(event, '$.payload.joinedAt', created_at)
+    WHERE json_extract(event, '$.eventType') = 'userJoined'
+        AND json_type(event, '$.payload.joinedAt') IS NULL;
+    CREATE TEMP TABLE vote_times AS
+        SELECT reveal.room_id, reveal.seq,
+            json_extract(submitted.event, '$.payload.userId') AS user_id,
+            MIN(submitted.created_at) AS cast_at, MAX(submitted.created_at) AS updated_at
+        FROM room_events reveal
+        JOIN room_events submitted
+            ON submitted.room_id = reveal.room_id AND submitted.seq < reveal.seq
+        WHERE json_extract(reveal.event, '$.eventType') = 'votesRevealed'
+            AND json_extract(submitted.event, '$.eventType') = 'voteSubmitted'
+            AND NOT EXISTS (
+                SELECT 1 FROM room_events reset_event
+                WHERE reset_event.room_id = reveal.room_id
+                    AND reset_event.seq > submitted.seq AND reset_event.seq < reveal.seq
+                    AND json_extract(reset_event.event, '$.eventType') = 'votesReset'
+            )
+        GROUP BY reveal.room_id, reveal.seq, user_id;
+    UPDATE room_events SET event = json_set(event, '$.payload.votes', json((
+        SELECT json_group_array(json(vote)) FROM (
+            SELECT json_set(v.value,
+                '$.castAt', COALESCE(t.cast_at, room_events.created_at),
+                '$.updatedAt', COALESCE(t.updated_at, room_events.created_at)) AS vote
+            FROM json_each(room_events.event, '$.payload.votes') v
+            LEFT JOIN vote_times t ON t.room_id = room_events.room_id
+                AND t.seq = room_events.seq AND t.user_id = json_extract(v.value, '$.userId')
+            ORDER BY v.key
+        )
+    )))
+    WHERE json_extract(event, '$.eventType') = 'votesRevealed'
+        AND json_type(event, '$.payload.votes[0].castAt') IS NULL;
+    DROP TABLE vote_times;
+    "#,
+    // 11: room states in logged events and audit payloads in camelCase, like
+    // the API sends them
+    r#"
+    UPDATE room_events
+    SET event = json_set(event, '$.payload.state', lower(json_extract(event, '$.payload.state')))
+    WHERE json_extract(event, '$.eventType') = 'roomUpdated'
+        AND json_extract(event, '$.payload.state') <> lower(json_extract(event, '$.payload.state'));
+    UPDATE audit_events
+    SET payload = json_set(payload, '$.previousState', lower(json_extract(payload, '$.previousState')))
+    WHERE action = 'votes_reset'
+        AND json_extract(payload, '$.previousState') <> lower(json_extract(payload, '$.previousState'));
+    "#,
+];
+
+// How long a connection waits for another one's write lock before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Storage backed by a SQLite file, the default backend
+pub struct SqliteDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl SqliteDatabase {
+    pub async fn new(db_url: &str, max_connections: u32) -> Result<Self, AppError> {
+        use sqlx::migrate::MigrateDatabase;
+
+        // Check if database exists, create it if not
+        if !Sqlite::database_exists(db_url).await.map_err(|e| {
+            AppError::DatabaseError(format!("Failed to check if database exists: {}", e))
+        })? {
+            // Database doesn't exist, create it
+            Sqlite::create_database(db_url).await.map_err(|e| {
+                AppError::DatabaseError(format!("Failed to create database: {}", e))
+            })?;
+
+            tracing::info!("Database created at {}", db_url);
+        }
+
+        // Settings applied to every pooled connection. WAL lets readers run
+        // alongside a writer, and with it NORMAL sync is still safe from
+        // corruption, only the last commits may be lost on power failure.
+        let options = SqliteConnectOptions::from_str(db_url)
+            .map_err(|e| AppError::ConfigError(format!("Invalid DATABASE_URL: {}", e)))?
+            .foreign_keys(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(BUSY_TIMEOUT);
+
+        // Create connection pool
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await
+            .map_err(|e| {
+                AppError::DatabaseError(format!("Failed to connect to database: {}", e))
+            })?;
+
+        // Create schema
+        Self::create_schema(&pool).await?;
+        Self::run_migrations(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    async fn create_schema(pool: &Pool<Sqlite>) -> Result<(), AppError> {
+        // Create rooms table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS rooms (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                state TEXT NOT NULL,
+                owner_id TEXT
+            )
+            "#,
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        // Create users table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS users (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                is_observer INTEGER NOT NULL,
+                room_id TEXT NOT NULL,
+                FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        // Create votes table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS votes (
+                user_id TEXT PRIMARY KEY,
+                room_id TEXT NOT NULL,
+                vote TEXT NOT NULL,
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+                FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        // Create chat messages table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS chat_messages (
+                id TEXT PRIMARY KEY,
+                room_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                text TEXT NOT NULL,
+                sent_at TEXT NOT NULL,
+                FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        // Create event outbox table, shared by all server instances
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS event_outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                node_id TEXT NOT NULL,
+                room_id TEXT NOT NULL,
+                event TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn run_migrations(pool: &Pool<Sqlite>) -> Result<(), AppError> {
+        let mut conn = pool
+            .acquire()
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        // Take the write lock up front so concurrently starting instances
+        // don't apply the same migration twice
+        sqlx::query("BEGIN IMMEDIATE")
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let result = async {
+            let row = sqlx::query("PRAGMA user_version")
+                .fetch_one(&mut *conn)
+                .await?;
+            let version: i64 = row.get(0);
+
+            for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+                sqlx::raw_sql(migration).execute(&mut *conn).await?;
+                sqlx::query(&format!("PRAGMA user_version = {}", index + 1))
+                    .execute(&mut *conn)
+                    .await?;
+            }
+
+            Ok::<_, sqlx::Error>(())
+        }
+        .await;
+
+        let end = if result.is_ok() { "COMMIT" } else { "ROLLBACK" };
+        sqlx::query(end)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        result.map_err(|e| AppError::DatabaseError(format!("Migration failed: {}", e)))
+    }
+
+    // Pooled connection for a single operation
+    async fn conn(&self) -> Result<PoolConnection<Sqlite>, AppError> {
+        self.pool
+            .acquire()
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl Database for SqliteDatabase {
+    // Room operations
+    async fn get_room(&self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+        get_room(&mut *self.conn().await?, room_id).await
+    }
+
+    async fn list_room_ids(&self) -> Result<Vec<RoomId>, AppError> {
+        let rows = sqlx::query("SELECT id FROM rooms ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut room_ids = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id_str: String = row.get("id");
+            room_ids.push(
+                RoomId::from_string(&id_str)
+                    .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
+            );
+        }
+
+        Ok(room_ids)
+    }
+
+    // User operations
+    async fn user_in_room(&self, room_id: &RoomId, user_id: &UserId) -> Result<bool, AppError> {
+        user_in_room(&mut *self.conn().await?, room_id, user_id).await
+    }
+
+    // Vote operations
+    async fn get_votes(&self, room_id: &RoomId) -> Result<Vec<ArchivedVote>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT user_id, round_id, card_id, cast_at, updated_at FROM votes
+            WHERE room_id = ?
+            ORDER BY round_id ASC, cast_at ASC, user_id ASC
+            "#,
+        )
+        .bind(room_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut votes = Vec::with_capacity(rows.len());
+        for row in rows {
+            let user_id_str: String = row.get("user_id");
+            let card_id: String = row.get("card_id");
+
+            votes.push(ArchivedVote {
+                user_id: UserId::from_string(&user_id_str)
+                    .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
+                round_id: row.get("round_id"),
+                vote: CastVote {
+                    vote: Vote::from_card_id(&card_id).map_err(AppError::DatabaseError)?,
+                    cast_at: row.get("cast_at"),
+                    updated_at: row.get("updated_at"),
+                },
+            });
+        }
+
+        Ok(votes)
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>, AppError> {
+        // Take the write lock up front, a deferred transaction that reads
+        // before writing can fail to upgrade when another writer got in first
+        let tx = self
+            .pool
+            .begin_with("BEGIN IMMEDIATE")
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(Box::new(SqliteTransaction { tx }))
+    }
+
+    // Chat operations
+    async fn get_chat_messages(&self, room_id: &RoomId) -> Result<Vec<ChatMessage>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, user_id, text, sent_at FROM chat_messages
+            WHERE room_id = ?
+            ORDER BY sent_at ASC, id ASC
+            "#,
+        )
+        .bind(room_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut messages = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id_str: String = row.get("id");
+            let user_id_str: String = row.get("user_id");
+
+            messages.push(ChatMessage {
+                id: Uuid::from_str(&id_str)
+                    .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
+                user_id: UserId::from_string(&user_id_str)
+                    .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
+                text: row.get("text"),
+                sent_at: row.get("sent_at"),
+            });
+        }
+
+        Ok(messages)
+    }
+
+    // Audit operations
+    async fn get_audit_events(
+        &self,
+        room_id: &RoomId,
+        filter: &AuditFilter,
+    ) -> Result<Vec<AuditEvent>, AppError> {
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT id, actor_id, action, payload, created_at FROM audit_events WHERE room_id = ",
+        );
+        query.push_bind(room_id.to_string());
+
+        if let Some(action) = filter.action {
+            query.push(" AND action = ").push_bind(action.as_str());
+        }
+        if let Some(actor_id) = &filter.actor_id {
+            query
+                .push(" AND actor_id = ")
+                .push_bind(actor_id.to_string());
+        }
+        // Timestamps are RFC 3339 text in any offset and with a varying number
+        // of fractional digits, so compare the instants rather than the text
+        if let Some(since) = filter.since {
+            query
+                .push(" AND julianday(created_at) >= julianday(")
+                .push_bind(since)
+                .push(")");
+        }
+        if let Some(until) = filter.until {
+            query
+                .push(" AND julianday(created_at) < julianday(")
+                .push_bind(until)
+                .push(")");
+        }
+        if let Some(before) = filter.before {
+            query.push(" AND id < ").push_bind(before);
+        }
+        query
+            .push(" ORDER BY id DESC LIMIT ")
+            .push_bind(filter.limit);
+
+        let rows = query
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut events = Vec::with_capacity(rows.len());
+        for row in rows {
+            let actor_id_str: Option<String> = row.get("actor_id");
+            let action_str: String = row.get("action");
+            let Json(payload) = row.get("payload");
+
+            events.push(AuditEvent {
+                id: row.get("id"),
+                actor_id: actor_id_str
+                    .map(|id| UserId::from_string(&id))
+                    .transpose()
+                    .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
+                action: AuditAction::from_string(&action_str).map_err(AppError::DatabaseError)?,
+                payload,
+                created_at: row.get("created_at"),
+            });
+        }
+
+        Ok(events)
+    }
+
+    // Event log operations
+    async fn get_room_events(
+        &self,
+        room_id: &RoomId,
+        after_seq: i64,
+    ) -> Result<Vec<LoggedEvent>, AppError> {
+        get_room_events(&mut *self.conn().await?, room_id, after_seq).await
+    }
+
+    async fn get_room_with_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<(Option<Room>, Vec<LoggedEvent>), AppError> {
+        // A deferred transaction that only reads sees one snapshot of the
+        // database in WAL mode, without taking the write lock
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let room = get_room(&mut tx, room_id).await?;
+        let events = get_room_events(&mut tx, room_id, 0).await?;
+
+        tx.rollback()
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok((room, events))
+    }
+
+    async fn purge_archived_rooms(
+        &self,
+        archived_before: OffsetDateTime,
+    ) -> Result<Vec<RoomId>, AppError> {
+        // Users, votes and the room's history go with it through the foreign keys
+        let rows = sqlx::query("DELETE FROM rooms WHERE archived_at < ? RETURNING id")
+            .bind(archived_before)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut room_ids = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id_str: String = row.get("id");
+            room_ids.push(
+                RoomId::from_string(&id_str)
+                    .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
+            );
+        }
+
+        Ok(room_ids)
+    }
+
+    // Write a consistent copy of the whole database to a new file
+    async fn backup(&self, path: &str) -> Result<(), AppError> {
+        if std::path::Path::new(path).exists() {
+            return Err(AppError::BadRequest(
+                ErrorCode::BackupFileExists,
+                format!("Backup file already exists: {}", path),
+            ));
+        }
+
+        // VACUUM INTO reads a snapshot, so writers carry on meanwhile
+        sqlx::query("VACUUM INTO ?")
+            .bind(path)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Backup failed: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn schema_version(&self) -> Result<Option<i64>, AppError> {
+        let row = sqlx::query("PRAGMA user_version")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(Some(row.get(0)))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    fn as_outbox(self: Arc<Self>) -> Option<Arc<dyn EventOutbox>> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl EventOutbox for SqliteDatabase {
+    async fn add_outbox_event(
+        &self,
+        node_id: &Uuid,
+        room_id: &RoomId,
+        event: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO event_outbox (node_id, room_id, event, created_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(node_id.to_string())
+        .bind(room_id.to_string())
+        .bind(event)
+        .bind(OffsetDateTime::now_utc())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn latest_outbox_event_id(&self) -> Result<i64, AppError> {
+        let row = sqlx::query("SELECT COALESCE(MAX(id), 0) as id FROM event_outbox")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(row.get("id"))
+    }
+
+    async fn get_outbox_events_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<OutboxEvent>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, node_id, room_id, event FROM event_outbox
+            WHERE id > ?
+            ORDER BY id ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut events = Vec::with_capacity(rows.len());
+        for row in rows {
+            let node_id_str: String = row.get("node_id");
+            let room_id_str: String = row.get("room_id");
+
+            events.push(OutboxEvent {
+                id: row.get("id"),
+                node_id: Uuid::from_str(&node_id_str)
+                    .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
+                room_id: RoomId::from_string(&room_id_str)
+                    .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
+                event: row.get("event"),
+            });
+        }
+
+        Ok(events)
+    }
+
+    async fn prune_outbox_events(&self, older_than: OffsetDateTime) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM event_outbox WHERE created_at < ?")
+            .bind(older_than)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+// An open SQLite transaction, rolled back by sqlx if dropped before commit
+pub struct SqliteTransaction {
+    tx: sqlx::Transaction<'static, Sqlite>,
+}
+
+#[async_trait]
+impl Transaction for SqliteTransaction {
+    // Room operations
+    async fn create_room(&mut self, room: &Room) -> Result<(), AppError> {
+        create_room(&mut self.tx, room).await
+    }
+
+    async fn get_room(&mut self, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+        get_room(&mut self.tx, room_id).await
+    }
+
+    async fn update_room_state(
+        &mut self,
+        room_id: &RoomId,
+        state: &RoomState,
+    ) -> Result<(), AppError> {
+        update_room_state(&mut self.tx, room_id, state).await
+    }
+
+    async fn update_room_settings(
+        &mut self,
+        room_id: &RoomId,
+        settings: &RoomSettings,
+    ) -> Result<(), AppError> {
+        update_room_settings(&mut self.tx, room_id, settings).await
+    }
+
+    async fn update_room_archived_at(
+        &mut self,
+        room_id: &RoomId,
+        archived_at: Option<OffsetDateTime>,
+    ) -> Result<(), AppError> {
+        update_room_archived_at(&mut self.tx, room_id, archived_at).await
+    }
+
+    async fn update_room_owner(
+        &mut self,
+        room_id: &RoomId,
+        owner_id: Option<&UserId>,
+    ) -> Result<(), AppError> {
+        update_room_owner(&mut self.tx, room_id, owner_id).await
+    }
+
+    // User operations
+    async fn add_user(&mut self, user: &User, room_id: &RoomId) -> Result<(), AppError> {
+        add_user(&mut self.tx, user, room_id).await
+    }
+
+    async fn remove_user(&mut self, user_id: &UserId) -> Result<Option<(User, RoomId)>, AppError> {
+        remove_user(&mut self.tx, user_id).await
+    }
+
+    // Vote operations
+    async fn add_vote(
+        &mut self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        vote: &Vote,
+    ) -> Result<(), AppError> {
+        add_vote(&mut self.tx, room_id, user_id, &CastVote::new(vote.clone())).await
+    }
+
+    async fn reset_votes_for_room(&mut self, room_id: &RoomId) -> Result<(), AppError> {
+        reset_votes_for_room(&mut self.tx, room_id).await
+    }
+
+    // Chat operations
+    async fn add_chat_message(
+        &mut self,
+        room_id: &RoomId,
+        message: &ChatMessage,
+    ) -> Result<(), AppError> {
+        add_chat_message(&mut self.tx, room_id, message).await
+    }
+
+    // Audit operations
+    async fn add_audit_event(
+        &mut self,
+        room_id: &RoomId,
+        actor_id: Option<&UserId>,
+        action: AuditAction,
+        payload: &Value,
+    ) -> Result<(), AppError> {
+        add_audit_event(&mut self.tx, room_id, actor_id, action, payload).await
+    }
+
+    // Event log operations
+    async fn append_room_event(
+        &mut self,
+        room_id: &RoomId,
+        event: &str,
+        vote: Option<&Vote>,
+    ) -> Result<i64, AppError> {
+        append_room_event(&mut self.tx, room_id, event, vote).await
+    }
+
+    // Archive operations
+    async fn ids_taken(&mut self, archived: &ArchivedRoom) -> Result<bool, AppError> {
+        ids_taken(&mut self.tx, archived).await
+    }
+
+    async fn restore_room(&mut self, archived: &ArchivedRoom) -> Result<(), AppError> {
+        restore_room(&mut self.tx, archived).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), AppError> {
+        self.tx
+            .commit()
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))
+    }
+}
+
+// Queries shared by pooled connections and transactions
+
+// Room operations
+async fn create_room(conn: &mut SqliteConnection, room: &Room) -> Result<(), AppError> {
+    let room_id = room.id.to_string();
+    let state_str = match room.state {
+        RoomState::Voting => "voting",
+        RoomState::Revealed => "revealed",
+    };
+    let owner_id = room.owner_id.as_ref().map(|id| id.to_string());
+
+    sqlx::query(
+        r#"
+        INSERT INTO rooms (id, name, state, owner_id, session_mode, archived_at,
+                           created_at, updated_at, round_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(room_id)
+    .bind(&room.name)
+    .bind(state_str)
+    .bind(owner_id)
+    .bind(room.settings.session_mode.as_str())
+    .bind(room.archived_at)
+    .bind(room.created_at)
+    .bind(room.updated_at)
+    .bind(room.round_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    // Add initial users if any, keeping the room's own updated_at
+    for user in room.users.values() {
+        add_user(conn, user, &room.id).await?;
+    }
+    touch_room(conn, &room.id, room.updated_at).await?;
+
+    Ok(())
+}
+
+async fn get_room(conn: &mut SqliteConnection, room_id: &RoomId) -> Result<Option<Room>, AppError> {
+    // Load the room with its users and their votes in the current round in one
+    // go, one row per user or a single row with NULL user columns for an empty room
+    let rows = sqlx::query(
+        r#"
+        SELECT r.name, r.state, r.owner_id, r.session_mode, r.archived_at,
+               r.created_at, r.updated_at, r.round_id,
+               u.id AS user_id, u.name AS user_name, u.is_observer, u.joined_at,
+               v.card_id, v.cast_at, v.updated_at AS vote_updated_at
+        FROM rooms r
+        LEFT JOIN users u ON u.room_id = r.id
+        LEFT JOIN votes v ON v.user_id = u.id AND v.room_id = r.id AND v.round_id = r.round_id
+        WHERE r.id = ?
+        "#,
+    )
+    .bind(room_id.to_string())
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let Some(row) = rows.first() else {
+        return Ok(None);
+    };
+
+    let name: String = row.get("name");
+    let state_str: String = row.get("state");
+    let owner_id_str: Option<String> = row.get("owner_id");
+    let session_mode_str: String = row.get("session_mode");
+    let archived_at: Option<OffsetDateTime> = row.get("archived_at");
+    let created_at: OffsetDateTime = row.get("created_at");
+    let updated_at: OffsetDateTime = row.get("updated_at");
+    let round_id: i64 = row.get("round_id");
+
+    // Convert to Room model
+    let state = match state_str.as_str() {
+        "voting" => RoomState::Voting,
+        "revealed" => RoomState::Revealed,
+        _ => return Err(AppError::DatabaseError("Invalid room state".to_string())),
+    };
+
+    let owner_id = if let Some(id_str) = owner_id_str {
+        Some(UserId(Uuid::from_str(&id_str).map_err(|e| {
+            AppError::DatabaseError(format!("Invalid UUID: {}", e))
+        })?))
+    } else {
+        None
+    };
+
+    let settings = RoomSettings {
+        session_mode: SessionMode::from_string(&session_mode_str)
+            .map_err(AppError::DatabaseError)?,
+    };
+
+    let mut users = IndexMap::new();
+    let mut votes = IndexMap::new();
+    for row in &rows {
+        let Some(user_id_str) = row.get::<Option<String>, _>("user_id") else {
+            continue;
+        };
+
+        let user_id = UserId(
+            Uuid::from_str(&user_id_str)
+                .map_err(|e| AppError::DatabaseError(format!("Invalid UUID: {}", e)))?,
+        );
+        let is_observer: i64 = row.get("is_observer");
+
+        if let Some(card_id) = row.get::<Option<String>, _>("card_id") {
+            let vote = Vote::from_card_id(&card_id).map_err(AppError::DatabaseError)?;
+            votes.insert(
+                user_id.clone(),
+                CastVote {
+                    vote,
+                    cast_at: row.get("cast_at"),
+                    updated_at: row.get("vote_updated_at"),
+                },
+            );
+        }
+
+        users.insert(
+            user_id.clone(),
+            User {
+                id: user_id,
+                name: row.get("user_name"),
+                is_observer: is_observer != 0,
+                joined_at: row.get("joined_at"),
+            },
+        );
+    }
+
+    let mut room = Room {
+        id: room_id.clone(),
+        name,
+        state,
+        users,
+        votes,
+        round_id,
+        owner_id,
+        settings,
+        created_at,
+        updated_at,
+        archived_at,
+    };
+    room.sort_members();
+
+    Ok(Some(room))
+}
+
+async fn update_room_state(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    state: &RoomState,
+) -> Result<(), AppError> {
+    let room_id_str = room_id.to_string();
+    let state_str = match state {
+        RoomState::Voting => "voting",
+        RoomState::Revealed => "revealed",
+    };
+
+    sqlx::query("UPDATE rooms SET state = ?, updated_at = ? WHERE id = ?")
+        .bind(state_str)
+        .bind(OffsetDateTime::now_utc())
+        .bind(&room_id_str)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+async fn update_room_settings(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    settings: &RoomSettings,
+) -> Result<(), AppError> {
+    sqlx::query("UPDATE rooms SET session_mode = ?, updated_at = ? WHERE id = ?")
+        .bind(settings.session_mode.as_str())
+        .bind(OffsetDateTime::now_utc())
+        .bind(room_id.to_string())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+async fn update_room_archived_at(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    archived_at: Option<OffsetDateTime>,
+) -> Result<(), AppError> {
+    sqlx::query("UPDATE rooms SET archived_at = ?, updated_at = ? WHERE id = ?")
+        .bind(archived_at)
+        .bind(OffsetDateTime::now_utc())
+        .bind(room_id.to_string())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+// Record a change to the room, its users or votes
+async fn touch_room(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    at: OffsetDateTime,
+) -> Result<(), AppError> {
+    sqlx::query("UPDATE rooms SET updated_at = ? WHERE id = ?")
+        .bind(at)
+        .bind(room_id.to_string())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+// User operations
+async fn add_user(
+    conn: &mut SqliteConnection,
+    user: &User,
+    room_id: &RoomId,
+) -> Result<(), AppError> {
+    let user_id = user.id.to_string();
+    let room_id_str = room_id.to_string();
+    let is_observer = user.is_observer as i64;
+
+    sqlx::query(
+        r#"
+        INSERT INTO users (id, name, is_observer, room_id, joined_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&user_id)
+    .bind(&user.name)
+    .bind(is_observer)
+    .bind(&room_id_str)
+    .bind(user.joined_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    touch_room(conn, room_id, user.joined_at).await?;
+
+    Ok(())
+}
+
+async fn remove_user(
+    conn: &mut SqliteConnection,
+    user_id: &UserId,
+) -> Result<Option<(User, RoomId)>, AppError> {
+    let user_id_str = user_id.to_string();
+
+    // First get user data
+    let row = sqlx::query("SELECT name, is_observer, room_id, joined_at FROM users WHERE id = ?")
+        .bind(&user_id_str)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let name: String = row.get("name");
+    let is_observer: i64 = row.get("is_observer");
+    let room_id_str: String = row.get("room_id");
+    let joined_at: OffsetDateTime = row.get("joined_at");
+
+    // Now delete the user
+    sqlx::query("DELETE FROM users WHERE id = ?")
+        .bind(&user_id_str)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    // Remove their vote too
+    remove_vote(conn, user_id).await?;
+
+    // Create User and RoomId objects
+    let room_id =
+        RoomId::from_string(&room_id_str).map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    touch_room(conn, &room_id, OffsetDateTime::now_utc()).await?;
+
+    let user = User {
+        id: user_id.clone(),
+        name,
+        is_observer: is_observer != 0,
+        joined_at,
+    };
+
+    Ok(Some((user, room_id)))
+}
+
+async fn user_in_room(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    user_id: &UserId,
+) -> Result<bool, AppError> {
+    let row = sqlx::query("SELECT 1 FROM users WHERE id = ? AND room_id = ?")
+        .bind(user_id.to_string())
+        .bind(room_id.to_string())
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(row.is_some())
+}
+
+async fn update_room_owner(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    owner_id: Option<&UserId>,
+) -> Result<(), AppError> {
+    let room_id_str = room_id.to_string();
+    let owner_id_str = owner_id.map(|id| id.to_string());
+
+    sqlx::query("UPDATE rooms SET owner_id = ?, updated_at = ? WHERE id = ?")
+        .bind(owner_id_str)
+        .bind(OffsetDateTime::now_utc())
+        .bind(&room_id_str)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+// Vote operations
+// Store the vote, keeping when the user first voted if they change it
+async fn add_vote(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    user_id: &UserId,
+    vote: &CastVote,
+) -> Result<(), AppError> {
+    // Check the room exists and find its round, without loading all of it
+    let round_id: i64 =
+        sqlx::query("UPDATE rooms SET updated_at = ? WHERE id = ? RETURNING round_id")
+            .bind(vote.updated_at)
+            .bind(room_id.to_string())
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?
+            .ok_or_else(|| {
+                AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string())
+            })?
+            .get("round_id");
+
+    // Now save the vote to the database
+    let room_id_str = room_id.to_string();
+    let user_id_str = user_id.to_string();
+
+    sqlx::query(
+        r#"
+        INSERT INTO votes (user_id, room_id, round_id, card_id, cast_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT(user_id, round_id) DO UPDATE SET
+            card_id = excluded.card_id,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(&user_id_str)
+    .bind(&room_id_str)
+    .bind(round_id)
+    .bind(vote.vote.card_id())
+    .bind(vote.cast_at)
+    .bind(vote.updated_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+async fn remove_vote(conn: &mut SqliteConnection, user_id: &UserId) -> Result<(), AppError> {
+    let user_id_str = user_id.to_string();
+
+    sqlx::query("DELETE FROM votes WHERE user_id = ?")
+        .bind(&user_id_str)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+async fn reset_votes_for_room(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+) -> Result<(), AppError> {
+    let room_id_str = room_id.to_string();
+
+    // Start the next round, keeping the votes of this one
+    sqlx::query("UPDATE rooms SET round_id = round_id + 1 WHERE id = ?")
+        .bind(&room_id_str)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    // Also reset room state to voting
+    update_room_state(conn, room_id, &RoomState::Voting).await?;
+
+    Ok(())
+}
+
+// Chat operations
+// Store a message, keeping only the most recent ones per room
+async fn add_chat_message(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    message: &ChatMessage,
+) -> Result<(), AppError> {
+    let room_id_str = room_id.to_string();
+
+    sqlx::query(
+        r#"
+        INSERT INTO chat_messages (id, room_id, user_id, text, sent_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(message.id.to_string())
+    .bind(&room_id_str)
+    .bind(message.user_id.to_string())
+    .bind(&message.text)
+    .bind(message.sent_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    // Keep only the most recent messages for the room
+    sqlx::query(
+        r#"
+        DELETE FROM chat_messages
+        WHERE room_id = ? AND id NOT IN (
+            SELECT id FROM chat_messages
+            WHERE room_id = ?
+            ORDER BY sent_at DESC, id DESC
+            LIMIT ?
+        )
+        "#,
+    )
+    .bind(&room_id_str)
+    .bind(&room_id_str)
+    .bind(CHAT_HISTORY_LIMIT)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+// Audit operations
+async fn add_audit_event(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    actor_id: Option<&UserId>,
+    action: AuditAction,
+    payload: &Value,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_events (room_id, actor_id, action, payload, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(room_id.to_string())
+    .bind(actor_id.map(|id| id.to_string()))
+    .bind(action.as_str())
+    .bind(Json(payload))
+    .bind(OffsetDateTime::now_utc())
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+// Event log operations
+async fn append_room_event(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    event: &str,
+    vote: Option<&Vote>,
+) -> Result<i64, AppError> {
+    // The room hands out sequence numbers, and updating its row keeps
+    // concurrent appends from taking the same one
+    let seq: i64 = sqlx::query(
+        r#"
+        UPDATE rooms SET last_event_seq = last_event_seq + 1
+        WHERE id = ?
+        RETURNING last_event_seq
+        "#,
+    )
+    .bind(room_id.to_string())
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?
+    .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?
+    .get("last_event_seq");
+
+    sqlx::query(
+        r#"
+        INSERT INTO room_events (room_id, seq, event, card_id, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(room_id.to_string())
+    .bind(seq)
+    .bind(event)
+    .bind(vote.map(Vote::card_id))
+    .bind(OffsetDateTime::now_utc())
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(seq)
+}
+
+async fn get_room_events(
+    conn: &mut SqliteConnection,
+    room_id: &RoomId,
+    after_seq: i64,
+) -> Result<Vec<LoggedEvent>, AppError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT seq, event, card_id, created_at FROM room_events
+        WHERE room_id = ? AND seq > ?
+        ORDER BY seq ASC
+        "#,
+    )
+    .bind(room_id.to_string())
+    .bind(after_seq)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let mut events = Vec::with_capacity(rows.len());
+    for row in rows {
+        let card_id: Option<String> = row.get("card_id");
+
+        events.push(LoggedEvent {
+            seq: row.get("seq"),
+            event: row.get("event"),
+            vote: card_id
+                .map(|card_id| Vote::from_card_id(&card_id))
+                .transpose()
+                .map_err(AppError::DatabaseError)?,
+            created_at: row.get("created_at"),
+        });
+    }
+
+    Ok(events)
+}
+
+// Archive operations
+async fn ids_taken(conn: &mut SqliteConnection, archived: &ArchivedRoom) -> Result<bool, AppError> {
+    let room = &archived.room;
+
+    let ids = std::iter::once(("rooms", room.id.0))
+        .chain(room.users.keys().map(|user_id| ("users", user_id.0)))
+        .chain(
+            archived
+                .chat_messages
+                .iter()
+                .map(|message| ("chat_messages", message.id)),
+        );
+
+    for (table, id) in ids {
+        let taken = sqlx::query(&format!("SELECT 1 FROM {} WHERE id = ?", table))
+            .bind(id.to_string())
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?
+            .is_some();
+
+        if taken {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+async fn restore_room(
+    conn: &mut SqliteConnection,
+    archived: &ArchivedRoom,
+) -> Result<(), AppError> {
+    let room = &archived.room;
+
+    create_room(conn, room).await?;
+
+    for archived_vote in &archived.votes {
+        sqlx::query(
+            r#"
+            INSERT INTO votes (user_id, room_id, round_id, card_id, cast_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(archived_vote.user_id.to_string())
+        .bind(room.id.to_string())
+        .bind(archived_vote.round_id)
+        .bind(archived_vote.vote.vote.card_id())
+        .bind(archived_vote.vote.cast_at)
+        .bind(archived_vote.vote.updated_at)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    }
+
+    for message in &archived.chat_messages {
+        sqlx::query(
+            r#"
+            INSERT INTO chat_messages (id, room_id, user_id, text, sent_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(message.id.to_string())
+        .bind(room.id.to_string())
+        .bind(message.user_id.to_string())
+        .bind(&message.text)
+        .bind(message.sent_at)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    }
+
+    for audit_event in &archived.audit_events {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_events (room_id, actor_id, action, payload, created_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(room.id.to_string())
+        .bind(audit_event.actor_id.as_ref().map(|id| id.to_string()))
+        .bind(audit_event.action.as_str())
+        .bind(Json(&audit_event.payload))
+        .bind(audit_event.created_at)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    }
+
+    for logged in &archived.events {
+        let event = serde_json::to_string(&logged.event)
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO room_events (room_id, seq, event, card_id, created_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(room.id.to_string())
+        .bind(logged.seq)
+        .bind(event)
+        .bind(logged.vote.as_ref().map(Vote::card_id))
+        .bind(logged.created_at)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    }
+
+    // Carry on numbering after the restored events, and keep the room's own
+    // updated_at rather than when its votes were restored
+    let last_seq = archived.events.iter().map(|logged| logged.seq).max();
+    sqlx::query("UPDATE rooms SET last_event_seq = ?, updated_at = ? WHERE id = ?")
+        .bind(last_seq.unwrap_or(0))
+        .bind(room.updated_at)
+        .bind(room.id.to_string())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
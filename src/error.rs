@@ -1,46 +1,293 @@
 use axum::{
     Json,
+    extract::rejection::{JsonRejection, QueryRejection},
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use serde_json::json;
+use serde::Serialize;
+use std::error::Error as StdError;
 use thiserror::Error;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// Stable, machine-readable reason for an error response. Clients should
+// branch on these rather than on the message, which may change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    // Malformed requests
+    ValidationFailed,
+    InvalidBody,
+    InvalidQuery,
+    UnsupportedMediaType,
+    BodyTooLarge,
+    InvalidRoomId,
+    InvalidUserId,
+    InvalidVote,
+    InvalidChatMessage,
+    InvalidReaction,
+    InvalidLimit,
+    InvalidHistoryPoint,
+    UnsupportedProtocolVersion,
+    UnsupportedProtocol,
+    UnsupportedArchiveVersion,
+    InvalidArchive,
+    BackupFileExists,
+    BackupNotSupported,
+
+    // Missing resources
+    RoomNotFound,
+    UserNotFound,
+    AdminApiDisabled,
+
+    // Authentication and permissions
+    InvalidAdminToken,
+    NotRoomOwner,
+    UserNotInRoom,
+
+    // Requests conflicting with the room's state
+    RoomArchived,
+    RoomNotArchived,
+    RoomAlreadyExists,
+    VoteNotAllowedWhenRevealed,
+
+    RateLimited,
+    ServiceUnavailable,
+    InternalError,
+}
+
+// A request field that failed validation
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    // Name of the field as sent by the client, e.g. userId
+    pub field: String,
+    pub error_code: ErrorCode,
+    pub message: String,
+}
 
 #[derive(Error, Debug)]
 pub enum AppError {
-    #[error("Not found: {0}")]
-    NotFound(String),
+    #[error("Not found: {1}")]
+    NotFound(ErrorCode, String),
+
+    #[error("Bad request: {1}")]
+    BadRequest(ErrorCode, String),
+
+    #[error("Invalid request: {}", field_messages(.0))]
+    Validation(Vec<FieldError>),
+
+    // A request the body or query extractors couldn't read, with the status
+    // they chose and the fields at fault when known
+    #[error("Rejected request: {message}")]
+    Rejected {
+        status: StatusCode,
+        error_code: ErrorCode,
+        message: String,
+        details: Vec<FieldError>,
+    },
+
+    #[error("Unauthorized: {1}")]
+    Unauthorized(ErrorCode, String),
+
+    #[error("Forbidden: {1}")]
+    Forbidden(ErrorCode, String),
 
-    #[error("Bad request: {0}")]
-    BadRequest(String),
+    #[error("Conflict: {1}")]
+    Conflict(ErrorCode, String),
 
-    #[error("Forbidden: {0}")]
-    Forbidden(String),
+    #[error("Too many requests: {0}")]
+    RateLimited(String),
+
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
 
     #[error("Server startup error: {0}")]
     ServerStartupError(String),
 
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
     #[error("Database error: {0}")]
     DatabaseError(String),
 }
 
+impl AppError {
+    // A single invalid request field
+    pub fn invalid_field(field: &str, error_code: ErrorCode, message: impl Into<String>) -> Self {
+        AppError::Validation(vec![FieldError {
+            field: field.to_string(),
+            error_code,
+            message: message.into(),
+        }])
+    }
+}
+
+fn field_messages(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|error| error.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// Body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorDetail {
+    pub error_code: ErrorCode,
+    pub message: String,
+    // HTTP status code of the response
+    pub code: u16,
+    // The invalid fields, for VALIDATION_FAILED
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
+    // Identifies the server log entry of an internal error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference_id: Option<Uuid>,
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
-            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
-            AppError::ServerStartupError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            AppError::DatabaseError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-        };
+        let mut details = Vec::new();
+        let mut reference_id = None;
 
-        let body = Json(json!({
-            "error": {
-                "message": error_message,
-                "code": status.as_u16()
+        let (status, error_code, error_message) = match self {
+            AppError::NotFound(code, msg) => (StatusCode::NOT_FOUND, code, msg),
+            AppError::BadRequest(code, msg) => (StatusCode::BAD_REQUEST, code, msg),
+            AppError::Validation(errors) => {
+                let msg = field_messages(&errors);
+                details = errors;
+                (StatusCode::BAD_REQUEST, ErrorCode::ValidationFailed, msg)
             }
-        }));
+            AppError::Rejected {
+                status,
+                error_code,
+                message,
+                details: errors,
+            } => {
+                details = errors;
+                (status, error_code, message)
+            }
+            AppError::Unauthorized(code, msg) => (StatusCode::UNAUTHORIZED, code, msg),
+            AppError::Forbidden(code, msg) => (StatusCode::FORBIDDEN, code, msg),
+            AppError::Conflict(code, msg) => (StatusCode::CONFLICT, code, msg),
+            AppError::RateLimited(msg) => {
+                (StatusCode::TOO_MANY_REQUESTS, ErrorCode::RateLimited, msg)
+            }
+            AppError::ServiceUnavailable(msg) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::ServiceUnavailable,
+                msg,
+            ),
+            // Internal details, like database messages, stay in the server log
+            AppError::ServerStartupError(_)
+            | AppError::ConfigError(_)
+            | AppError::DatabaseError(_) => {
+                let id = Uuid::new_v4();
+                tracing::error!(reference_id = %id, "{}", self);
+                reference_id = Some(id);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorCode::InternalError,
+                    "Internal server error".to_string(),
+                )
+            }
+        };
+
+        let body = Json(ErrorResponse {
+            error: ErrorDetail {
+                error_code,
+                message: error_message,
+                code: status.as_u16(),
+                details,
+                reference_id,
+            },
+        });
 
         (status, body).into_response()
     }
 }
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        let status = rejection.status();
+        let error_code = match &rejection {
+            JsonRejection::MissingJsonContentType(_) => ErrorCode::UnsupportedMediaType,
+            _ if status == StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::BodyTooLarge,
+            _ => ErrorCode::InvalidBody,
+        };
+
+        // Only valid JSON of the wrong shape can be blamed on a field
+        let details = match &rejection {
+            JsonRejection::JsonDataError(_) => {
+                invalid_fields::<serde_json::Error>(&rejection, error_code)
+            }
+            _ => Vec::new(),
+        };
+
+        AppError::Rejected {
+            status,
+            error_code,
+            message: rejection.body_text(),
+            details,
+        }
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        AppError::Rejected {
+            status: rejection.status(),
+            error_code: ErrorCode::InvalidQuery,
+            message: rejection.body_text(),
+            details: invalid_fields::<serde::de::value::Error>(&rejection, ErrorCode::InvalidQuery),
+        }
+    }
+}
+
+// The field a rejected body or query string failed on, named as the client
+// sent it, e.g. `vote.value`. Empty if the error isn't about a single field.
+fn invalid_fields<E>(rejection: &(dyn StdError + 'static), error_code: ErrorCode) -> Vec<FieldError>
+where
+    E: StdError + 'static,
+{
+    let mut source = rejection.source();
+    while let Some(error) = source {
+        if let Some(error) = error.downcast_ref::<serde_path_to_error::Error<E>>() {
+            let message = error.inner().to_string();
+            let mut field = match error.path().to_string() {
+                path if path == "." => String::new(),
+                path => path,
+            };
+
+            // A missing field is reported on the object that lacks it
+            if let Some(name) = message
+                .strip_prefix("missing field `")
+                .and_then(|rest| rest.split('`').next())
+            {
+                if !field.is_empty() {
+                    field.push('.');
+                }
+                field.push_str(name);
+            }
+
+            if field.is_empty() {
+                return Vec::new();
+            }
+            return vec![FieldError {
+                field,
+                error_code,
+                message,
+            }];
+        }
+        source = error.source();
+    }
+
+    Vec::new()
+}
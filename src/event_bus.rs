@@ -0,0 +1,26 @@
+pub mod database;
+pub mod memory;
+
+use crate::events::SequencedEvent;
+use crate::models::room::RoomId;
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+
+pub use database::DatabaseEventBus;
+pub use memory::InMemoryEventBus;
+
+// Fans out room events to every WebSocket subscribed to a room
+#[async_trait]
+pub trait EventBus: Send + Sync {
+    // Publish an event to all subscribers of the room
+    async fn publish(&self, room_id: &RoomId, event: SequencedEvent);
+
+    // Subscribe to events of a room on this server instance
+    fn subscribe(&self, room_id: &RoomId) -> broadcast::Receiver<SequencedEvent>;
+
+    // Drop the channel of a room that no longer exists
+    fn remove_room(&self, room_id: &RoomId);
+
+    // Number of rooms with a channel on this server instance
+    fn room_count(&self) -> usize;
+}
@@ -0,0 +1,256 @@
+use crate::db::EventOutbox;
+use crate::error::AppError;
+use crate::event_bus::{EventBus, InMemoryEventBus};
+use crate::events::{RoomEvent, SequencedEvent};
+use crate::metrics::Metrics;
+use crate::models::room::RoomId;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+// Maximum number of outbox rows delivered per poll
+const POLL_BATCH_SIZE: i64 = 500;
+
+// How long published events stay in the outbox before being pruned
+const OUTBOX_RETENTION: Duration = Duration::from_secs(300);
+
+// How long a missing outbox id is waited for before it's taken as a rolled
+// back insert. Inserts commit on their own, so this is far longer than one
+// should take.
+const OUTBOX_GAP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How far the bus has read the outbox. Ids are handed out when a row is
+// inserted but the row only shows up once it commits, so a row can appear
+// after rows with higher ids. Reading on from the highest id seen would skip
+// it, so the cursor stays below any missing id until it turns up or times out.
+struct OutboxCursor {
+    // Every id up to this one has been delivered or given up on
+    floor: i64,
+    // Delivered ids above the floor
+    delivered: BTreeSet<i64>,
+    // Missing ids above the floor, with when they were first missed
+    gaps: BTreeMap<i64, Instant>,
+}
+
+impl OutboxCursor {
+    fn new(floor: i64) -> Self {
+        Self {
+            floor,
+            delivered: BTreeSet::new(),
+            gaps: BTreeMap::new(),
+        }
+    }
+
+    // Whether the row still has to be delivered, marking it as delivered
+    fn deliver(&mut self, id: i64) -> bool {
+        id > self.floor && self.delivered.insert(id)
+    }
+
+    // Note the ids missing below the highest one delivered and move the floor
+    // up past the delivered ones and the gaps that timed out
+    fn advance(&mut self, now: Instant) {
+        let Some(&highest) = self.delivered.last() else {
+            return;
+        };
+        for id in self.floor + 1..highest {
+            if !self.delivered.contains(&id) {
+                self.gaps.entry(id).or_insert(now);
+            }
+        }
+
+        loop {
+            let next = self.floor + 1;
+            if self.delivered.remove(&next) {
+                self.gaps.remove(&next);
+                self.floor = next;
+            } else if let Some(&missed_at) = self.gaps.get(&next)
+                && now.duration_since(missed_at) >= OUTBOX_GAP_TIMEOUT
+            {
+                self.gaps.remove(&next);
+                self.floor = next;
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+// Called with each event published by another instance, before it's delivered
+type RemoteEventHandler = Box<dyn Fn(&RoomId, &RoomEvent) + Send + Sync>;
+
+// Event bus shared by several server instances through the database. Events are
+// delivered to local subscribers straight away and appended to an outbox table,
+// which every instance polls to pick up events published by the others.
+pub struct DatabaseEventBus {
+    node_id: Uuid,
+    outbox: Arc<dyn EventOutbox>,
+    local: InMemoryEventBus,
+    // Called before an event from another instance is delivered, as that
+    // instance has probably changed the room
+    on_remote_event: RemoteEventHandler,
+}
+
+impl DatabaseEventBus {
+    pub async fn start(
+        outbox: Arc<dyn EventOutbox>,
+        poll_interval: Duration,
+        metrics: Arc<Metrics>,
+        on_remote_event: impl Fn(&RoomId, &RoomEvent) + Send + Sync + 'static,
+    ) -> Result<Arc<Self>, AppError> {
+        // Only events published after startup are of interest
+        let last_id = outbox.latest_outbox_event_id().await?;
+
+        let bus = Arc::new(Self {
+            node_id: Uuid::new_v4(),
+            outbox,
+            local: InMemoryEventBus::new(metrics),
+            on_remote_event: Box::new(on_remote_event),
+        });
+
+        tracing::info!("Database event bus started as node {}", bus.node_id);
+        tokio::spawn(Self::poll(
+            Arc::downgrade(&bus),
+            OutboxCursor::new(last_id),
+            poll_interval,
+        ));
+
+        Ok(bus)
+    }
+
+    // Deliver events published by other instances until the bus is dropped
+    async fn poll(bus: Weak<Self>, mut cursor: OutboxCursor, poll_interval: Duration) {
+        let mut ticker = tokio::time::interval(poll_interval);
+        let mut last_prune = tokio::time::Instant::now();
+
+        loop {
+            ticker.tick().await;
+
+            let Some(bus) = bus.upgrade() else {
+                break;
+            };
+
+            match bus
+                .outbox
+                .get_outbox_events_after(cursor.floor, POLL_BATCH_SIZE)
+                .await
+            {
+                Ok(events) => {
+                    for outbox_event in events {
+                        if !cursor.deliver(outbox_event.id) {
+                            continue;
+                        }
+
+                        // Our own events were already delivered locally
+                        if outbox_event.node_id == bus.node_id {
+                            continue;
+                        }
+
+                        match serde_json::from_str::<SequencedEvent>(&outbox_event.event) {
+                            Ok(event) => {
+                                (bus.on_remote_event)(&outbox_event.room_id, &event.event);
+
+                                let closed = matches!(event.event, RoomEvent::RoomClosed(_));
+                                bus.local.send(&outbox_event.room_id, event);
+                                if closed {
+                                    bus.local.remove_room(&outbox_event.room_id);
+                                }
+                            }
+                            Err(e) => {
+                                tracing::warn!("Skipping undecodable outbox event: {}", e)
+                            }
+                        }
+                    }
+                    cursor.advance(Instant::now());
+                }
+                Err(e) => tracing::warn!("Failed to poll event outbox: {}", e),
+            }
+
+            // Any instance may prune, old rows have been seen by everyone
+            if last_prune.elapsed() >= OUTBOX_RETENTION {
+                last_prune = tokio::time::Instant::now();
+                let cutoff = OffsetDateTime::now_utc() - OUTBOX_RETENTION;
+                if let Err(e) = bus.outbox.prune_outbox_events(cutoff).await {
+                    tracing::warn!("Failed to prune event outbox: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EventBus for DatabaseEventBus {
+    async fn publish(&self, room_id: &RoomId, event: SequencedEvent) {
+        let serialized_event = match serde_json::to_string(&event) {
+            Ok(serialized_event) => serialized_event,
+            Err(e) => {
+                tracing::warn!("Failed to serialize room event: {}", e);
+                return;
+            }
+        };
+
+        self.local.send(room_id, event);
+
+        if let Err(e) = self
+            .outbox
+            .add_outbox_event(&self.node_id, room_id, &serialized_event)
+            .await
+        {
+            tracing::warn!("Failed to publish room event to outbox: {}", e);
+        }
+    }
+
+    fn subscribe(&self, room_id: &RoomId) -> broadcast::Receiver<SequencedEvent> {
+        self.local.subscribe(room_id)
+    }
+
+    fn remove_room(&self, room_id: &RoomId) {
+        self.local.remove_room(room_id);
+    }
+
+    fn room_count(&self) -> usize {
+        self.local.room_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OUTBOX_GAP_TIMEOUT, OutboxCursor};
+    use tokio::time::Instant;
+
+    #[test]
+    fn outbox_cursor_waits_for_rows_committed_out_of_order() {
+        let start = Instant::now();
+        let mut cursor = OutboxCursor::new(10);
+
+        // 12 commits before 11
+        assert!(cursor.deliver(12));
+        cursor.advance(start);
+        assert_eq!(cursor.floor, 10);
+
+        // The next poll reads from 10 again, finding 11 and 12
+        assert!(cursor.deliver(11));
+        assert!(!cursor.deliver(12));
+        cursor.advance(start);
+        assert_eq!(cursor.floor, 12);
+        assert!(cursor.gaps.is_empty());
+    }
+
+    #[test]
+    fn outbox_cursor_gives_up_on_rolled_back_rows() {
+        let start = Instant::now();
+        let mut cursor = OutboxCursor::new(0);
+
+        assert!(cursor.deliver(2));
+        cursor.advance(start);
+        assert_eq!(cursor.floor, 0);
+
+        cursor.advance(start + OUTBOX_GAP_TIMEOUT);
+        assert_eq!(cursor.floor, 2);
+        assert!(!cursor.deliver(1));
+    }
+}
@@ -0,0 +1,58 @@
+use crate::event_bus::EventBus;
+use crate::events::SequencedEvent;
+use crate::metrics::Metrics;
+use crate::models::room::RoomId;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+// Capacity of each room's broadcast channel
+const ROOM_CHANNEL_CAPACITY: usize = 100;
+
+// Broadcast channels for real-time updates - one per room, local to this process
+pub struct InMemoryEventBus {
+    rooms: DashMap<RoomId, broadcast::Sender<SequencedEvent>>,
+    metrics: Arc<Metrics>,
+}
+
+impl InMemoryEventBus {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            rooms: DashMap::new(),
+            metrics,
+        }
+    }
+
+    // Deliver an event to this process' subscribers only
+    pub fn send(&self, room_id: &RoomId, event: SequencedEvent) {
+        // Sending fails once every subscriber of the room has gone
+        if let Some(sender) = self.rooms.get(room_id)
+            && sender.send(event).is_err()
+        {
+            self.metrics.broadcast_send_failures.inc();
+        }
+    }
+}
+
+#[async_trait]
+impl EventBus for InMemoryEventBus {
+    async fn publish(&self, room_id: &RoomId, event: SequencedEvent) {
+        self.send(room_id, event);
+    }
+
+    fn subscribe(&self, room_id: &RoomId) -> broadcast::Receiver<SequencedEvent> {
+        self.rooms
+            .entry(room_id.clone())
+            .or_insert_with(|| broadcast::channel(ROOM_CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    fn remove_room(&self, room_id: &RoomId) {
+        self.rooms.remove(room_id);
+    }
+
+    fn room_count(&self) -> usize {
+        self.rooms.len()
+    }
+}
@@ -0,0 +1,253 @@
+use crate::models::chat::ChatMessage;
+use crate::models::room::{Room, RoomSettings, RoomState};
+use crate::models::status::{ActiveStatus, UserStatus};
+use crate::models::user::User;
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+// Current version of the WebSocket event protocol. Bump this whenever an event
+// or payload changes in a way existing clients can't handle.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// Versions this server can still speak, oldest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[u32] = &[1];
+
+// WebSocket subprotocol name for a protocol version, e.g. "pointing-poker.v1"
+pub fn subprotocol_name(version: u32) -> String {
+    format!("pointing-poker.v{}", version)
+}
+
+// Events sent from the server to WebSocket clients
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "eventType", content = "payload")]
+pub enum RoomEvent {
+    Welcome(WelcomePayload),
+    UserJoined(UserJoinedPayload),
+    UserLeft(UserLeftPayload),
+    VoteSubmitted(VoteSubmittedPayload),
+    VotesRevealed(VotesRevealedPayload),
+    VotesReset(VotesResetPayload),
+    ReactionSent(ReactionSentPayload),
+    ChatMessageSent(ChatMessageSentPayload),
+    RoomUpdated(RoomUpdatedPayload),
+    RoomClosed(RoomClosedPayload),
+    PresenceChanged(PresenceChangedPayload),
+    UserStatusChanged(UserStatusChangedPayload),
+    ServerShuttingDown(ServerShuttingDownPayload),
+}
+
+impl RoomEvent {
+    // Whether the event changes the room and belongs in its event log.
+    // Ephemeral signals are only broadcast, and RoomClosed only disconnects
+    // clients: archiving is logged as RoomUpdated, and purging a room deletes
+    // its log.
+    pub fn is_logged(&self) -> bool {
+        match self {
+            RoomEvent::UserJoined(_)
+            | RoomEvent::UserLeft(_)
+            | RoomEvent::VoteSubmitted(_)
+            | RoomEvent::VotesRevealed(_)
+            | RoomEvent::VotesReset(_)
+            | RoomEvent::ChatMessageSent(_)
+            | RoomEvent::RoomUpdated(_) => true,
+            RoomEvent::Welcome(_)
+            | RoomEvent::ReactionSent(_)
+            | RoomEvent::RoomClosed(_)
+            | RoomEvent::PresenceChanged(_)
+            | RoomEvent::UserStatusChanged(_)
+            | RoomEvent::ServerShuttingDown(_) => false,
+        }
+    }
+}
+
+// A room event as delivered to clients, with its sequence number in the room's
+// event log if it was logged
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SequencedEvent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<i64>,
+    #[serde(flatten)]
+    pub event: RoomEvent,
+}
+
+impl From<RoomEvent> for SequencedEvent {
+    fn from(event: RoomEvent) -> Self {
+        Self { seq: None, event }
+    }
+}
+
+// Sent once to each socket after connecting, never broadcast
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WelcomePayload {
+    pub protocol_version: u32,
+    pub room_id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserJoinedPayload {
+    #[serde(flatten)]
+    pub user: User,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserLeftPayload {
+    pub user_id: uuid::Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VoteSubmittedPayload {
+    pub user_id: uuid::Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VotesRevealedPayload {
+    pub votes: Vec<VoteWithUser>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VoteWithUser {
+    pub user_id: uuid::Uuid,
+    pub value: String,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schemars(with = "String")]
+    pub cast_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schemars(with = "String")]
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VotesResetPayload {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionSentPayload {
+    pub user_id: uuid::Uuid,
+    pub emoji: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessageSentPayload {
+    #[serde(flatten)]
+    pub message: ChatMessage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomUpdatedPayload {
+    pub room_id: uuid::Uuid,
+    pub name: String,
+    pub owner_id: Option<uuid::Uuid>,
+    pub state: RoomState,
+    pub settings: RoomSettings,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    #[schemars(with = "Option<String>")]
+    pub archived_at: Option<OffsetDateTime>,
+}
+
+impl RoomUpdatedPayload {
+    pub fn from_room(room: &Room) -> Self {
+        Self {
+            room_id: room.id.0,
+            name: room.name.clone(),
+            owner_id: room.owner_id.as_ref().map(|id| id.0),
+            state: room.state.clone(),
+            settings: room.settings.clone(),
+            archived_at: room.archived_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomClosedPayload {
+    pub room_id: uuid::Uuid,
+}
+
+// A user came online on their first connection or went offline with their last one
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PresenceChangedPayload {
+    pub user_id: uuid::Uuid,
+    pub online: bool,
+}
+
+// A user's status signal was set, refreshed, cleared or expired (status is null)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserStatusChangedPayload {
+    pub user_id: uuid::Uuid,
+    pub status: Option<ActiveStatus>,
+}
+
+// Sent to each socket right before the server closes it for a restart, never
+// broadcast. Clients should reconnect after the delay, passing `since` to
+// catch up on what they missed.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerShuttingDownPayload {
+    pub reconnect_after_ms: u64,
+}
+
+// Events clients can send to the server over the WebSocket
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "eventType", content = "payload")]
+pub enum ClientEvent {
+    SendReaction(SendReactionPayload),
+    SendChatMessage(SendChatMessagePayload),
+    SetStatus(SetStatusPayload),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SendReactionPayload {
+    pub emoji: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SendChatMessagePayload {
+    pub text: String,
+}
+
+// Set to null to clear the current status
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetStatusPayload {
+    pub status: Option<UserStatus>,
+}
+
+// JSON Schema describing both directions of the event protocol
+pub fn event_schema() -> serde_json::Value {
+    let mut generator = SchemaGenerator::default();
+    let room_event = generator.subschema_for::<SequencedEvent>();
+    let client_event = generator.subschema_for::<ClientEvent>();
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "PointingPokerEvents",
+        "description": format!("WebSocket event protocol version {}", PROTOCOL_VERSION),
+        "protocolVersion": PROTOCOL_VERSION,
+        "type": "object",
+        "properties": {
+            "roomEvent": room_event,
+            "clientEvent": client_event,
+        },
+        "$defs": generator.take_definitions(true),
+    })
+}
@@ -0,0 +1,38 @@
+use crate::config::LogFormat;
+use axum::{extract::Request, http::HeaderName};
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+// Header carrying the ID that correlates a request's log lines
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// Install the global subscriber writing to stdout, filtered by RUST_LOG
+pub fn init(format: LogFormat) {
+    let subscriber = tracing_subscriber::fmt().with_env_filter(EnvFilter::from_default_env());
+
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+    }
+}
+
+// Span of an HTTP request, which every log line written while handling it is
+// nested in. The request ID has already been set, from the caller or fresh.
+pub fn request_span(request: &Request) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id,
+    )
+}
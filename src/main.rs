@@ -1,29 +1,58 @@
+mod archive;
+mod config;
+mod connections;
 mod db;
 mod error;
+mod event_bus;
+mod events;
+mod extract;
+mod logging;
+mod metrics;
 mod models;
+mod openapi;
+mod projection;
+mod rate_limit;
+mod retention;
 mod routes;
+mod shutdown;
 mod state;
+mod statuses;
 
+use crate::config::Config;
 use crate::error::AppError;
 use crate::routes::create_router;
 use crate::state::AppState;
 
+use std::future::IntoFuture;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tower_http::cors::{Any, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
 use tracing::info;
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
+    // Load configuration from the environment
+    let config = Config::from_env()?;
+
     // Initialize tracing
-    tracing_subscriber::fmt::init();
+    logging::init(config.log_format);
 
     // Create application state with database connection
-    let app_state = Arc::new(AppState::new().await?);
+    let app_state = Arc::new(AppState::new(&config).await?);
 
     info!("Database connection established");
 
+    // Expire status signals in the background
+    tokio::spawn(statuses::sweep_expired(app_state.clone()));
+
+    // Delete archived rooms once their retention period is over
+    tokio::spawn(retention::purge_archived_rooms(
+        app_state.clone(),
+        config.archived_room_retention,
+    ));
+
     // Configure CORS
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -31,12 +60,18 @@ async fn main() -> Result<(), AppError> {
         .allow_headers(Any);
 
     // Build application with routes
-    let app = create_router(app_state)
-        .layer(TraceLayer::new_for_http())
+    let app = create_router(app_state.clone())
+        .layer(TraceLayer::new_for_http().make_span_with(logging::request_span))
+        // Give every request an X-Request-Id, keeping the caller's, and echo it back
+        .layer(PropagateRequestIdLayer::new(logging::REQUEST_ID_HEADER))
+        .layer(SetRequestIdLayer::new(
+            logging::REQUEST_ID_HEADER,
+            MakeRequestUuid,
+        ))
         .layer(cors);
 
     // Define the address to run the server on
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
     info!("Starting server on {}", addr);
 
     // Start the server - updated for Axum 0.8 with Hyper
@@ -44,12 +79,21 @@ async fn main() -> Result<(), AppError> {
         .await
         .map_err(|e| AppError::ServerStartupError(e.to_string()))?;
 
-    axum::serve(
+    // Stop on SIGINT or SIGTERM, letting in-flight requests finish first
+    let server = axum::serve(
         listener,
         app.into_make_service_with_connect_info::<SocketAddr>(),
     )
-    .await
-    .map_err(|e| AppError::ServerStartupError(e.to_string()))?;
+    .with_graceful_shutdown(shutdown::signal(
+        app_state.clone(),
+        config.shutdown_readiness_delay,
+    ));
 
-    Ok(())
+    shutdown::serve_until_drained(
+        server.into_future(),
+        &app_state,
+        config.shutdown_readiness_delay,
+        config.shutdown_drain_period,
+    )
+    .await
 }
@@ -0,0 +1,170 @@
+use crate::error::AppError;
+use crate::state::AppState;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder, core::Collector,
+};
+use std::sync::Arc;
+
+// Prefix of every metric name
+const NAMESPACE: &str = "pointing_poker";
+
+// Prometheus metrics of this server instance, served at /metrics
+pub struct Metrics {
+    registry: Registry,
+
+    // HTTP requests by method, route and status, and their latency
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+
+    // Open WebSocket connections
+    pub websocket_connections: IntGauge,
+
+    // Rooms with an event channel on this instance, updated when scraped
+    pub active_rooms: IntGauge,
+
+    // Events that couldn't be broadcast and events skipped by lagging
+    // WebSocket connections
+    pub broadcast_send_failures: IntCounter,
+    pub broadcast_lagged_events: IntCounter,
+
+    // Latency of the storage backend per Database and Transaction method
+    pub db_query_duration: HistogramVec,
+
+    // Votes cast and reveals in all rooms
+    pub votes: IntCounter,
+    pub reveals: IntCounter,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, AppError> {
+        let registry = Registry::new();
+
+        Ok(Self {
+            http_requests: register(
+                &registry,
+                IntCounterVec::new(
+                    opts("http_requests_total", "HTTP requests handled"),
+                    &["method", "route", "status"],
+                ),
+            )?,
+            http_request_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::from(opts(
+                        "http_request_duration_seconds",
+                        "Time spent handling HTTP requests",
+                    )),
+                    &["method", "route"],
+                ),
+            )?,
+            websocket_connections: register(
+                &registry,
+                IntGauge::with_opts(opts("websocket_connections", "Open WebSocket connections")),
+            )?,
+            active_rooms: register(
+                &registry,
+                IntGauge::with_opts(opts(
+                    "active_rooms",
+                    "Rooms with an event channel on this instance",
+                )),
+            )?,
+            broadcast_send_failures: register(
+                &registry,
+                IntCounter::with_opts(opts(
+                    "broadcast_send_failures_total",
+                    "Room events that had no subscriber to receive them",
+                )),
+            )?,
+            broadcast_lagged_events: register(
+                &registry,
+                IntCounter::with_opts(opts(
+                    "broadcast_lagged_events_total",
+                    "Room events skipped by WebSocket connections that fell behind",
+                )),
+            )?,
+            db_query_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::from(opts(
+                        "db_query_duration_seconds",
+                        "Time spent in storage backend calls",
+                    )),
+                    &["method"],
+                ),
+            )?,
+            votes: register(
+                &registry,
+                IntCounter::with_opts(opts("votes_total", "Votes cast")),
+            )?,
+            reveals: register(
+                &registry,
+                IntCounter::with_opts(opts("reveals_total", "Votes revealed")),
+            )?,
+            registry,
+        })
+    }
+
+    // All metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::warn!("Failed to encode metrics: {}", e);
+        }
+
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+fn opts(name: &str, help: &str) -> Opts {
+    Opts::new(name, help).namespace(NAMESPACE)
+}
+
+// Add a newly created metric to the registry
+fn register<C: Collector + Clone + 'static>(
+    registry: &Registry,
+    collector: prometheus::Result<C>,
+) -> Result<C, AppError> {
+    let collector = collector.map_err(|e| AppError::ServerStartupError(e.to_string()))?;
+    registry
+        .register(Box::new(collector.clone()))
+        .map_err(|e| AppError::ServerStartupError(e.to_string()))?;
+
+    Ok(collector)
+}
+
+// Count and time every request by its route pattern, e.g. /rooms/{room_id},
+// so room IDs don't end up in the labels
+pub async fn track_http_requests(
+    State(state): State<Arc<AppState>>,
+    matched_path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = matched_path
+        .as_ref()
+        .map_or("unmatched", |path| path.as_str())
+        .to_string();
+
+    let timer = state
+        .metrics
+        .http_request_duration
+        .with_label_values(&[&method, &route])
+        .start_timer();
+    let response = next.run(request).await;
+    timer.observe_duration();
+
+    state
+        .metrics
+        .http_requests
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+
+    response
+}
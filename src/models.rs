@@ -1,3 +1,8 @@
+pub mod archive;
+pub mod audit;
+pub mod chat;
+pub mod history;
 pub mod room;
+pub mod status;
 pub mod user;
 pub mod vote;
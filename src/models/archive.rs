@@ -0,0 +1,119 @@
+use crate::events::RoomEvent;
+use crate::models::audit::AuditEvent;
+use crate::models::chat::ChatMessage;
+use crate::models::room::{Room, RoomId};
+use crate::models::user::UserId;
+use crate::models::vote::{CastVote, Vote};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
+
+// Version of the archive format, bumped whenever the shape changes. Only
+// archives of this version can be imported, so every field they hold can be
+// required rather than guessed.
+pub const ARCHIVE_VERSION: u32 = 3;
+
+// Exported rooms, for moving them between environments or keeping them
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Archive {
+    pub version: u32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub exported_at: OffsetDateTime,
+    pub rooms: Vec<ArchivedRoom>,
+}
+
+// A room with its users and votes, and everything that happened in it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedRoom {
+    pub room: Room,
+    // Votes of every round, the current one included, oldest round first
+    pub votes: Vec<ArchivedVote>,
+    // Oldest first
+    pub chat_messages: Vec<ChatMessage>,
+    // Oldest first, the ids are reassigned on import
+    pub audit_events: Vec<AuditEvent>,
+    // The room's event log, in sequence
+    pub events: Vec<ArchivedEvent>,
+}
+
+// A vote cast in one of the room's rounds
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedVote {
+    pub user_id: UserId,
+    pub round_id: i64,
+    #[serde(flatten)]
+    pub vote: CastVote,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedEvent {
+    pub seq: i64,
+    // One of the room events described by /ws/schema
+    #[schema(value_type = Object)]
+    pub event: RoomEvent,
+    // Value behind a VoteSubmitted event
+    pub vote: Option<Vote>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+// What to do with an archived room whose IDs are already taken
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictPolicy {
+    // Abort the whole import
+    #[default]
+    Fail,
+    // Leave the existing room alone and import the others
+    Skip,
+    // Import the room under fresh IDs
+    Remap,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportQuery {
+    pub on_conflict: Option<ConflictPolicy>,
+    // Give every imported room fresh IDs, e.g. to copy rooms within one environment
+    pub remap_ids: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportOutcome {
+    Imported,
+    Remapped,
+    Skipped,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedRoom {
+    pub original_id: RoomId,
+    // Where the room ended up, unless it was skipped
+    pub room_id: Option<RoomId>,
+    pub outcome: ImportOutcome,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub rooms: Vec<ImportedRoom>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupRequest {
+    // File to write the backup to, which must not exist yet
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupResponse {
+    pub path: String,
+}
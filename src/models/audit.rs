@@ -0,0 +1,122 @@
+use crate::models::user::UserId;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
+
+// Default and maximum number of audit events returned per page
+pub const AUDIT_PAGE_SIZE: i64 = 50;
+pub const MAX_AUDIT_PAGE_SIZE: i64 = 200;
+
+// Changes to a room that are recorded in its audit log
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditAction {
+    RoomCreated,
+    UserJoined,
+    UserLeft,
+    VoteSubmitted,
+    VotesRevealed,
+    VotesReset,
+    OwnerChanged,
+    SettingsUpdated,
+    RoomArchived,
+    RoomReopened,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::RoomCreated => "room_created",
+            AuditAction::UserJoined => "user_joined",
+            AuditAction::UserLeft => "user_left",
+            AuditAction::VoteSubmitted => "vote_submitted",
+            AuditAction::VotesRevealed => "votes_revealed",
+            AuditAction::VotesReset => "votes_reset",
+            AuditAction::OwnerChanged => "owner_changed",
+            AuditAction::SettingsUpdated => "settings_updated",
+            AuditAction::RoomArchived => "room_archived",
+            AuditAction::RoomReopened => "room_reopened",
+        }
+    }
+
+    pub fn from_string(s: &str) -> Result<Self, String> {
+        match s {
+            "room_created" => Ok(AuditAction::RoomCreated),
+            "user_joined" => Ok(AuditAction::UserJoined),
+            "user_left" => Ok(AuditAction::UserLeft),
+            "vote_submitted" => Ok(AuditAction::VoteSubmitted),
+            "votes_revealed" => Ok(AuditAction::VotesRevealed),
+            "votes_reset" => Ok(AuditAction::VotesReset),
+            "owner_changed" => Ok(AuditAction::OwnerChanged),
+            "settings_updated" => Ok(AuditAction::SettingsUpdated),
+            "room_archived" => Ok(AuditAction::RoomArchived),
+            "room_reopened" => Ok(AuditAction::RoomReopened),
+            _ => Err(format!("Invalid audit action: {}", s)),
+        }
+    }
+}
+
+// One recorded change. Ids increase with every event, so they double as the
+// pagination cursor.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    pub id: i64,
+    // Who made the change, if anyone did
+    pub actor_id: Option<UserId>,
+    pub action: AuditAction,
+    // Action-specific details
+    pub payload: Value,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+// Which audit events of a room to load, newest first
+#[derive(Debug, Clone)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    pub actor_id: Option<UserId>,
+    pub since: Option<OffsetDateTime>,
+    pub until: Option<OffsetDateTime>,
+    // Only events older than this id, to fetch the next page
+    pub before: Option<i64>,
+    pub limit: i64,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.action.is_none_or(|action| event.action == action)
+            && self
+                .actor_id
+                .as_ref()
+                .is_none_or(|actor_id| event.actor_id.as_ref() == Some(actor_id))
+            && self.since.is_none_or(|since| event.created_at >= since)
+            && self.until.is_none_or(|until| event.created_at < until)
+            && self.before.is_none_or(|before| event.id < before)
+    }
+}
+
+// Query parameters of the audit log endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditQuery {
+    // The requesting user, who must own the room
+    pub user_id: String,
+    pub action: Option<AuditAction>,
+    pub actor_id: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub since: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub until: Option<OffsetDateTime>,
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+// A page of audit events, with the cursor for the next one if there is more
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditPage {
+    pub events: Vec<AuditEvent>,
+    pub next_before: Option<i64>,
+}
@@ -0,0 +1,82 @@
+use crate::models::user::UserId;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// Number of chat messages kept per room, older ones are pruned on insert
+pub const CHAT_HISTORY_LIMIT: i64 = 200;
+
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 500;
+pub const MAX_REACTION_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub text: String,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schemars(with = "String")]
+    pub sent_at: OffsetDateTime,
+}
+
+impl ChatMessage {
+    pub fn new(user_id: UserId, text: String) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            text,
+            sent_at: OffsetDateTime::now_utc(),
+        }
+    }
+}
+
+// Trims a chat message and checks it is non-empty and within the length limit
+pub fn validate_chat_text(text: &str) -> Result<String, String> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return Err("Chat message cannot be empty".to_string());
+    }
+
+    if text.chars().count() > MAX_CHAT_MESSAGE_LENGTH {
+        return Err(format!(
+            "Chat message cannot be longer than {} characters",
+            MAX_CHAT_MESSAGE_LENGTH
+        ));
+    }
+
+    Ok(text.to_string())
+}
+
+// Reactions are short emoji sequences, so reject anything resembling free text
+pub fn validate_reaction(emoji: &str) -> Result<String, String> {
+    let emoji = emoji.trim();
+
+    if emoji.is_empty()
+        || emoji.chars().count() > MAX_REACTION_LENGTH
+        || emoji
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c.is_ascii_alphanumeric())
+    {
+        return Err(format!("Invalid reaction: {}", emoji));
+    }
+
+    Ok(emoji.to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SendChatMessageRequest {
+    pub user_id: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SendReactionRequest {
+    pub user_id: String,
+    pub emoji: String,
+}
@@ -0,0 +1,33 @@
+use crate::models::room::Room;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
+
+// Point in a room's event log to rebuild it at, the latest if neither is given
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryQuery {
+    // Last event to apply
+    pub seq: Option<i64>,
+    // Apply the events logged up to this time
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub at: Option<OffsetDateTime>,
+}
+
+// A room as rebuilt from its event log
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomHistory {
+    // Sequence number of the last event applied
+    pub seq: i64,
+    pub room: Room,
+}
+
+// Result of comparing the rebuilt room with the stored one
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyReport {
+    pub seq: i64,
+    pub consistent: bool,
+    pub differences: Vec<String>,
+}
@@ -1,10 +1,15 @@
-use crate::models::user::{CreateUserRequest, User, UserId};
-use crate::models::vote::Vote;
+use crate::models::status::ActiveStatus;
+use crate::models::user::{User, UserId, sort_users};
+use crate::models::vote::CastVote;
+use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
 pub struct RoomId(pub Uuid);
 
 impl RoomId {
@@ -29,27 +34,81 @@ impl Default for RoomId {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub enum RoomState {
     Voting,
     Revealed,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// What happens when a user opens a second WebSocket connection to the room
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, JsonSchema, ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionMode {
+    // Every connection receives events, e.g. a laptop and a phone
+    #[default]
+    MultiDevice,
+    // A new connection closes the user's older ones
+    SingleSession,
+}
+
+impl SessionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionMode::MultiDevice => "multi_device",
+            SessionMode::SingleSession => "single_session",
+        }
+    }
+
+    pub fn from_string(s: &str) -> Result<Self, String> {
+        match s {
+            "multi_device" => Ok(SessionMode::MultiDevice),
+            "single_session" => Ok(SessionMode::SingleSession),
+            _ => Err(format!("Invalid session mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSettings {
+    pub session_mode: SessionMode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Room {
     pub id: RoomId,
     pub name: String,
     pub state: RoomState,
-    pub users: HashMap<UserId, User>,
-    pub votes: HashMap<UserId, Vote>,
+    // In the order the users joined
+    pub users: IndexMap<UserId, User>,
+    // Votes of the current round, in the order they were cast
+    pub votes: IndexMap<UserId, CastVote>,
+    // Voting round, starting at 1 and moving on with every reset
+    pub round_id: i64,
     pub owner_id: Option<UserId>,
+    pub settings: RoomSettings,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    // Last change to the room, its users or votes
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+    // When the room was archived, it is read-only until reopened
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub archived_at: Option<OffsetDateTime>,
 }
 
+// Round a new room starts voting in
+pub const FIRST_ROUND: i64 = 1;
+
 impl Room {
-    pub fn new(name: String, owner: Option<User>) -> Self {
+    pub fn new(name: String, owner: Option<User>, settings: RoomSettings) -> Self {
+        let now = OffsetDateTime::now_utc();
         let owner_id = owner.as_ref().map(|o| o.id.clone());
-        let mut users = HashMap::new();
+        let mut users = IndexMap::new();
 
         if let Some(owner) = owner {
             users.insert(owner.id.clone(), owner);
@@ -60,21 +119,63 @@ impl Room {
             name,
             state: RoomState::Voting,
             users,
-            votes: HashMap::new(),
+            votes: IndexMap::new(),
+            round_id: FIRST_ROUND,
             owner_id,
+            settings,
+            created_at: now,
+            updated_at: now,
+            archived_at: None,
         }
     }
+
+    pub fn is_archived(&self) -> bool {
+        self.archived_at.is_some()
+    }
+
+    // Put users in the order they joined and votes in the order they were
+    // cast, with ties broken by user ID, so a room always lists them the same way
+    pub fn sort_members(&mut self) {
+        sort_users(&mut self.users);
+        self.votes
+            .sort_by(|a_id, a, b_id, b| (a.cast_at, a_id.0).cmp(&(b.cast_at, b_id.0)));
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateRoomRequest {
     pub name: String,
     pub creator_name: Option<String>,
+    pub settings: Option<RoomSettings>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateRoomSettingsRequest {
+    pub user_id: String,
+    pub session_mode: Option<SessionMode>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReopenRoomRequest {
+    pub user_id: String,
+}
+
+// A room together with who is currently connected to it and their status signals
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSnapshot {
+    #[serde(flatten)]
+    pub room: Room,
+    pub online_user_ids: Vec<UserId>,
+    pub user_statuses: HashMap<UserId, ActiveStatus>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct JoinRoomRequest {
-    pub user: CreateUserRequest,
+    pub name: String,
+    pub is_observer: Option<bool>,
 }
@@ -0,0 +1,34 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+// Ephemeral signals a user can show to the room, never persisted
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum UserStatus {
+    Thinking,
+    HandRaised,
+    Away,
+}
+
+impl UserStatus {
+    // How long a status lasts unless it is refreshed or cleared
+    pub fn ttl(&self) -> Duration {
+        match self {
+            UserStatus::Thinking => Duration::from_secs(60),
+            UserStatus::HandRaised => Duration::from_secs(10 * 60),
+            UserStatus::Away => Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveStatus {
+    pub status: UserStatus,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schemars(with = "String")]
+    pub expires_at: OffsetDateTime,
+}
@@ -1,7 +1,11 @@
+use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, JsonSchema, ToSchema)]
 pub struct UserId(pub Uuid);
 
 impl UserId {
@@ -27,12 +31,15 @@ impl Default for UserId {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct User {
     pub id: UserId,
     pub name: String,
     pub is_observer: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schemars(with = "String")]
+    pub joined_at: OffsetDateTime,
 }
 
 impl User {
@@ -41,13 +48,12 @@ impl User {
             id: UserId::new(),
             name,
             is_observer,
+            joined_at: OffsetDateTime::now_utc(),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CreateUserRequest {
-    pub name: String,
-    pub is_observer: Option<bool>,
+// Put users in the order they joined, with ties broken by ID
+pub fn sort_users(users: &mut IndexMap<UserId, User>) {
+    users.sort_by(|a_id, a, b_id, b| (a.joined_at, a_id.0).cmp(&(b.joined_at, b_id.0)));
 }
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub enum Vote {
     Zero,
     One,
@@ -32,6 +35,41 @@ impl Vote {
         }
     }
 
+    // Stable ID of the card, used for storage so relabelling a card doesn't
+    // change votes already stored
+    pub fn card_id(&self) -> &'static str {
+        match self {
+            Vote::Zero => "zero",
+            Vote::One => "one",
+            Vote::Two => "two",
+            Vote::Three => "three",
+            Vote::Five => "five",
+            Vote::Eight => "eight",
+            Vote::Thirteen => "thirteen",
+            Vote::TwentyOne => "twenty_one",
+            Vote::QuestionMark => "question_mark",
+            Vote::Coffee => "coffee",
+            Vote::Hidden => "hidden",
+        }
+    }
+
+    pub fn from_card_id(card_id: &str) -> Result<Self, String> {
+        match card_id {
+            "zero" => Ok(Vote::Zero),
+            "one" => Ok(Vote::One),
+            "two" => Ok(Vote::Two),
+            "three" => Ok(Vote::Three),
+            "five" => Ok(Vote::Five),
+            "eight" => Ok(Vote::Eight),
+            "thirteen" => Ok(Vote::Thirteen),
+            "twenty_one" => Ok(Vote::TwentyOne),
+            "question_mark" => Ok(Vote::QuestionMark),
+            "coffee" => Ok(Vote::Coffee),
+            "hidden" => Ok(Vote::Hidden),
+            _ => Err(format!("Invalid card ID: {}", card_id)),
+        }
+    }
+
     pub fn from_string(value: &str) -> Result<Self, String> {
         match value.to_lowercase().as_str() {
             "0" => Ok(Vote::Zero),
@@ -44,12 +82,32 @@ impl Vote {
             "21" => Ok(Vote::TwentyOne),
             "?" => Ok(Vote::QuestionMark),
             "coffee" => Ok(Vote::Coffee),
+            "hidden" => Ok(Vote::Hidden),
             _ => Err(format!("Invalid vote value: {}", value)),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct VoteRequest {
-    pub value: String,
+// A user's vote in the current round, with when it was first cast and when it
+// was last changed
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CastVote {
+    pub vote: Vote,
+    #[serde(with = "time::serde::rfc3339")]
+    pub cast_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+impl CastVote {
+    pub fn new(vote: Vote) -> Self {
+        let now = OffsetDateTime::now_utc();
+
+        Self {
+            vote,
+            cast_at: now,
+            updated_at: now,
+        }
+    }
 }
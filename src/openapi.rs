@@ -0,0 +1,43 @@
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+};
+
+// Path of the generated document, and of the docs UI rendering it
+pub const SPEC_PATH: &str = "/openapi.json";
+pub const DOCS_PATH: &str = "/docs";
+
+// Base of the OpenAPI document. Paths and the schemas they use are collected
+// from the handlers' #[utoipa::path] attributes when the router is built.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Pointing Poker API",
+        description = "Rooms, voting, chat and the WebSocket feed of a planning poker server"
+    ),
+    modifiers(&AdminToken),
+    tags(
+        (name = "rooms", description = "Creating, joining and configuring rooms"),
+        (name = "voting", description = "Casting, revealing and resetting votes"),
+        (name = "chat", description = "Chat messages and emoji reactions"),
+        (name = "audit", description = "Audit log of owner actions"),
+        (name = "history", description = "Rooms rebuilt from their event log"),
+        (name = "websocket", description = "Live room events"),
+        (name = "admin", description = "Export, import and backups, guarded by ADMIN_TOKEN"),
+        (name = "monitoring", description = "Health checks and metrics")
+    )
+)]
+pub struct ApiDoc;
+
+// Bearer token checked by the admin routes
+struct AdminToken;
+
+impl Modify for AdminToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "adminToken",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
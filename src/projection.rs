@@ -0,0 +1,201 @@
+use crate::db::LoggedEvent;
+use crate::error::AppError;
+use crate::events::RoomEvent;
+use crate::models::room::{FIRST_ROUND, Room, RoomId, RoomSettings, RoomState};
+use crate::models::user::UserId;
+use crate::models::vote::CastVote;
+use indexmap::IndexMap;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+// Rebuild a room by replaying its logged events in order. Returns None if the
+// events don't create the room.
+pub fn project(room_id: &RoomId, events: &[LoggedEvent]) -> Result<Option<Room>, AppError> {
+    let mut room = None;
+
+    for logged in events {
+        let event = serde_json::from_str(&logged.event).map_err(|e| {
+            AppError::DatabaseError(format!("Invalid logged event {}: {}", logged.seq, e))
+        })?;
+        apply(&mut room, room_id, event, logged);
+    }
+
+    if let Some(room) = &mut room {
+        room.sort_members();
+    }
+
+    Ok(room)
+}
+
+// Apply one logged event to the room rebuilt so far
+fn apply(room: &mut Option<Room>, room_id: &RoomId, event: RoomEvent, logged: &LoggedEvent) {
+    // The first RoomUpdated event creates the room
+    if let RoomEvent::RoomUpdated(payload) = event {
+        let room = room.get_or_insert_with(|| Room {
+            id: room_id.clone(),
+            name: String::new(),
+            state: RoomState::Voting,
+            users: IndexMap::new(),
+            votes: IndexMap::new(),
+            round_id: FIRST_ROUND,
+            owner_id: None,
+            settings: RoomSettings::default(),
+            created_at: logged.created_at,
+            updated_at: logged.created_at,
+            archived_at: None,
+        });
+
+        room.name = payload.name;
+        room.owner_id = payload.owner_id.map(UserId);
+        room.state = payload.state;
+        room.settings = payload.settings;
+        room.archived_at = payload.archived_at;
+        room.updated_at = logged.created_at;
+        return;
+    }
+
+    if let RoomEvent::RoomClosed(_) = event {
+        *room = None;
+        return;
+    }
+
+    let Some(current) = room else {
+        return;
+    };
+
+    match event {
+        RoomEvent::UserJoined(payload) => {
+            current.users.insert(payload.user.id.clone(), payload.user);
+        }
+        RoomEvent::UserLeft(payload) => {
+            let user_id = UserId(payload.user_id);
+            current.users.shift_remove(&user_id);
+            current.votes.shift_remove(&user_id);
+        }
+        RoomEvent::VoteSubmitted(payload) => {
+            let Some(vote) = &logged.vote else {
+                return;
+            };
+
+            // A changed vote keeps its place
+            let cast_vote = current
+                .votes
+                .entry(UserId(payload.user_id))
+                .or_insert_with(|| CastVote {
+                    vote: vote.clone(),
+                    cast_at: logged.created_at,
+                    updated_at: logged.created_at,
+                });
+            cast_vote.vote = vote.clone();
+            cast_vote.updated_at = logged.created_at;
+        }
+        RoomEvent::VotesRevealed(_) => current.state = RoomState::Revealed,
+        RoomEvent::VotesReset(_) => {
+            current.votes.clear();
+            current.round_id += 1;
+            current.state = RoomState::Voting;
+        }
+        // Chat and ephemeral signals don't change the room
+        _ => return,
+    }
+
+    current.updated_at = logged.created_at;
+}
+
+// Ways in which the room rebuilt from its event log differs from the stored
+// one, empty if they agree
+pub fn differences(projected: Option<&Room>, stored: Option<&Room>) -> Vec<String> {
+    let (projected, stored) = match (projected, stored) {
+        (Some(projected), Some(stored)) => (projected, stored),
+        (None, None) => return Vec::new(),
+        (Some(_), None) => return vec!["Room is in the event log but not stored".to_string()],
+        (None, Some(_)) => return vec!["Room is stored but not in the event log".to_string()],
+    };
+
+    let mut differences = Vec::new();
+
+    if projected.name != stored.name {
+        differences.push(format!(
+            "Name is {:?} in the event log but {:?} stored",
+            projected.name, stored.name
+        ));
+    }
+    if projected.state != stored.state {
+        differences.push(format!(
+            "State is {:?} in the event log but {:?} stored",
+            projected.state, stored.state
+        ));
+    }
+    if projected.owner_id != stored.owner_id {
+        differences.push(format!(
+            "Owner is {} in the event log but {} stored",
+            describe(projected.owner_id.as_ref()),
+            describe(stored.owner_id.as_ref())
+        ));
+    }
+    if projected.round_id != stored.round_id {
+        differences.push(format!(
+            "Round is {} in the event log but {} stored",
+            projected.round_id, stored.round_id
+        ));
+    }
+    if projected.settings != stored.settings {
+        differences.push(format!(
+            "Settings are {:?} in the event log but {:?} stored",
+            projected.settings, stored.settings
+        ));
+    }
+    if projected.archived_at != stored.archived_at {
+        differences.push(format!(
+            "Archived at {:?} in the event log but {:?} stored",
+            projected.archived_at, stored.archived_at
+        ));
+    }
+
+    // Sorted so the report is the same every time
+    let user_ids: BTreeSet<Uuid> = projected
+        .users
+        .keys()
+        .chain(stored.users.keys())
+        .chain(projected.votes.keys())
+        .chain(stored.votes.keys())
+        .map(|user_id| user_id.0)
+        .collect();
+
+    for user_id in user_ids.into_iter().map(UserId) {
+        match (projected.users.get(&user_id), stored.users.get(&user_id)) {
+            (Some(_), None) => differences.push(format!(
+                "User {} is in the event log but not stored",
+                user_id
+            )),
+            (None, Some(_)) => differences.push(format!(
+                "User {} is stored but not in the event log",
+                user_id
+            )),
+            (Some(projected_user), Some(stored_user))
+                if projected_user.name != stored_user.name
+                    || projected_user.is_observer != stored_user.is_observer =>
+            {
+                differences.push(format!("User {} has different details", user_id))
+            }
+            _ => {}
+        }
+
+        // Timestamps are left out, the event log records when an event was
+        // sent rather than when it was stored
+        let projected_vote = projected.votes.get(&user_id).map(|vote| &vote.vote);
+        let stored_vote = stored.votes.get(&user_id).map(|vote| &vote.vote);
+        if projected_vote != stored_vote {
+            differences.push(format!(
+                "Vote of user {} is {:?} in the event log but {:?} stored",
+                user_id, projected_vote, stored_vote
+            ));
+        }
+    }
+
+    differences
+}
+
+fn describe(user_id: Option<&UserId>) -> String {
+    user_id.map_or_else(|| "nobody".to_string(), UserId::to_string)
+}
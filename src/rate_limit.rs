@@ -0,0 +1,46 @@
+use crate::models::user::UserId;
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Sliding window rate limiter keyed by user
+pub struct RateLimiter {
+    max_events: usize,
+    window: Duration,
+    hits: DashMap<UserId, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_events: usize, window: Duration) -> Self {
+        Self {
+            max_events,
+            window,
+            hits: DashMap::new(),
+        }
+    }
+
+    // Records an event for the user, returning false if they are over the limit
+    pub fn check(&self, user_id: &UserId) -> bool {
+        let now = Instant::now();
+        let mut hits = self.hits.entry(user_id.clone()).or_default();
+
+        while hits
+            .front()
+            .is_some_and(|hit| now.duration_since(*hit) >= self.window)
+        {
+            hits.pop_front();
+        }
+
+        if hits.len() >= self.max_events {
+            return false;
+        }
+
+        hits.push_back(now);
+        true
+    }
+
+    // Drop tracking for a user, e.g. once they have left their room
+    pub fn forget(&self, user_id: &UserId) {
+        self.hits.remove(user_id);
+    }
+}
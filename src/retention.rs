@@ -0,0 +1,40 @@
+use crate::state::AppState;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+// How often rooms past their retention period are looked for
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Background task deleting rooms that have been archived for longer than
+// `retention`. With several instances each one purges, which is harmless since
+// a room is only deleted once.
+pub async fn purge_archived_rooms(state: Arc<AppState>, retention: Duration) {
+    let mut ticker = tokio::time::interval(PURGE_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+
+        // Can only fail for retentions longer than the config allows
+        let Some(cutoff) = time::Duration::try_from(retention)
+            .ok()
+            .and_then(|retention| OffsetDateTime::now_utc().checked_sub(retention))
+        else {
+            tracing::warn!("Archived room retention is too long, not purging");
+            continue;
+        };
+
+        match state.db.purge_archived_rooms(cutoff).await {
+            Ok(room_ids) => {
+                for room_id in &room_ids {
+                    // Lets other instances drop the room from their caches
+                    state.close_room(room_id).await;
+                }
+                if !room_ids.is_empty() {
+                    tracing::info!("Purged {} archived rooms", room_ids.len());
+                }
+            }
+            Err(e) => tracing::warn!("Failed to purge archived rooms: {}", e),
+        }
+    }
+}
@@ -1,35 +1,150 @@
+pub mod admin;
+pub mod audit;
+pub mod chat;
+pub mod health;
+pub mod history;
+pub mod legacy;
 pub mod room;
 pub mod vote;
 pub mod ws;
 
+use crate::metrics::track_http_requests;
+use crate::openapi::{ApiDoc, DOCS_PATH, SPEC_PATH};
 use crate::state::AppState;
 use axum::{
-    Router,
-    routing::{get, post},
+    Json, Router,
+    extract::{DefaultBodyLimit, State},
+    middleware,
+    routing::get,
 };
 use std::sync::Arc;
+use utoipa::OpenApi;
+use utoipa_axum::{router::OpenApiRouter, router::UtoipaMethodRouterExt, routes};
+use utoipa_scalar::{Scalar, Servable};
+
+// Archives can be much larger than the default 2 MB request body limit
+pub const IMPORT_BODY_LIMIT: usize = 64 * 1024 * 1024;
 
 /// Creates the application router with all routes
 pub fn create_router(state: Arc<AppState>) -> Router {
-    Router::new()
-        // Health check
-        .route("/health", get(health_check))
+    let (router, api) = api_router().split_for_parts();
+
+    router
+        // The OpenAPI document and a docs UI rendering it
+        .merge(Scalar::with_url(DOCS_PATH, api.clone()))
+        .route(SPEC_PATH, get(move || async move { Json(api) }))
+        // Unversioned routes, deprecated in favour of /v1
+        .merge(legacy::router())
+        // Count and time requests by route
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            track_http_requests,
+        ))
+        // Apply state to all routes
+        .with_state(state)
+}
+
+// Routes whose handlers describe themselves with #[utoipa::path], so the
+// OpenAPI document is built from the same definitions that serve requests
+fn api_router() -> OpenApiRouter<Arc<AppState>> {
+    OpenApiRouter::with_openapi(ApiDoc::openapi())
+        // Health checks
+        .routes(routes!(health_check))
+        .routes(routes!(health::liveness))
+        .routes(routes!(health::readiness))
+        // Prometheus metrics
+        .routes(routes!(metrics))
+        // The versioned API
+        .nest("/v1", v1_router())
+}
+
+fn v1_router() -> OpenApiRouter<Arc<AppState>> {
+    OpenApiRouter::new()
         // Room routes
-        .route("/rooms", post(room::create_room))
-        .route("/rooms/{room_id}", get(room::get_room))
-        .route("/rooms/{room_id}/join", post(room::join_room))
-        .route("/rooms/{room_id}/leave/{user_id}", post(room::leave_room))
+        .routes(routes!(room::create_room))
+        .routes(routes!(room::get_room))
+        .routes(routes!(room::join_room))
+        .routes(routes!(room::leave_room))
+        .routes(routes!(room::reopen_room))
+        .routes(routes!(room::update_room_settings))
+        .routes(routes!(audit::get_audit_log))
+        .routes(routes!(history::get_room_history))
+        .routes(routes!(history::check_room_history))
         // Voting routes
-        .route("/rooms/{room_id}/vote", post(vote::submit_vote))
-        .route("/rooms/{room_id}/reveal", post(vote::reveal_votes))
-        .route("/rooms/{room_id}/reset", post(vote::reset_votes))
+        .routes(routes!(vote::submit_vote))
+        .routes(routes!(vote::reveal_votes))
+        .routes(routes!(vote::reset_votes))
+        // Chat and reaction routes
+        .routes(routes!(chat::get_chat_history, chat::send_chat_message))
+        .routes(routes!(chat::send_reaction))
         // WebSocket route
-        .route("/ws/rooms/{room_id}/users/{user_id}", get(ws::ws_handler))
-        // Apply state to all routes
-        .with_state(state)
+        .routes(routes!(ws::ws_handler))
+        .routes(routes!(ws::event_schema))
+        // Admin routes, guarded by ADMIN_TOKEN
+        .routes(routes!(admin::export_rooms))
+        .routes(routes!(admin::export_room))
+        .routes(routes!(admin::import_rooms).layer(DefaultBodyLimit::max(IMPORT_BODY_LIMIT)))
+        .routes(routes!(admin::backup))
 }
 
 /// Simple health check endpoint
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "monitoring",
+    responses(
+        (status = 200, description = "The server is up", body = String, content_type = "text/plain")
+    )
+)]
 async fn health_check() -> &'static str {
     "OK"
 }
+
+/// Metrics of this instance in the Prometheus text format
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "monitoring",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain")
+    )
+)]
+async fn metrics(State(state): State<Arc<AppState>>) -> String {
+    state
+        .metrics
+        .active_rooms
+        .set(state.events.room_count() as i64);
+
+    state.metrics.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::api_router;
+
+    // Snapshot of the served document, kept in the repository so API changes
+    // show up in review. Regenerate it with:
+    // UPDATE_OPENAPI=1 cargo test openapi_snapshot
+    const SNAPSHOT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    #[test]
+    fn openapi_snapshot_matches_routes() {
+        let generated = api_router()
+            .into_openapi()
+            .to_pretty_json()
+            .map(|json| json + "\n")
+            .unwrap();
+
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(SNAPSHOT_PATH, &generated).unwrap();
+            return;
+        }
+
+        let snapshot = std::fs::read_to_string(SNAPSHOT_PATH).unwrap_or_default();
+        assert!(
+            snapshot == generated,
+            "openapi.json is out of date with the route definitions, \
+             regenerate it with: UPDATE_OPENAPI=1 cargo test openapi_snapshot"
+        );
+    }
+}
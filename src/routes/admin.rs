@@ -0,0 +1,168 @@
+use crate::archive;
+use crate::error::{AppError, ErrorCode, ErrorResponse};
+use crate::extract::{Json, Query};
+use crate::models::archive::{Archive, BackupRequest, BackupResponse, ImportQuery, ImportReport};
+use crate::models::room::RoomId;
+use crate::state::AppState;
+use axum::{
+    extract::{FromRequestParts, Path, State},
+    http::{header, request::Parts},
+};
+use std::sync::Arc;
+
+// Proof that a request carries the ADMIN_TOKEN as a bearer token
+pub struct Admin;
+
+impl FromRequestParts<Arc<AppState>> for Admin {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        // Without a token configured the admin endpoints don't exist
+        let Some(admin_token) = &state.admin_token else {
+            return Err(AppError::NotFound(
+                ErrorCode::AdminApiDisabled,
+                "Admin API is disabled".to_string(),
+            ));
+        };
+
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match token {
+            Some(token) if tokens_match(token.as_bytes(), admin_token.as_bytes()) => Ok(Admin),
+            _ => Err(AppError::Unauthorized(
+                ErrorCode::InvalidAdminToken,
+                "Missing or invalid admin token".to_string(),
+            )),
+        }
+    }
+}
+
+// Compare every byte rather than stopping at the first difference, so the
+// response time doesn't give the token away
+fn tokens_match(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Export every room
+#[utoipa::path(
+    get,
+    path = "/admin/rooms/export",
+    tag = "admin",
+    security(("adminToken" = [])),
+    responses(
+        (status = 200, description = "Archive of every room", body = Archive),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin API is disabled", body = ErrorResponse)
+    )
+)]
+pub async fn export_rooms(
+    _: Admin,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Archive>, AppError> {
+    let room_ids = state.db.list_room_ids().await?;
+    let archive = archive::export_rooms(state.db.as_ref(), &room_ids).await?;
+
+    Ok(Json(archive))
+}
+
+// Export a single room
+#[utoipa::path(
+    get,
+    path = "/admin/rooms/{room_id}/export",
+    tag = "admin",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    security(("adminToken" = [])),
+    responses(
+        (status = 200, description = "Archive of the room", body = Archive),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin API is disabled", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str))]
+pub async fn export_room(
+    _: Admin,
+    State(state): State<Arc<AppState>>,
+    Path(room_id_str): Path<String>,
+) -> Result<Json<Archive>, AppError> {
+    // Parse room ID
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
+
+    let archive = archive::export_rooms(state.db.as_ref(), &[room_id]).await?;
+    if archive.rooms.is_empty() {
+        return Err(AppError::NotFound(
+            ErrorCode::RoomNotFound,
+            "Room not found".to_string(),
+        ));
+    }
+
+    Ok(Json(archive))
+}
+
+// Import the rooms of an archive
+#[utoipa::path(
+    post,
+    path = "/admin/rooms/import",
+    tag = "admin",
+    params(ImportQuery),
+    request_body = Archive,
+    security(("adminToken" = [])),
+    responses(
+        (status = 200, description = "What happened to each room", body = ImportReport),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin API is disabled", body = ErrorResponse),
+        (status = 409, description = "IDs already taken with onConflict=fail", body = ErrorResponse)
+    )
+)]
+pub async fn import_rooms(
+    _: Admin,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ImportQuery>,
+    Json(archive): Json<Archive>,
+) -> Result<Json<ImportReport>, AppError> {
+    let report = archive::import_rooms(
+        state.db.as_ref(),
+        archive,
+        query.on_conflict.unwrap_or_default(),
+        query.remap_ids.unwrap_or(false),
+    )
+    .await?;
+
+    Ok(Json(report))
+}
+
+// Back up the database to a file on the server
+#[utoipa::path(
+    post,
+    path = "/admin/backup",
+    tag = "admin",
+    request_body = BackupRequest,
+    security(("adminToken" = [])),
+    responses(
+        (status = 200, description = "Backup written", body = BackupResponse),
+        (status = 400, description = "File exists or storage is not SQLite", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin API is disabled", body = ErrorResponse)
+    )
+)]
+pub async fn backup(
+    _: Admin,
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<BackupRequest>,
+) -> Result<Json<BackupResponse>, AppError> {
+    state.db.backup(&request.path).await?;
+
+    tracing::info!("Database backed up to {}", request.path);
+
+    Ok(Json(BackupResponse { path: request.path }))
+}
@@ -0,0 +1,98 @@
+use crate::error::{AppError, ErrorCode, ErrorResponse};
+use crate::extract::{Json, Query};
+use crate::models::audit::{
+    AUDIT_PAGE_SIZE, AuditFilter, AuditPage, AuditQuery, MAX_AUDIT_PAGE_SIZE,
+};
+use crate::models::room::RoomId;
+use crate::models::user::UserId;
+use crate::state::AppState;
+use axum::extract::{Path, State};
+use std::sync::Arc;
+
+// Get the audit log of a room, newest first (owner only)
+#[utoipa::path(
+    get,
+    path = "/rooms/{room_id}/audit",
+    tag = "audit",
+    params(("room_id" = Uuid, Path, description = "Room ID"), AuditQuery),
+    responses(
+        (status = 200, description = "A page of audit events, newest first", body = AuditPage),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "Not the room owner", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = %query.user_id))]
+pub async fn get_audit_log(
+    State(state): State<Arc<AppState>>,
+    Path(room_id_str): Path<String>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditPage>, AppError> {
+    // Parse IDs
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
+
+    let user_id = UserId::from_string(&query.user_id).map_err(|_| {
+        AppError::invalid_field("userId", ErrorCode::InvalidUserId, "Invalid user ID")
+    })?;
+
+    let actor_id = query
+        .actor_id
+        .as_deref()
+        .map(UserId::from_string)
+        .transpose()
+        .map_err(|_| {
+            AppError::invalid_field("actorId", ErrorCode::InvalidUserId, "Invalid actor ID")
+        })?;
+
+    let limit = query.limit.unwrap_or(AUDIT_PAGE_SIZE);
+    if !(1..=MAX_AUDIT_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::invalid_field(
+            "limit",
+            ErrorCode::InvalidLimit,
+            format!("Limit must be between 1 and {}", MAX_AUDIT_PAGE_SIZE),
+        ));
+    }
+
+    let room =
+        state.db.get_room(&room_id).await?.ok_or_else(|| {
+            AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string())
+        })?;
+
+    // Check if the user is the room owner
+    if room.owner_id.as_ref() != Some(&user_id) {
+        return Err(AppError::Forbidden(
+            ErrorCode::NotRoomOwner,
+            "Only the room owner can view the audit log".to_string(),
+        ));
+    }
+
+    // Load one extra event to tell whether there is another page
+    let mut events = state
+        .db
+        .get_audit_events(
+            &room_id,
+            &AuditFilter {
+                action: query.action,
+                actor_id,
+                since: query.since,
+                until: query.until,
+                before: query.before,
+                limit: limit + 1,
+            },
+        )
+        .await?;
+
+    let next_before = if events.len() as i64 > limit {
+        events.truncate(limit as usize);
+        events.last().map(|event| event.id)
+    } else {
+        None
+    };
+
+    Ok(Json(AuditPage {
+        events,
+        next_before,
+    }))
+}
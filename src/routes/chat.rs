@@ -0,0 +1,198 @@
+use crate::db;
+use crate::error::{AppError, ErrorCode, ErrorResponse};
+use crate::events::{ChatMessageSentPayload, ReactionSentPayload, RoomEvent};
+use crate::extract::Json;
+use crate::models::chat::{
+    ChatMessage, SendChatMessageRequest, SendReactionRequest, validate_chat_text, validate_reaction,
+};
+use crate::models::room::RoomId;
+use crate::models::user::UserId;
+use crate::state::AppState;
+use axum::extract::{Path, State};
+use std::sync::Arc;
+
+// Send an emoji reaction to the room
+#[utoipa::path(
+    post,
+    path = "/rooms/{room_id}/reactions",
+    tag = "chat",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    request_body = SendReactionRequest,
+    responses(
+        (status = 200, description = "Reaction sent", body = ReactionSentPayload),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse),
+        (status = 409, description = "Room is archived", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = %request.user_id))]
+pub async fn send_reaction(
+    State(state): State<Arc<AppState>>,
+    Path(room_id_str): Path<String>,
+    Json(request): Json<SendReactionRequest>,
+) -> Result<Json<ReactionSentPayload>, AppError> {
+    // Parse IDs
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
+
+    let user_id = UserId::from_string(&request.user_id).map_err(|_| {
+        AppError::invalid_field("userId", ErrorCode::InvalidUserId, "Invalid user ID")
+    })?;
+
+    let reaction = react(&state, &room_id, &user_id, &request.emoji).await?;
+
+    Ok(Json(reaction))
+}
+
+// Post a chat message to the room
+#[utoipa::path(
+    post,
+    path = "/rooms/{room_id}/chat",
+    tag = "chat",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    request_body = SendChatMessageRequest,
+    responses(
+        (status = 200, description = "The stored message", body = ChatMessage),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse),
+        (status = 409, description = "Room is archived", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = %request.user_id))]
+pub async fn send_chat_message(
+    State(state): State<Arc<AppState>>,
+    Path(room_id_str): Path<String>,
+    Json(request): Json<SendChatMessageRequest>,
+) -> Result<Json<ChatMessage>, AppError> {
+    // Parse IDs
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
+
+    let user_id = UserId::from_string(&request.user_id).map_err(|_| {
+        AppError::invalid_field("userId", ErrorCode::InvalidUserId, "Invalid user ID")
+    })?;
+
+    let message = chat(&state, &room_id, &user_id, &request.text).await?;
+
+    Ok(Json(message))
+}
+
+// Get the recent chat history of a room
+#[utoipa::path(
+    get,
+    path = "/rooms/{room_id}/chat",
+    tag = "chat",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    responses(
+        (status = 200, description = "Recent chat messages, oldest first", body = [ChatMessage]),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str))]
+pub async fn get_chat_history(
+    State(state): State<Arc<AppState>>,
+    Path(room_id_str): Path<String>,
+) -> Result<Json<Vec<ChatMessage>>, AppError> {
+    // Parse room ID
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
+
+    // Check if room exists
+    if state.db.get_room(&room_id).await?.is_none() {
+        return Err(AppError::NotFound(
+            ErrorCode::RoomNotFound,
+            "Room not found".to_string(),
+        ));
+    }
+
+    let messages = state.db.get_chat_messages(&room_id).await?;
+
+    Ok(Json(messages))
+}
+
+// Validate and broadcast a reaction, shared by the REST and WebSocket paths
+pub async fn react(
+    state: &AppState,
+    room_id: &RoomId,
+    user_id: &UserId,
+    emoji: &str,
+) -> Result<ReactionSentPayload, AppError> {
+    let emoji = validate_reaction(emoji)
+        .map_err(|e| AppError::invalid_field("emoji", ErrorCode::InvalidReaction, e))?;
+
+    state.get_open_room(room_id).await?;
+    if !state.db.user_in_room(room_id, user_id).await? {
+        return Err(AppError::Forbidden(
+            ErrorCode::UserNotInRoom,
+            "User not in room".to_string(),
+        ));
+    }
+
+    if !state.reaction_limiter.check(user_id) {
+        return Err(AppError::RateLimited(
+            "Too many reactions, slow down".to_string(),
+        ));
+    }
+
+    let reaction = ReactionSentPayload {
+        user_id: user_id.0,
+        emoji,
+    };
+
+    // Reactions are ephemeral, so they are only broadcast
+    state
+        .publish(room_id, RoomEvent::ReactionSent(reaction.clone()))
+        .await;
+
+    Ok(reaction)
+}
+
+// Validate, persist and broadcast a chat message, shared by the REST and WebSocket paths
+pub async fn chat(
+    state: &AppState,
+    room_id: &RoomId,
+    user_id: &UserId,
+    text: &str,
+) -> Result<ChatMessage, AppError> {
+    let text = validate_chat_text(text)
+        .map_err(|e| AppError::invalid_field("text", ErrorCode::InvalidChatMessage, e))?;
+
+    state.get_open_room(room_id).await?;
+    if !state.db.user_in_room(room_id, user_id).await? {
+        return Err(AppError::Forbidden(
+            ErrorCode::UserNotInRoom,
+            "User not in room".to_string(),
+        ));
+    }
+
+    if !state.chat_limiter.check(user_id) {
+        return Err(AppError::RateLimited(
+            "Too many chat messages, slow down".to_string(),
+        ));
+    }
+
+    let message = ChatMessage::new(user_id.clone(), text);
+    let mut tx = state.db.begin().await?;
+    tx.add_chat_message(room_id, &message).await?;
+
+    // Notify about the new message
+    let event = db::log_event(
+        &mut *tx,
+        room_id,
+        RoomEvent::ChatMessageSent(ChatMessageSentPayload {
+            message: message.clone(),
+        }),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    state.publish_logged(room_id, vec![event]).await;
+
+    Ok(message)
+}
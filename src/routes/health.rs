@@ -0,0 +1,129 @@
+use crate::state::AppState;
+use axum::{Json, extract::State, http::StatusCode};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+// How long the database check may take before the instance counts as not ready
+const DATABASE_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+}
+
+const BUILD_INFO: BuildInfo = BuildInfo {
+    name: env!("CARGO_PKG_NAME"),
+    version: env!("CARGO_PKG_VERSION"),
+};
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LivenessResponse {
+    pub status: &'static str,
+    pub uptime_seconds: u64,
+    pub build: BuildInfo,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseCheck {
+    pub ok: bool,
+    pub latency_ms: u64,
+    // Applied schema migrations, None for in-memory storage
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<i64>,
+    // Why the check failed, without the database's own message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessResponse {
+    pub status: &'static str,
+    pub shutting_down: bool,
+    pub database: DatabaseCheck,
+    pub websocket_connections: usize,
+    pub uptime_seconds: u64,
+    pub build: BuildInfo,
+}
+
+// The process is up and serving requests, without checking its dependencies
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "monitoring",
+    responses(
+        (status = 200, description = "The server is up", body = LivenessResponse)
+    )
+)]
+pub async fn liveness(State(state): State<Arc<AppState>>) -> Json<LivenessResponse> {
+    Json(LivenessResponse {
+        status: "ok",
+        uptime_seconds: state.started_at.elapsed().as_secs(),
+        build: BUILD_INFO,
+    })
+}
+
+// Whether this instance should receive traffic: the database answers a real
+// query and the server isn't shutting down. Responds 503 when it shouldn't.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "monitoring",
+    responses(
+        (status = 200, description = "Ready to receive traffic", body = ReadinessResponse),
+        (status = 503, description = "Database unavailable or shutting down", body = ReadinessResponse)
+    )
+)]
+pub async fn readiness(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    let database = check_database(&state).await;
+    let shutting_down = state.shutdown.is_cancelled();
+
+    let (status_code, status) = if database.ok && !shutting_down {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "notReady")
+    };
+
+    (
+        status_code,
+        Json(ReadinessResponse {
+            status,
+            shutting_down,
+            database,
+            websocket_connections: state.connections.count(),
+            uptime_seconds: state.started_at.elapsed().as_secs(),
+            build: BUILD_INFO,
+        }),
+    )
+}
+
+async fn check_database(state: &AppState) -> DatabaseCheck {
+    let started = Instant::now();
+    let result = tokio::time::timeout(DATABASE_CHECK_TIMEOUT, state.db.schema_version()).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let (schema_version, error) = match result {
+        Ok(Ok(schema_version)) => (schema_version, None),
+        // The database's own message stays in the server log
+        Ok(Err(e)) => {
+            tracing::error!("Database readiness check failed: {}", e);
+            (None, Some("Database unavailable".to_string()))
+        }
+        Err(_) => (None, Some("Database check timed out".to_string())),
+    };
+
+    DatabaseCheck {
+        ok: error.is_none(),
+        latency_ms,
+        schema_version,
+        error,
+    }
+}
@@ -0,0 +1,108 @@
+use crate::error::{AppError, ErrorCode, ErrorResponse};
+use crate::extract::{Json, Query};
+use crate::models::history::{ConsistencyReport, HistoryQuery, RoomHistory};
+use crate::models::room::RoomId;
+use crate::projection;
+use crate::state::AppState;
+use axum::extract::{Path, State};
+use std::sync::Arc;
+
+// Get a room as it was at a point of its event log
+#[utoipa::path(
+    get,
+    path = "/rooms/{room_id}/history",
+    tag = "history",
+    params(("room_id" = Uuid, Path, description = "Room ID"), HistoryQuery),
+    responses(
+        (status = 200, description = "The room rebuilt from its event log", body = RoomHistory),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Room not found or did not exist at that point", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str))]
+pub async fn get_room_history(
+    State(state): State<Arc<AppState>>,
+    Path(room_id_str): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<RoomHistory>, AppError> {
+    // Parse room ID
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
+
+    if query.seq.is_some() && query.at.is_some() {
+        return Err(AppError::invalid_field(
+            "at",
+            ErrorCode::InvalidHistoryPoint,
+            "Pass either seq or at, not both",
+        ));
+    }
+
+    let mut events = state.db.get_room_events(&room_id, 0).await?;
+    if events.is_empty() {
+        return Err(AppError::NotFound(
+            ErrorCode::RoomNotFound,
+            "Room not found".to_string(),
+        ));
+    }
+
+    // Keep the events up to the requested point
+    events.retain(|event| {
+        query.seq.is_none_or(|seq| event.seq <= seq)
+            && query.at.is_none_or(|at| event.created_at <= at)
+    });
+
+    let seq = events.last().map_or(0, |event| event.seq);
+    let room = projection::project(&room_id, &events)?.ok_or_else(|| {
+        AppError::NotFound(
+            ErrorCode::RoomNotFound,
+            "Room did not exist at that point".to_string(),
+        )
+    })?;
+
+    Ok(Json(RoomHistory { seq, room }))
+}
+
+// Compare the room rebuilt from its event log with the stored one
+#[utoipa::path(
+    get,
+    path = "/rooms/{room_id}/history/check",
+    tag = "history",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    responses(
+        (status = 200, description = "Differences between the rebuilt and the stored room", body = ConsistencyReport),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str))]
+pub async fn check_room_history(
+    State(state): State<Arc<AppState>>,
+    Path(room_id_str): Path<String>,
+) -> Result<Json<ConsistencyReport>, AppError> {
+    // Parse room ID
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
+
+    // Read the tables rather than the cache, with the room and its log from
+    // the same snapshot so a concurrent change can't show as a difference
+    let (stored, events) = state.db.get_room_with_events(&room_id).await?;
+
+    if stored.is_none() && events.is_empty() {
+        return Err(AppError::NotFound(
+            ErrorCode::RoomNotFound,
+            "Room not found".to_string(),
+        ));
+    }
+
+    let seq = events.last().map_or(0, |event| event.seq);
+    let projected = projection::project(&room_id, &events)?;
+    let differences = projection::differences(projected.as_ref(), stored.as_ref());
+
+    Ok(Json(ConsistencyReport {
+        seq,
+        consistent: differences.is_empty(),
+        differences,
+    }))
+}
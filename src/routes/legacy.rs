@@ -0,0 +1,285 @@
+// Unversioned routes from before /v1, kept so existing clients keep working.
+// They take the old request bodies and answer with a Deprecation header and a
+// link to the /v1 route replacing them. Rooms and users keep the shape these
+// routes always sent them in.
+use crate::error::AppError;
+use crate::extract::{Json, Query};
+use crate::models::history::HistoryQuery;
+use crate::models::room::{
+    CreateRoomRequest, JoinRoomRequest, ReopenRoomRequest, Room, RoomId, RoomState,
+    UpdateRoomSettingsRequest,
+};
+use crate::models::user::{User, UserId};
+use crate::models::vote::Vote;
+use crate::routes::vote::{self, AdminActionRequest, SubmitVoteRequest, VoteResponse};
+use crate::routes::{IMPORT_BODY_LIMIT, admin, audit, chat, history, room, ws};
+use crate::state::AppState;
+use axum::{
+    Router,
+    extract::{DefaultBodyLimit, Path, Request, State},
+    http::{HeaderValue, header},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, patch, post},
+};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+// Prefix of the routes replacing these
+const SUCCESSOR_PREFIX: &str = "/v1";
+
+#[derive(Deserialize)]
+pub struct LegacySubmitVoteRequest {
+    pub user_id: String,
+    pub vote: LegacyVoteRequest,
+}
+
+#[derive(Deserialize)]
+pub struct LegacyVoteRequest {
+    pub value: String,
+}
+
+#[derive(Deserialize)]
+pub struct LegacyAdminActionRequest {
+    pub user_id: String,
+}
+
+impl From<LegacySubmitVoteRequest> for SubmitVoteRequest {
+    fn from(request: LegacySubmitVoteRequest) -> Self {
+        Self {
+            user_id: request.user_id,
+            value: request.vote.value,
+        }
+    }
+}
+
+impl From<LegacyAdminActionRequest> for AdminActionRequest {
+    fn from(request: LegacyAdminActionRequest) -> Self {
+        Self {
+            user_id: request.user_id,
+        }
+    }
+}
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        // Room routes
+        .route("/rooms", post(create_room))
+        .route("/rooms/{room_id}", get(get_room))
+        .route("/rooms/{room_id}/join", post(join_room))
+        .route("/rooms/{room_id}/leave/{user_id}", post(leave_room))
+        .route("/rooms/{room_id}/reopen", post(reopen_room))
+        .route("/rooms/{room_id}/settings", patch(update_room_settings))
+        .route("/rooms/{room_id}/audit", get(audit::get_audit_log))
+        .route("/rooms/{room_id}/history", get(get_room_history))
+        .route(
+            "/rooms/{room_id}/history/check",
+            get(history::check_room_history),
+        )
+        // Voting routes, with snake_case bodies and the vote nested
+        .route("/rooms/{room_id}/vote", post(submit_vote))
+        .route("/rooms/{room_id}/reveal", post(reveal_votes))
+        .route("/rooms/{room_id}/reset", post(reset_votes))
+        // Chat and reaction routes
+        .route(
+            "/rooms/{room_id}/chat",
+            get(chat::get_chat_history).post(chat::send_chat_message),
+        )
+        .route("/rooms/{room_id}/reactions", post(chat::send_reaction))
+        // WebSocket route
+        .route("/ws/rooms/{room_id}/users/{user_id}", get(ws::ws_handler))
+        .route("/ws/schema", get(ws::event_schema))
+        // Admin routes, guarded by ADMIN_TOKEN
+        .route("/admin/rooms/export", get(admin::export_rooms))
+        .route("/admin/rooms/{room_id}/export", get(admin::export_room))
+        .route(
+            "/admin/rooms/import",
+            post(admin::import_rooms).layer(DefaultBodyLimit::max(IMPORT_BODY_LIMIT)),
+        )
+        .route("/admin/backup", post(admin::backup))
+        .layer(middleware::from_fn(mark_deprecated))
+}
+
+// A room as these routes sent it: the state and votes by their PascalCase
+// names, and none of the fields added since
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyRoom {
+    pub id: RoomId,
+    pub name: String,
+    pub state: &'static str,
+    pub users: IndexMap<UserId, LegacyUser>,
+    pub votes: IndexMap<UserId, &'static str>,
+    pub owner_id: Option<UserId>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyUser {
+    pub id: UserId,
+    pub name: String,
+    pub is_observer: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyRoomHistory {
+    pub seq: i64,
+    pub room: LegacyRoom,
+}
+
+impl From<Room> for LegacyRoom {
+    fn from(room: Room) -> Self {
+        Self {
+            id: room.id,
+            name: room.name,
+            state: match room.state {
+                RoomState::Voting => "Voting",
+                RoomState::Revealed => "Revealed",
+            },
+            users: room
+                .users
+                .into_iter()
+                .map(|(user_id, user)| (user_id, user.into()))
+                .collect(),
+            votes: room
+                .votes
+                .into_iter()
+                .map(|(user_id, cast)| (user_id, vote_name(&cast.vote)))
+                .collect(),
+            owner_id: room.owner_id,
+        }
+    }
+}
+
+impl From<User> for LegacyUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            name: user.name,
+            is_observer: user.is_observer,
+        }
+    }
+}
+
+fn vote_name(vote: &Vote) -> &'static str {
+    match vote {
+        Vote::Zero => "Zero",
+        Vote::One => "One",
+        Vote::Two => "Two",
+        Vote::Three => "Three",
+        Vote::Five => "Five",
+        Vote::Eight => "Eight",
+        Vote::Thirteen => "Thirteen",
+        Vote::TwentyOne => "TwentyOne",
+        Vote::QuestionMark => "QuestionMark",
+        Vote::Coffee => "Coffee",
+        Vote::Hidden => "Hidden",
+    }
+}
+
+async fn create_room(
+    state: State<Arc<AppState>>,
+    request: Json<CreateRoomRequest>,
+) -> Result<Json<LegacyRoom>, AppError> {
+    let Json(room) = room::create_room(state, request).await?;
+    Ok(Json(room.into()))
+}
+
+async fn get_room(
+    state: State<Arc<AppState>>,
+    room_id: Path<String>,
+) -> Result<Json<LegacyRoom>, AppError> {
+    let Json(snapshot) = room::get_room(state, room_id).await?;
+    Ok(Json(snapshot.room.into()))
+}
+
+async fn join_room(
+    state: State<Arc<AppState>>,
+    room_id: Path<String>,
+    request: Json<JoinRoomRequest>,
+) -> Result<Json<LegacyUser>, AppError> {
+    let Json(user) = room::join_room(state, room_id, request).await?;
+    Ok(Json(user.into()))
+}
+
+async fn leave_room(
+    state: State<Arc<AppState>>,
+    ids: Path<(String, String)>,
+) -> Result<Json<LegacyUser>, AppError> {
+    let Json(user) = room::leave_room(state, ids).await?;
+    Ok(Json(user.into()))
+}
+
+async fn update_room_settings(
+    state: State<Arc<AppState>>,
+    room_id: Path<String>,
+    request: Json<UpdateRoomSettingsRequest>,
+) -> Result<Json<LegacyRoom>, AppError> {
+    let Json(room) = room::update_room_settings(state, room_id, request).await?;
+    Ok(Json(room.into()))
+}
+
+async fn reopen_room(
+    state: State<Arc<AppState>>,
+    room_id: Path<String>,
+    request: Json<ReopenRoomRequest>,
+) -> Result<Json<LegacyRoom>, AppError> {
+    let Json(room) = room::reopen_room(state, room_id, request).await?;
+    Ok(Json(room.into()))
+}
+
+async fn get_room_history(
+    state: State<Arc<AppState>>,
+    room_id: Path<String>,
+    query: Query<HistoryQuery>,
+) -> Result<Json<LegacyRoomHistory>, AppError> {
+    let Json(history) = history::get_room_history(state, room_id, query).await?;
+    Ok(Json(LegacyRoomHistory {
+        seq: history.seq,
+        room: history.room.into(),
+    }))
+}
+
+async fn submit_vote(
+    state: State<Arc<AppState>>,
+    room_id: Path<String>,
+    Json(payload): Json<LegacySubmitVoteRequest>,
+) -> Result<Json<VoteResponse>, AppError> {
+    vote::submit_vote(state, room_id, Json(payload.into())).await
+}
+
+async fn reveal_votes(
+    state: State<Arc<AppState>>,
+    room_id: Path<String>,
+    Json(payload): Json<LegacyAdminActionRequest>,
+) -> Result<Json<VoteResponse>, AppError> {
+    vote::reveal_votes(state, room_id, Json(payload.into())).await
+}
+
+async fn reset_votes(
+    state: State<Arc<AppState>>,
+    room_id: Path<String>,
+    Json(payload): Json<LegacyAdminActionRequest>,
+) -> Result<Json<VoteResponse>, AppError> {
+    vote::reset_votes(state, room_id, Json(payload.into())).await
+}
+
+// Tell clients the route is deprecated and where its replacement is
+async fn mark_deprecated(request: Request, next: Next) -> Response {
+    let successor = format!(
+        "<{}{}>; rel=\"successor-version\"",
+        SUCCESSOR_PREFIX,
+        request.uri().path()
+    );
+    let mut response = next.run(request).await;
+
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+
+    response
+}
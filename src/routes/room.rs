@@ -1,14 +1,33 @@
-use crate::error::AppError;
-use crate::models::room::{CreateRoomRequest, Room, RoomId};
-use crate::models::user::{User, UserId};
-use crate::state::{AppState, RoomEvent};
-use axum::{
-    Json,
-    extract::{Path, State},
+use crate::db::{self, Transaction};
+use crate::error::{AppError, ErrorCode, ErrorResponse};
+use crate::events::{
+    RoomEvent, RoomUpdatedPayload, SequencedEvent, UserJoinedPayload, UserLeftPayload,
+};
+use crate::extract::Json;
+use crate::models::audit::AuditAction;
+use crate::models::room::{
+    CreateRoomRequest, JoinRoomRequest, ReopenRoomRequest, Room, RoomId, RoomSnapshot,
+    UpdateRoomSettingsRequest,
 };
+use crate::models::user::{User, UserId};
+use crate::state::AppState;
+use axum::extract::{Path, State};
+use serde_json::json;
 use std::sync::Arc;
+use time::OffsetDateTime;
 
 // Create a new room
+#[utoipa::path(
+    post,
+    path = "/rooms",
+    tag = "rooms",
+    request_body = CreateRoomRequest,
+    responses(
+        (status = 200, description = "The new room", body = Room),
+        (status = 400, description = "Invalid request", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = tracing::field::Empty))]
 pub async fn create_room(
     State(state): State<Arc<AppState>>,
     Json(request): Json<CreateRoomRequest>,
@@ -17,124 +36,449 @@ pub async fn create_room(
     let owner = request.creator_name.map(|name| User::new(name, false));
 
     // Create a new room
-    let room = Room::new(request.name.clone(), owner);
+    let room = Room::new(
+        request.name.clone(),
+        owner,
+        request.settings.unwrap_or_default(),
+    );
     let room_id = room.id.clone();
+    tracing::Span::current().record("room_id", tracing::field::display(&room_id));
 
-    // Store room in database
-    state.db.create_room(&room).await?;
+    // Store room in database, along with its audit entry
+    let mut tx = state.db.begin().await?;
+    tx.create_room(&room).await?;
+    tx.add_audit_event(
+        &room_id,
+        room.owner_id.as_ref(),
+        AuditAction::RoomCreated,
+        &json!({ "name": room.name, "settings": room.settings }),
+    )
+    .await?;
 
-    // Create event channel for this room
-    let _ = state.ensure_room_event_sender(&room_id);
+    // Announce the new room and its creator, so the room's event log
+    // describes it completely
+    let mut events = vec![
+        db::log_event(
+            &mut *tx,
+            &room_id,
+            RoomEvent::RoomUpdated(RoomUpdatedPayload::from_room(&room)),
+            None,
+        )
+        .await?,
+    ];
+    for user in room.users.values() {
+        events.push(
+            db::log_event(
+                &mut *tx,
+                &room_id,
+                RoomEvent::UserJoined(UserJoinedPayload { user: user.clone() }),
+                None,
+            )
+            .await?,
+        );
+    }
+    tx.commit().await?;
+    state.publish_logged(&room_id, events).await;
 
     // Return the newly created room
     Ok(Json(room))
 }
 
 // Get room details
+#[utoipa::path(
+    get,
+    path = "/rooms/{room_id}",
+    tag = "rooms",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    responses(
+        (status = 200, description = "The room with who is online and their status signals", body = RoomSnapshot),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str))]
 pub async fn get_room(
     State(state): State<Arc<AppState>>,
     Path(room_id_str): Path<String>,
-) -> Result<Json<Room>, AppError> {
+) -> Result<Json<RoomSnapshot>, AppError> {
     // Parse room ID
-    let room_id = RoomId::from_string(&room_id_str)
-        .map_err(|_| AppError::BadRequest("Invalid room ID".to_string()))?;
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
 
     // Get room from database
-    let room = state
-        .db
+    let room =
+        state.db.get_room(&room_id).await?.ok_or_else(|| {
+            AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string())
+        })?;
+
+    // Add who is currently connected and what they are signalling
+    let online_user_ids = state.connections.online_users(&room_id);
+    let user_statuses = state.statuses.statuses(&room_id);
+
+    Ok(Json(RoomSnapshot {
+        room,
+        online_user_ids,
+        user_statuses,
+    }))
+}
+
+// Update room settings (owner only)
+#[utoipa::path(
+    patch,
+    path = "/rooms/{room_id}/settings",
+    tag = "rooms",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    request_body = UpdateRoomSettingsRequest,
+    responses(
+        (status = 200, description = "The updated room", body = Room),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "Not the room owner", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse),
+        (status = 409, description = "Room is archived", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = %request.user_id))]
+pub async fn update_room_settings(
+    State(state): State<Arc<AppState>>,
+    Path(room_id_str): Path<String>,
+    Json(request): Json<UpdateRoomSettingsRequest>,
+) -> Result<Json<Room>, AppError> {
+    // Parse IDs
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
+
+    let user_id = UserId::from_string(&request.user_id).map_err(|_| {
+        AppError::invalid_field("userId", ErrorCode::InvalidUserId, "Invalid user ID")
+    })?;
+
+    // Check ownership and update in one transaction
+    let mut tx = state.db.begin().await?;
+
+    let mut room = tx
         .get_room(&room_id)
         .await?
-        .ok_or_else(|| AppError::NotFound("Room not found".to_string()))?;
+        .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?;
+
+    // Check if the user is the room owner
+    if room.owner_id.as_ref() != Some(&user_id) {
+        return Err(AppError::Forbidden(
+            ErrorCode::NotRoomOwner,
+            "Only the room owner can change settings".to_string(),
+        ));
+    }
+    db::check_open(&room)?;
+
+    // Apply the provided changes
+    if let Some(session_mode) = request.session_mode {
+        room.settings.session_mode = session_mode;
+    }
+
+    tx.update_room_settings(&room_id, &room.settings).await?;
+    tx.add_audit_event(
+        &room_id,
+        Some(&user_id),
+        AuditAction::SettingsUpdated,
+        &json!({ "settings": room.settings }),
+    )
+    .await?;
+
+    // Notify about the new settings
+    let event = db::log_event(
+        &mut *tx,
+        &room_id,
+        RoomEvent::RoomUpdated(RoomUpdatedPayload::from_room(&room)),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    state.publish_logged(&room_id, vec![event]).await;
 
     Ok(Json(room))
 }
 
 // Join a room
+#[utoipa::path(
+    post,
+    path = "/rooms/{room_id}/join",
+    tag = "rooms",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    request_body = JoinRoomRequest,
+    responses(
+        (status = 200, description = "The new user", body = User),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse),
+        (status = 409, description = "Room is archived", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = tracing::field::Empty))]
 pub async fn join_room(
     State(state): State<Arc<AppState>>,
     Path(room_id_str): Path<String>,
-    Json(request): Json<CreateUserRequest>,
+    Json(request): Json<JoinRoomRequest>,
 ) -> Result<Json<User>, AppError> {
     // Parse room ID
-    let room_id = RoomId::from_string(&room_id_str)
-        .map_err(|_| AppError::BadRequest("Invalid room ID".to_string()))?;
-
-    // Check if room exists
-    let room_exists = state.db.get_room(&room_id).await?.is_some();
-
-    if !room_exists {
-        return Err(AppError::NotFound("Room not found".to_string()));
-    }
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
 
     // Create user
     let is_observer = request.is_observer.unwrap_or(false);
     let user = User::new(request.name, is_observer);
+    tracing::Span::current().record("user_id", tracing::field::display(&user.id));
 
-    // Add user to room in database
-    state.db.add_user(&user, &room_id).await?;
+    // Add user to room in database with its audit entry, checking the room
+    // exists and is open in the same transaction
+    let mut tx = state.db.begin().await?;
+    let room = tx
+        .get_room(&room_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?;
+    db::check_open(&room)?;
+
+    tx.add_user(&user, &room_id).await?;
+    tx.add_audit_event(
+        &room_id,
+        Some(&user.id),
+        AuditAction::UserJoined,
+        &json!({ "name": user.name, "isObserver": user.is_observer }),
+    )
+    .await?;
 
     // Notify about new user
-    let event_sender = state.ensure_room_event_sender(&room_id);
-    let _ = event_sender.send(RoomEvent::UserJoined(user.clone()));
+    let event = db::log_event(
+        &mut *tx,
+        &room_id,
+        RoomEvent::UserJoined(UserJoinedPayload { user: user.clone() }),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    state.publish_logged(&room_id, vec![event]).await;
 
     Ok(Json(user))
 }
 
 // Leave a room
+#[utoipa::path(
+    post,
+    path = "/rooms/{room_id}/leave/{user_id}",
+    tag = "rooms",
+    params(("room_id" = Uuid, Path, description = "Room ID"), ("user_id" = Uuid, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "The user who left", body = User),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Room or user not found", body = ErrorResponse),
+        (status = 409, description = "Room is archived", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = %user_id_str))]
 pub async fn leave_room(
     State(state): State<Arc<AppState>>,
     Path((room_id_str, user_id_str)): Path<(String, String)>,
 ) -> Result<Json<User>, AppError> {
     // Parse IDs
-    let room_id = RoomId::from_string(&room_id_str)
-        .map_err(|_| AppError::BadRequest("Invalid room ID".to_string()))?;
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
 
-    let user_id = UserId::from_string(&user_id_str)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+    let user_id = UserId::from_string(&user_id_str).map_err(|_| {
+        AppError::invalid_field("userId", ErrorCode::InvalidUserId, "Invalid user ID")
+    })?;
 
-    // Remove user from database and get user data
-    let (user, _) = state
-        .db
-        .remove_user(&user_id)
+    // Remove the user and hand over or archive the room in one transaction, so
+    // concurrent leaves can't both keep or both archive the room
+    let mut tx = state.db.begin().await?;
+
+    // Read the room first to lock it for the rest of the transaction
+    let room = tx
+        .get_room(&room_id)
         .await?
-        .ok_or_else(|| AppError::NotFound("User not found in room".to_string()))?;
+        .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?;
+    db::check_open(&room)?;
 
-    // Notify about user leaving
-    if let Some(tx) = state.get_room_event_sender(&room_id) {
-        let _ = tx.send(RoomEvent::UserLeft(crate::state::UserLeftPayload {
-            user_id: user_id.0,
-        }));
+    // Only users of this room can leave it, whatever room the user ID is in
+    let Some(user) = room.users.get(&user_id) else {
+        return Err(AppError::NotFound(
+            ErrorCode::UserNotFound,
+            "User not found in room".to_string(),
+        ));
+    };
+
+    // The owner leaving a room nobody else is in archives it as it is, so
+    // they stay listed as its owner and can reopen it later
+    if room.owner_id.as_ref() == Some(&user_id) && room.users.len() == 1 {
+        tx.update_room_archived_at(&room_id, Some(OffsetDateTime::now_utc()))
+            .await?;
+        tx.add_audit_event(
+            &room_id,
+            Some(&user_id),
+            AuditAction::RoomArchived,
+            &json!({ "name": room.name }),
+        )
+        .await?;
+
+        // Announce the archived room, then disconnect everyone
+        let event = log_room_updated(&mut *tx, &room_id).await?;
+        tx.commit().await?;
+
+        state.chat_limiter.forget(&user_id);
+        state.reaction_limiter.forget(&user_id);
+        state.statuses.clear(&room_id, &user_id);
+
+        state.publish_logged(&room_id, vec![event]).await;
+        state.close_room(&room_id).await;
+
+        return Ok(Json(user.clone()));
     }
 
-    // Check if this was the room owner
-    let room = state.db.get_room(&room_id).await?;
-
-    if let Some(room) = room {
-        // If the owner is leaving, assign a new owner if there are other users
-        if room.owner_id.as_ref() == Some(&user_id) {
-            // Count remaining users
-            let user_count = state.db.count_users_in_room(&room_id).await?;
-
-            if user_count > 0 {
-                // Get first remaining user
-                let users = state.db.get_users_for_room(&room_id).await?;
-                if let Some((first_user_id, _)) = users.iter().next() {
-                    // Assign as new owner
-                    state
-                        .db
-                        .update_room_owner(&room_id, Some(first_user_id))
-                        .await?;
-                }
-            } else {
-                // If room is empty, remove it and its event sender
-                state.db.delete_room(&room_id).await?;
-                state.remove_room_event_sender(&room_id);
-            }
-        }
+    // Remove user from database and get user data
+    let (user, _) = tx.remove_user(&user_id).await?.ok_or_else(|| {
+        AppError::NotFound(
+            ErrorCode::UserNotFound,
+            "User not found in room".to_string(),
+        )
+    })?;
+    tx.add_audit_event(
+        &room_id,
+        Some(&user_id),
+        AuditAction::UserLeft,
+        &json!({ "name": user.name }),
+    )
+    .await?;
+
+    // Notify about user leaving
+    let mut events = vec![
+        db::log_event(
+            &mut *tx,
+            &room_id,
+            RoomEvent::UserLeft(UserLeftPayload { user_id: user_id.0 }),
+            None,
+        )
+        .await?,
+    ];
+
+    // If the owner is leaving, hand the room over to any remaining user
+    if room.owner_id.as_ref() == Some(&user_id)
+        && let Some(new_owner_id) = room.users.keys().find(|id| **id != user_id)
+    {
+        tx.update_room_owner(&room_id, Some(new_owner_id)).await?;
+        tx.add_audit_event(
+            &room_id,
+            Some(&user_id),
+            AuditAction::OwnerChanged,
+            &json!({ "previousOwnerId": user_id, "newOwnerId": new_owner_id }),
+        )
+        .await?;
+
+        // Notify about the ownership change
+        events.push(log_room_updated(&mut *tx, &room_id).await?);
     }
 
+    tx.commit().await?;
+
+    // Stop tracking their chat and reaction rate limits
+    state.chat_limiter.forget(&user_id);
+    state.reaction_limiter.forget(&user_id);
+    state.statuses.clear(&room_id, &user_id);
+
+    state.publish_logged(&room_id, events).await;
+
     Ok(Json(user))
 }
 
-// Import CreateUserRequest
-use crate::models::user::CreateUserRequest;
+// Reopen an archived room (owner only)
+#[utoipa::path(
+    post,
+    path = "/rooms/{room_id}/reopen",
+    tag = "rooms",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    request_body = ReopenRoomRequest,
+    responses(
+        (status = 200, description = "The reopened room", body = Room),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "Not the room owner", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = %request.user_id))]
+pub async fn reopen_room(
+    State(state): State<Arc<AppState>>,
+    Path(room_id_str): Path<String>,
+    Json(request): Json<ReopenRoomRequest>,
+) -> Result<Json<Room>, AppError> {
+    // Parse IDs
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
+
+    let user_id = UserId::from_string(&request.user_id).map_err(|_| {
+        AppError::invalid_field("userId", ErrorCode::InvalidUserId, "Invalid user ID")
+    })?;
+
+    // Check ownership and reopen in one transaction
+    let mut tx = state.db.begin().await?;
+
+    let mut room = tx
+        .get_room(&room_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?;
+
+    // Check if the user is the room owner
+    if room.owner_id.as_ref() != Some(&user_id) {
+        return Err(AppError::Forbidden(
+            ErrorCode::NotRoomOwner,
+            "Only the room owner can reopen the room".to_string(),
+        ));
+    }
+
+    if !room.is_archived() {
+        return Err(AppError::Conflict(
+            ErrorCode::RoomNotArchived,
+            "Room is not archived".to_string(),
+        ));
+    }
+
+    tx.update_room_archived_at(&room_id, None).await?;
+    tx.add_audit_event(
+        &room_id,
+        Some(&user_id),
+        AuditAction::RoomReopened,
+        &json!({ "name": room.name }),
+    )
+    .await?;
+
+    // Notify about the reopened room
+    room.archived_at = None;
+    let event = db::log_event(
+        &mut *tx,
+        &room_id,
+        RoomEvent::RoomUpdated(RoomUpdatedPayload::from_room(&room)),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    state.publish_logged(&room_id, vec![event]).await;
+
+    Ok(Json(room))
+}
+
+// Log the room's metadata as changed so far in the transaction
+async fn log_room_updated(
+    tx: &mut dyn Transaction,
+    room_id: &RoomId,
+) -> Result<SequencedEvent, AppError> {
+    let room = tx
+        .get_room(room_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?;
+
+    db::log_event(
+        tx,
+        room_id,
+        RoomEvent::RoomUpdated(RoomUpdatedPayload::from_room(&room)),
+        None,
+    )
+    .await
+}
@@ -1,53 +1,115 @@
-use crate::error::AppError;
-use crate::models::room::RoomId;
-use crate::models::user::UserId;
-use crate::models::vote::{Vote, VoteRequest};
-use crate::state::{AppState, RoomEvent};
-use axum::{
-    Json,
-    extract::{Path, State},
+use crate::db;
+use crate::error::{AppError, ErrorCode, ErrorResponse};
+use crate::events::{
+    RoomEvent, RoomUpdatedPayload, VoteSubmittedPayload, VoteWithUser, VotesResetPayload,
+    VotesRevealedPayload,
 };
+use crate::extract::Json;
+use crate::models::audit::AuditAction;
+use crate::models::room::{RoomId, RoomState};
+use crate::models::user::UserId;
+use crate::models::vote::Vote;
+use crate::state::AppState;
+use axum::extract::{Path, State};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::sync::Arc;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct VoteResponse {
     pub success: bool,
     pub message: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct SubmitVoteRequest {
     pub user_id: String,
-    pub vote: VoteRequest,
+    // Card label, e.g. "5" or "?"
+    pub value: String,
 }
 
 // Submit a vote
+#[utoipa::path(
+    post,
+    path = "/rooms/{room_id}/vote",
+    tag = "voting",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    request_body = SubmitVoteRequest,
+    responses(
+        (status = 200, description = "Vote recorded", body = VoteResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "User not in room", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse),
+        (status = 409, description = "Room is archived or votes are revealed", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = %payload.user_id))]
 pub async fn submit_vote(
     State(state): State<Arc<AppState>>,
     Path(room_id_str): Path<String>,
     Json(payload): Json<SubmitVoteRequest>,
 ) -> Result<Json<VoteResponse>, AppError> {
     // Parse room ID
-    let room_id = RoomId::from_string(&room_id_str)
-        .map_err(|_| AppError::BadRequest("Invalid room ID".to_string()))?;
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
 
     // Parse user ID
-    let user_id = UserId::from_string(&payload.user_id)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+    let user_id = UserId::from_string(&payload.user_id).map_err(|_| {
+        AppError::invalid_field("userId", ErrorCode::InvalidUserId, "Invalid user ID")
+    })?;
 
     // Parse vote
-    let vote = Vote::from_string(&payload.vote.value).map_err(AppError::BadRequest)?;
-
-    // Add vote to database (validation happens in the model through db.add_vote)
-    state.db.add_vote(&room_id, &user_id, &vote).await?;
+    let vote = Vote::from_string(&payload.value)
+        .map_err(|e| AppError::invalid_field("value", ErrorCode::InvalidVote, e))?;
 
-    // Notify about vote submission
-    if let Some(tx) = state.get_room_event_sender(&room_id) {
-        let _ = tx.send(RoomEvent::VoteSubmitted(crate::state::UserLeftPayload {
-            user_id: user_id.0,
-        }));
+    // Votes can only change until they are revealed, by users in the room.
+    // Checked in the transaction storing the vote, so a reveal can't slip in
+    let mut tx = state.db.begin().await?;
+    let room = tx
+        .get_room(&room_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?;
+    db::check_open(&room)?;
+    if room.state == RoomState::Revealed {
+        return Err(AppError::Conflict(
+            ErrorCode::VoteNotAllowedWhenRevealed,
+            "Votes have been revealed, reset them to vote again".to_string(),
+        ));
     }
+    if !room.users.contains_key(&user_id) {
+        return Err(AppError::Forbidden(
+            ErrorCode::UserNotInRoom,
+            "User not in room".to_string(),
+        ));
+    }
+
+    // Add vote to database with its audit entry. The value stays out of the
+    // log, the owner could read it before the reveal
+    tx.add_vote(&room_id, &user_id, &vote).await?;
+    tx.add_audit_event(
+        &room_id,
+        Some(&user_id),
+        AuditAction::VoteSubmitted,
+        &json!({}),
+    )
+    .await?;
+
+    // Notify about vote submission. The value is kept in the room's log for
+    // rebuilding the room, but not sent out.
+    let event = db::log_event(
+        &mut *tx,
+        &room_id,
+        RoomEvent::VoteSubmitted(VoteSubmittedPayload { user_id: user_id.0 }),
+        Some(&vote),
+    )
+    .await?;
+    tx.commit().await?;
+    state.metrics.votes.inc();
+    state.publish_logged(&room_id, vec![event]).await;
 
     Ok(Json(VoteResponse {
         success: true,
@@ -56,44 +118,77 @@ pub async fn submit_vote(
 }
 
 // Reveal votes
+#[utoipa::path(
+    post,
+    path = "/rooms/{room_id}/reveal",
+    tag = "voting",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    request_body = AdminActionRequest,
+    responses(
+        (status = 200, description = "Votes revealed", body = VoteResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "Not the room owner", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse),
+        (status = 409, description = "Room is archived", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = %payload.user_id))]
 pub async fn reveal_votes(
     State(state): State<Arc<AppState>>,
     Path(room_id_str): Path<String>,
     Json(payload): Json<AdminActionRequest>,
 ) -> Result<Json<VoteResponse>, AppError> {
     // Parse room ID
-    let room_id = RoomId::from_string(&room_id_str)
-        .map_err(|_| AppError::BadRequest("Invalid room ID".to_string()))?;
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
 
     // Parse user ID
-    let user_id = UserId::from_string(&payload.user_id)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+    let user_id = UserId::from_string(&payload.user_id).map_err(|_| {
+        AppError::invalid_field("userId", ErrorCode::InvalidUserId, "Invalid user ID")
+    })?;
 
     // Reveal votes using domain model logic in database layer
-    state.db.reveal_votes(&room_id, &user_id).await?;
+    let mut tx = state.db.begin().await?;
+    let mut room = db::reveal_votes(&mut *tx, &room_id, &user_id).await?;
+    room.state = RoomState::Revealed;
 
-    // Notify about votes being revealed
-    if let Some(tx) = state.get_room_event_sender(&room_id) {
-        // Get room with votes and users
-        let room = state.db.get_room(&room_id).await?
-            .ok_or_else(|| AppError::NotFound("Room not found".to_string()))?;
-        
-        // Create vote payloads from room data
-        let mut vote_payloads = Vec::new();
-        for (user_id, vote) in room.votes.iter() {
-            if room.users.contains_key(user_id) {
-                vote_payloads.push(crate::state::VoteWithUser {
-                    user_id: user_id.0,
-                    value: vote.value().unwrap_or_else(|| "hidden".to_string()),
-                });
-            }
+    // Create vote payloads from room data
+    let mut vote_payloads = Vec::new();
+    for (user_id, vote) in room.votes.iter() {
+        if room.users.contains_key(user_id) {
+            vote_payloads.push(VoteWithUser {
+                user_id: user_id.0,
+                value: vote.vote.value().unwrap_or_else(|| "hidden".to_string()),
+                cast_at: vote.cast_at,
+                updated_at: vote.updated_at,
+            });
         }
-            
-        let _ = tx.send(RoomEvent::VotesRevealed(crate::state::VotesRevealedPayload {
-            votes: vote_payloads,
-        }));
     }
 
+    // Notify about votes being revealed
+    let events = vec![
+        db::log_event(
+            &mut *tx,
+            &room_id,
+            RoomEvent::VotesRevealed(VotesRevealedPayload {
+                votes: vote_payloads,
+            }),
+            None,
+        )
+        .await?,
+        db::log_event(
+            &mut *tx,
+            &room_id,
+            RoomEvent::RoomUpdated(RoomUpdatedPayload::from_room(&room)),
+            None,
+        )
+        .await?,
+    ];
+    tx.commit().await?;
+    state.metrics.reveals.inc();
+    state.publish_logged(&room_id, events).await;
+
     Ok(Json(VoteResponse {
         success: true,
         message: "Votes revealed successfully".to_string(),
@@ -101,26 +196,63 @@ pub async fn reveal_votes(
 }
 
 // Reset votes
+#[utoipa::path(
+    post,
+    path = "/rooms/{room_id}/reset",
+    tag = "voting",
+    params(("room_id" = Uuid, Path, description = "Room ID")),
+    request_body = AdminActionRequest,
+    responses(
+        (status = 200, description = "Votes reset for a new round", body = VoteResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "Not the room owner", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse),
+        (status = 409, description = "Room is archived", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = %payload.user_id))]
 pub async fn reset_votes(
     State(state): State<Arc<AppState>>,
     Path(room_id_str): Path<String>,
     Json(payload): Json<AdminActionRequest>,
 ) -> Result<Json<VoteResponse>, AppError> {
     // Parse room ID
-    let room_id = RoomId::from_string(&room_id_str)
-        .map_err(|_| AppError::BadRequest("Invalid room ID".to_string()))?;
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
 
     // Parse user ID
-    let user_id = UserId::from_string(&payload.user_id)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+    let user_id = UserId::from_string(&payload.user_id).map_err(|_| {
+        AppError::invalid_field("userId", ErrorCode::InvalidUserId, "Invalid user ID")
+    })?;
 
     // Reset votes using domain model logic in database layer
-    state.db.reset_votes(&room_id, &user_id).await?;
+    let mut tx = state.db.begin().await?;
+    db::reset_votes(&mut *tx, &room_id, &user_id).await?;
+    let room = tx
+        .get_room(&room_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string()))?;
 
-    // Notify about votes being reset
-    if let Some(tx) = state.get_room_event_sender(&room_id) {
-        let _ = tx.send(RoomEvent::VotesReset(crate::state::VotesResetPayload {}));
-    }
+    // Notify about votes being reset and the room state going back to voting
+    let events = vec![
+        db::log_event(
+            &mut *tx,
+            &room_id,
+            RoomEvent::VotesReset(VotesResetPayload {}),
+            None,
+        )
+        .await?,
+        db::log_event(
+            &mut *tx,
+            &room_id,
+            RoomEvent::RoomUpdated(RoomUpdatedPayload::from_room(&room)),
+            None,
+        )
+        .await?,
+    ];
+    tx.commit().await?;
+    state.publish_logged(&room_id, events).await;
 
     Ok(Json(VoteResponse {
         success: true,
@@ -128,7 +260,8 @@ pub async fn reset_votes(
     }))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct AdminActionRequest {
     pub user_id: String,
 }
@@ -1,86 +1,456 @@
-use crate::error::AppError;
+use crate::connections::CLOSE_SERVICE_RESTART;
+use crate::error::{AppError, ErrorCode, ErrorResponse};
+use crate::events::{
+    ClientEvent, PROTOCOL_VERSION, PresenceChangedPayload, RoomEvent, SUPPORTED_PROTOCOL_VERSIONS,
+    SequencedEvent, ServerShuttingDownPayload, UserStatusChangedPayload, WelcomePayload,
+    subprotocol_name,
+};
+use crate::extract::Query;
 use crate::models::room::RoomId;
+use crate::models::status::UserStatus;
 use crate::models::user::UserId;
+use crate::routes::chat;
 use crate::state::AppState;
 use axum::{
+    Json,
     extract::{Path, State, WebSocketUpgrade, connect_info::ConnectInfo, ws},
+    http::{HeaderMap, header},
     response::IntoResponse,
 };
 use futures::{sink::SinkExt, stream::StreamExt};
-// Removed unused import
+use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::Instrument;
+use utoipa::{IntoParams, ToSchema};
+
+// Suggested delay before clients reconnect when the server shuts down
+const RECONNECT_AFTER: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct WsParams {
+    pub protocol_version: Option<u32>,
+    pub encoding: Option<EventEncoding>,
+    // Replay the logged events after this sequence number before live ones
+    pub since: Option<i64>,
+}
+
+// Wire encoding of events on a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EventEncoding {
+    Json,
+    Msgpack,
+}
+
+impl EventEncoding {
+    // Encode an event as a text frame (JSON) or a binary frame (MessagePack)
+    fn encode(self, event: &SequencedEvent) -> Option<ws::Message> {
+        match self {
+            EventEncoding::Json => serde_json::to_string(event)
+                .ok()
+                .map(|text| ws::Message::Text(text.into())),
+            EventEncoding::Msgpack => {
+                // Human readable mode keeps UUIDs as strings, matching the JSON shape
+                let mut buf = Vec::new();
+                let mut serializer = rmp_serde::Serializer::new(&mut buf)
+                    .with_struct_map()
+                    .with_human_readable();
+                event.serialize(&mut serializer).ok()?;
+                Some(ws::Message::Binary(buf.into()))
+            }
+        }
+    }
+}
+
+// Decode a client event, text frames are JSON and binary frames are MessagePack
+fn decode_client_event(msg: &ws::Message) -> Option<Result<ClientEvent, String>> {
+    match msg {
+        ws::Message::Text(text) => {
+            Some(serde_json::from_str(text.as_str()).map_err(|e| e.to_string()))
+        }
+        ws::Message::Binary(bytes) => {
+            let mut deserializer =
+                rmp_serde::Deserializer::from_read_ref(bytes.as_ref()).with_human_readable();
+            Some(ClientEvent::deserialize(&mut deserializer).map_err(|e| e.to_string()))
+        }
+        _ => None,
+    }
+}
+
+// Parse a "pointing-poker.vN" or "pointing-poker.vN.msgpack" subprotocol
+fn parse_subprotocol(protocol: &str) -> Option<(u32, EventEncoding)> {
+    let rest = protocol.strip_prefix("pointing-poker.v")?;
+    let (version, encoding) = match rest.split_once('.') {
+        Some((version, "msgpack")) => (version, EventEncoding::Msgpack),
+        Some(_) => return None,
+        None => (rest, EventEncoding::Json),
+    };
+
+    Some((version.parse().ok()?, encoding))
+}
+
+// WebSocket subprotocol name for a protocol version and encoding
+fn subprotocol_for(version: u32, encoding: EventEncoding) -> String {
+    match encoding {
+        EventEncoding::Json => subprotocol_name(version),
+        EventEncoding::Msgpack => format!("{}.msgpack", subprotocol_name(version)),
+    }
+}
+
+// Pick the protocol version and encoding for a connection. Explicit query
+// parameters win, otherwise the highest supported subprotocol offered by the
+// client is used, falling back to the current version with JSON.
+fn negotiate(params: &WsParams, headers: &HeaderMap) -> Result<(u32, EventEncoding), AppError> {
+    if let Some(version) = params.protocol_version
+        && !SUPPORTED_PROTOCOL_VERSIONS.contains(&version)
+    {
+        return Err(AppError::BadRequest(
+            ErrorCode::UnsupportedProtocolVersion,
+            format!("Unsupported protocol version: {}", version),
+        ));
+    }
+
+    let offered: Vec<&str> = headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|protocol| protocol.starts_with("pointing-poker."))
+        .collect();
+
+    // Client preference order is kept among subprotocols of the same version
+    let mut candidates: Vec<(u32, EventEncoding)> = offered
+        .iter()
+        .filter_map(|protocol| parse_subprotocol(protocol))
+        .filter(|(version, _)| SUPPORTED_PROTOCOL_VERSIONS.contains(version))
+        .filter(|(version, _)| params.protocol_version.is_none_or(|v| v == *version))
+        .collect();
+    candidates.sort_by_key(|(version, _)| std::cmp::Reverse(*version));
+
+    let (version, encoding) = match candidates.first() {
+        Some(candidate) => *candidate,
+        None if offered.is_empty() || params.protocol_version.is_some() => (
+            params.protocol_version.unwrap_or(PROTOCOL_VERSION),
+            EventEncoding::Json,
+        ),
+        None => {
+            return Err(AppError::BadRequest(
+                ErrorCode::UnsupportedProtocol,
+                format!("Unsupported protocol, offered: {}", offered.join(", ")),
+            ));
+        }
+    };
+
+    Ok((version, params.encoding.unwrap_or(encoding)))
+}
+
+// Set or clear a user's status signal and let the room know
+async fn set_status(
+    state: &AppState,
+    room_id: &RoomId,
+    user_id: &UserId,
+    status: Option<UserStatus>,
+) {
+    let status = match status {
+        Some(status) => Some(state.statuses.set(room_id, user_id, status)),
+        None if state.statuses.clear(room_id, user_id) => None,
+        // Nothing to clear, nothing to announce
+        None => return,
+    };
+
+    state
+        .publish(
+            room_id,
+            RoomEvent::UserStatusChanged(UserStatusChangedPayload {
+                user_id: user_id.0,
+                status,
+            }),
+        )
+        .await;
+}
+
+// JSON Schema of all WebSocket events for client code generation
+#[utoipa::path(
+    get,
+    path = "/ws/schema",
+    tag = "websocket",
+    responses(
+        (status = 200, description = "JSON Schema of the WebSocket events", body = Object)
+    )
+)]
+pub async fn event_schema() -> Json<serde_json::Value> {
+    Json(crate::events::event_schema())
+}
 
 // WebSocket handler
+#[utoipa::path(
+    get,
+    path = "/ws/rooms/{room_id}/users/{user_id}",
+    tag = "websocket",
+    params(("room_id" = Uuid, Path, description = "Room ID"), ("user_id" = Uuid, Path, description = "User ID"), WsParams),
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol, events are described by /ws/schema"),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 403, description = "User not in room", body = ErrorResponse),
+        (status = 404, description = "Room not found", body = ErrorResponse),
+        (status = 409, description = "Room is archived", body = ErrorResponse),
+        (status = 503, description = "Server is shutting down", body = ErrorResponse)
+    )
+)]
+#[tracing::instrument(skip_all, fields(room_id = %room_id_str, user_id = %user_id_str))]
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     Path((room_id_str, user_id_str)): Path<(String, String)>,
+    Query(params): Query<WsParams>,
+    headers: HeaderMap,
     State(state): State<Arc<AppState>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
 ) -> Result<impl IntoResponse, AppError> {
+    // Clients should come back once the server has restarted
+    if state.shutdown.is_cancelled() {
+        return Err(AppError::ServiceUnavailable(
+            "Server is shutting down".to_string(),
+        ));
+    }
+
+    // Agree on the event protocol and encoding before doing any work
+    let (protocol_version, encoding) = negotiate(&params, &headers)?;
+
     // Parse IDs
-    let room_id = RoomId::from_string(&room_id_str)
-        .map_err(|_| AppError::BadRequest("Invalid room ID".to_string()))?;
+    let room_id = RoomId::from_string(&room_id_str).map_err(|_| {
+        AppError::invalid_field("roomId", ErrorCode::InvalidRoomId, "Invalid room ID")
+    })?;
 
-    let user_id = UserId::from_string(&user_id_str)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+    let user_id = UserId::from_string(&user_id_str).map_err(|_| {
+        AppError::invalid_field("userId", ErrorCode::InvalidUserId, "Invalid user ID")
+    })?;
 
-    // Verify room exists
-    let _room = state
-        .db
-        .get_room(&room_id)
-        .await?
-        .ok_or_else(|| AppError::NotFound("Room not found".to_string()))?;
+    // Verify room exists and isn't archived
+    let room = state.get_open_room(&room_id).await?;
 
     // Verify user is in this room
-    let users = state.db.get_users_for_room(&room_id).await?;
-    if !users.contains_key(&user_id) {
-        return Err(AppError::BadRequest("User not in room".to_string()));
+    if !room.users.contains_key(&user_id) {
+        return Err(AppError::Forbidden(
+            ErrorCode::UserNotInRoom,
+            "User not in room".to_string(),
+        ));
+    }
+
+    // Subscribe to the room's events, creating its channel if needed
+    let mut rx = state.events.subscribe(&room_id);
+
+    // Load missed events only after subscribing, so none fall in between
+    let mut replay = Vec::new();
+    if let Some(since) = params.since {
+        for logged in state.db.get_room_events(&room_id, since).await? {
+            match serde_json::from_str(&logged.event) {
+                Ok(event) => replay.push(SequencedEvent {
+                    seq: Some(logged.seq),
+                    event,
+                }),
+                Err(e) => tracing::warn!("Skipping undecodable logged event: {}", e),
+            }
+        }
     }
 
-    // Get or create a broadcast channel for this room
-    let tx = state.ensure_room_event_sender(&room_id);
-    let mut rx = tx.subscribe();
+    let welcome = SequencedEvent::from(RoomEvent::Welcome(WelcomePayload {
+        protocol_version,
+        room_id: room_id.0,
+        user_id: user_id.0,
+    }));
 
     // Return the WebSocket connection
-    Ok(ws.on_upgrade(move |socket| async move {
-        tracing::debug!("WebSocket connected: {}", addr);
-
-        // Split socket into sender and receiver
-        let (mut sender, mut receiver) = socket.split();
-
-        // Handle messages from client
-        let mut send_task = tokio::spawn(async move {
-            while let Ok(msg) = rx.recv().await {
-                // The event is already properly typed and structured
-                // Serialize the RoomEvent enum directly - it has the correct tag/content structure
-                if let Ok(serialized_event) = serde_json::to_string(&msg) {
-                    // Send serialized event to client
-                    if sender
-                        .send(ws::Message::Text(serialized_event.into()))
-                        .await
-                        .is_err()
+    let ws = ws.protocols([subprotocol_for(protocol_version, encoding)]);
+    let session = tracing::info_span!("websocket", %addr, protocol_version);
+    Ok(ws.on_upgrade(move |socket| {
+        async move {
+            tracing::debug!(
+                "WebSocket connected: {} (protocol v{}, {:?})",
+                addr,
+                protocol_version,
+                encoding
+            );
+
+            // Track the connection, closing older ones if the room allows a single session
+            let registration =
+                state
+                    .connections
+                    .register(&room_id, &user_id, room.settings.session_mode);
+            let connection_id = registration.connection_id;
+            let mut close_rx = registration.close_rx;
+            state.metrics.websocket_connections.inc();
+
+            if registration.came_online {
+                state
+                    .publish(
+                        &room_id,
+                        RoomEvent::PresenceChanged(PresenceChangedPayload {
+                            user_id: user_id.0,
+                            online: true,
+                        }),
+                    )
+                    .await;
+            }
+
+            // Split socket into sender and receiver
+            let (mut sender, mut receiver) = socket.split();
+
+            // Handle messages from client
+            let metrics = state.metrics.clone();
+            let shutdown = state.shutdown.clone();
+            let mut send_task = tokio::spawn(
+                async move {
+                    // Greet the client with the negotiated protocol version first
+                    if let Some(frame) = encoding.encode(&welcome)
+                        && sender.send(frame).await.is_err()
                     {
-                        break;
+                        return;
+                    }
+
+                    // Then catch up on what was missed
+                    let mut replayed_seq = params.since.unwrap_or(0);
+                    for event in &replay {
+                        if let Some(frame) = encoding.encode(event)
+                            && sender.send(frame).await.is_err()
+                        {
+                            return;
+                        }
+                        replayed_seq = event.seq.unwrap_or(replayed_seq);
+                    }
+
+                    loop {
+                        tokio::select! {
+                            msg = rx.recv() => {
+                                let msg = match msg {
+                                    Ok(msg) => msg,
+                                    // The client fell behind and missed events, it
+                                    // has to reconnect and replay them
+                                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                                        metrics.broadcast_lagged_events.inc_by(skipped);
+                                        break;
+                                    }
+                                    Err(broadcast::error::RecvError::Closed) => break,
+                                };
+
+                                // Skip live events that were already replayed
+                                if msg.seq.is_some_and(|seq| seq <= replayed_seq) {
+                                    continue;
+                                }
+
+                                // The event is already properly typed and structured
+                                // Encode the RoomEvent enum directly - it has the correct tag/content structure
+                                if let Some(frame) = encoding.encode(&msg) {
+                                    // Send encoded event to client
+                                    if sender.send(frame).await.is_err() {
+                                        break;
+                                    }
+                                }
+                            }
+                            reason = &mut close_rx => {
+                                // The server closed this connection, e.g. a newer session replaced it
+                                if let Ok(reason) = reason {
+                                    let _ = sender
+                                        .send(ws::Message::Close(Some(ws::CloseFrame {
+                                            code: reason.code,
+                                            reason: reason.reason.into(),
+                                        })))
+                                        .await;
+                                }
+                                break;
+                            }
+                            () = shutdown.cancelled() => {
+                                // Tell the client when to come back, then close as restarting
+                                let notice = SequencedEvent::from(RoomEvent::ServerShuttingDown(
+                                    ServerShuttingDownPayload {
+                                        reconnect_after_ms: RECONNECT_AFTER.as_millis() as u64,
+                                    },
+                                ));
+                                if let Some(frame) = encoding.encode(&notice) {
+                                    let _ = sender.send(frame).await;
+                                }
+                                let _ = sender
+                                    .send(ws::Message::Close(Some(ws::CloseFrame {
+                                        code: CLOSE_SERVICE_RESTART,
+                                        reason: "Server is restarting".into(),
+                                    })))
+                                    .await;
+                                break;
+                            }
+                        }
                     }
                 }
+                .in_current_span(),
+            );
+            // Handle messages from client (most communication happens through the REST API,
+            // only lightweight chat and reactions are accepted here)
+            let mut recv_task = tokio::spawn({
+                let (state, room_id, user_id) = (state.clone(), room_id.clone(), user_id.clone());
+                async move {
+                    while let Some(Ok(msg)) = receiver.next().await {
+                        let Some(event) = decode_client_event(&msg) else {
+                            continue;
+                        };
+
+                        let result = match event {
+                            Ok(ClientEvent::SendReaction(payload)) => {
+                                chat::react(&state, &room_id, &user_id, &payload.emoji)
+                                    .await
+                                    .map(|_| ())
+                            }
+                            Ok(ClientEvent::SendChatMessage(payload)) => {
+                                chat::chat(&state, &room_id, &user_id, &payload.text)
+                                    .await
+                                    .map(|_| ())
+                            }
+                            Ok(ClientEvent::SetStatus(payload)) => {
+                                set_status(&state, &room_id, &user_id, payload.status).await;
+                                Ok(())
+                            }
+                            Err(e) => {
+                                Err(AppError::BadRequest(ErrorCode::InvalidBody, e.to_string()))
+                            }
+                        };
+
+                        if let Err(e) = result {
+                            tracing::debug!("Rejected WebSocket message from {}: {}", addr, e);
+                        }
+                    }
+                }
+                .in_current_span()
+            });
+
+            // Wait for either task to finish
+            tokio::select! {
+                _ = &mut send_task => recv_task.abort(),
+                _ = &mut recv_task => send_task.abort(),
             }
-        });
-        // Handle messages from client (we mostly ignore them, as clients communicate through REST API)
-        let mut recv_task = tokio::spawn(async move {
-            while let Some(Ok(_msg)) = receiver.next().await {
-                // Most communication happens through REST API
-                // We can process custom WebSocket messages here if needed
+
+            state.metrics.websocket_connections.dec();
+
+            // Presence only changes once the user's last connection is gone
+            if state
+                .connections
+                .unregister(&room_id, &user_id, &connection_id)
+            {
+                state
+                    .publish(
+                        &room_id,
+                        RoomEvent::PresenceChanged(PresenceChangedPayload {
+                            user_id: user_id.0,
+                            online: false,
+                        }),
+                    )
+                    .await;
             }
-        });
 
-        // Wait for either task to finish
-        tokio::select! {
-            _ = &mut send_task => recv_task.abort(),
-            _ = &mut recv_task => send_task.abort(),
+            // Log disconnection
+            tracing::debug!("WebSocket client disconnected: {}", addr);
         }
-
-        // Log disconnection
-        tracing::debug!("WebSocket client disconnected: {}", addr);
+        .instrument(session)
     }))
 }
@@ -0,0 +1,88 @@
+use crate::error::AppError;
+use crate::state::AppState;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+// How often the remaining WebSockets are counted while waiting for them to close
+const CLOSE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Resolve on SIGINT or SIGTERM, once shutdown has started: open WebSockets are
+// told to reconnect later and closed, and new ones are turned away. Requests
+// are still accepted for `readiness_delay`, while readiness reports the
+// instance as not ready, so load balancers can stop routing to it first.
+pub async fn signal(state: Arc<AppState>, readiness_delay: Duration) {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = interrupt => {}
+        () = terminate => {}
+    }
+
+    tracing::info!(
+        "Shutting down, closing {} WebSockets",
+        state.connections.count()
+    );
+    state.shutdown.cancel();
+
+    tokio::time::sleep(readiness_delay).await;
+}
+
+// Run the server until it has shut down and its in-flight requests and
+// WebSockets have finished, giving them at most `drain_period` once it stops
+// accepting requests, then close the storage
+pub async fn serve_until_drained(
+    server: impl Future<Output = std::io::Result<()>>,
+    state: &AppState,
+    readiness_delay: Duration,
+    drain_period: Duration,
+) -> Result<(), AppError> {
+    let drained = async {
+        server.await?;
+
+        // WebSockets outlive the HTTP connections they were upgraded from
+        while state.connections.count() > 0 {
+            tokio::time::sleep(CLOSE_POLL_INTERVAL).await;
+        }
+
+        Ok::<_, std::io::Error>(())
+    };
+
+    let drain_period_over = async {
+        state.shutdown.cancelled().await;
+        tokio::time::sleep(readiness_delay + drain_period).await;
+    };
+
+    tokio::select! {
+        result = drained => result.map_err(|e| AppError::ServerStartupError(e.to_string()))?,
+        () = drain_period_over => tracing::warn!(
+            "Drain period over, dropping remaining requests and {} WebSockets",
+            state.connections.count()
+        ),
+    }
+
+    state.db.close().await;
+    tracing::info!("Shutdown complete");
+
+    Ok(())
+}
@@ -1,85 +1,144 @@
-use crate::db::Database;
-use crate::models::room::RoomId;
+use crate::config::{Config, EventBusKind};
+use crate::connections::ConnectionRegistry;
+use crate::db::{self, CachedDatabase, Database, MeteredDatabase, RoomCache};
+use crate::error::{AppError, ErrorCode};
+use crate::event_bus::{DatabaseEventBus, EventBus, InMemoryEventBus};
+use crate::events::{RoomClosedPayload, RoomEvent, SequencedEvent};
+use crate::metrics::Metrics;
+use crate::models::room::{Room, RoomId};
+use crate::rate_limit::RateLimiter;
+use crate::statuses::StatusRegistry;
 use std::sync::Arc;
-use tokio::sync::broadcast;
-
-// Type alias for room events broadcast
-pub type RoomEventSender = broadcast::Sender<RoomEvent>;
-
-// Define room events structure
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-#[serde(tag = "eventType", content = "payload")]
-pub enum RoomEvent {
-    UserJoined(crate::models::user::User),
-    UserLeft(UserLeftPayload),
-    VoteSubmitted(UserLeftPayload), // Reusing the simple UUID payload
-    VotesRevealed(VotesRevealedPayload),
-    VotesReset(VotesResetPayload),
-}
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct UserLeftPayload {
-    pub user_id: uuid::Uuid,
-}
+// Application state shared across handlers
+#[derive(Clone)]
+pub struct AppState {
+    // Storage backend, SQLite or in-memory depending on DATABASE_URL
+    pub db: Arc<dyn Database>,
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct VotesRevealedPayload {
-    pub votes: Vec<VoteWithUser>,
-}
+    // Real-time updates - fans room events out to every subscribed WebSocket
+    pub events: Arc<dyn EventBus>,
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct VoteWithUser {
-    pub user_id: uuid::Uuid,
-    pub value: String,
-}
+    // Open WebSocket connections per user, used for presence and session policy
+    pub connections: Arc<ConnectionRegistry>,
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct VotesResetPayload {}
+    // Ephemeral status signals (thinking, hand raised, away) per room
+    pub statuses: Arc<StatusRegistry>,
 
-// Application state shared across handlers
-#[derive(Clone)]
-pub struct AppState {
-    // SQLite database connection
-    pub db: Arc<Database>,
+    // Per-user limits on chat messages and reactions
+    pub chat_limiter: Arc<RateLimiter>,
+    pub reaction_limiter: Arc<RateLimiter>,
+
+    // Token guarding the admin endpoints, None if they are disabled
+    pub admin_token: Option<String>,
 
-    // Broadcasting channels for real-time updates - one per room
-    pub room_events: Arc<dashmap::DashMap<RoomId, RoomEventSender>>,
+    // Prometheus metrics served at /metrics
+    pub metrics: Arc<Metrics>,
+
+    // Cancelled once the server starts shutting down, which closes every
+    // WebSocket and turns new ones away
+    pub shutdown: CancellationToken,
+
+    // When this instance started, for reporting its uptime
+    pub started_at: Instant,
 }
 
 impl AppState {
-    pub async fn new() -> Result<Self, crate::error::AppError> {
-        // Initialize database connection
-        let db = Database::new().await?;
+    pub async fn new(config: &Config) -> Result<Self, AppError> {
+        let metrics = Arc::new(Metrics::new()?);
+
+        // Initialize the storage backend, with recently used rooms kept in memory
+        let room_cache = Arc::new(RoomCache::new());
+        let db: Arc<dyn Database> = Arc::new(CachedDatabase::new(
+            Arc::new(MeteredDatabase::new(
+                db::connect(&config.database_url, config.database_max_connections).await?,
+                metrics.clone(),
+            )),
+            room_cache.clone(),
+        ));
+
+        let statuses = Arc::new(StatusRegistry::new());
+
+        // Pick the event bus, the database one lets several instances share rooms
+        let events: Arc<dyn EventBus> = match config.event_bus {
+            EventBusKind::Memory => Arc::new(InMemoryEventBus::new(metrics.clone())),
+            EventBusKind::Database => {
+                let outbox = db.clone().as_outbox().ok_or_else(|| {
+                    AppError::ConfigError(
+                        "EVENT_BUS=database needs a shared DATABASE_URL, not in-memory storage"
+                            .to_string(),
+                    )
+                })?;
+                // Rooms changed by other instances must be reloaded, and
+                // statuses set on them followed
+                let statuses = statuses.clone();
+                DatabaseEventBus::start(
+                    outbox,
+                    config.event_bus_poll_interval,
+                    metrics.clone(),
+                    move |room_id, event| {
+                        room_cache.invalidate(room_id);
+                        statuses.apply_remote(room_id, event);
+                    },
+                )
+                .await?
+            }
+        };
 
         Ok(Self {
-            db: Arc::new(db),
-            room_events: Arc::new(dashmap::DashMap::new()),
+            db,
+            events,
+            connections: Arc::new(ConnectionRegistry::new()),
+            statuses,
+            chat_limiter: Arc::new(RateLimiter::new(5, Duration::from_secs(10))),
+            reaction_limiter: Arc::new(RateLimiter::new(10, Duration::from_secs(10))),
+            admin_token: config.admin_token.clone(),
+            metrics,
+            shutdown: CancellationToken::new(),
+            started_at: Instant::now(),
         })
     }
 
-    // Get event sender for a room
-    pub fn get_room_event_sender(&self, room_id: &RoomId) -> Option<RoomEventSender> {
-        self.room_events.get(room_id).map(|sender| sender.clone())
+    // Publish an event that isn't kept in the room's log, like presence or a
+    // reaction. Events changing the room are logged with `db::log_event` in
+    // the transaction making the change, and published with `publish_logged`.
+    pub async fn publish(&self, room_id: &RoomId, event: RoomEvent) {
+        debug_assert!(
+            !event.is_logged(),
+            "logged events are published with publish_logged"
+        );
+
+        self.events.publish(room_id, event.into()).await;
     }
 
-    // Create event sender for a room if it doesn't exist
-    pub fn ensure_room_event_sender(&self, room_id: &RoomId) -> RoomEventSender {
-        if let Some(sender) = self.room_events.get(room_id) {
-            sender.clone()
-        } else {
-            let (sender, _) = broadcast::channel(100);
-            self.room_events.insert(room_id.clone(), sender.clone());
-            sender
+    // Publish events logged by a transaction, once it has committed
+    pub async fn publish_logged(&self, room_id: &RoomId, events: Vec<SequencedEvent>) {
+        for event in events {
+            self.events.publish(room_id, event).await;
         }
     }
 
-    // Remove event sender for a room
-    pub fn remove_room_event_sender(&self, room_id: &RoomId) {
-        self.room_events.remove(room_id);
+    // Load a room that can still be changed, i.e. exists and isn't archived
+    pub async fn get_open_room(&self, room_id: &RoomId) -> Result<Room, AppError> {
+        let room = self.db.get_room(room_id).await?.ok_or_else(|| {
+            AppError::NotFound(ErrorCode::RoomNotFound, "Room not found".to_string())
+        })?;
+        db::check_open(&room)?;
+
+        Ok(room)
+    }
+
+    // Notify subscribers that the room is gone, then drop its event channel
+    pub async fn close_room(&self, room_id: &RoomId) {
+        self.publish(
+            room_id,
+            RoomEvent::RoomClosed(RoomClosedPayload { room_id: room_id.0 }),
+        )
+        .await;
+
+        self.events.remove_room(room_id);
+        self.statuses.remove_room(room_id);
     }
 }
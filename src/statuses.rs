@@ -0,0 +1,211 @@
+use crate::events::{RoomEvent, UserStatusChangedPayload};
+use crate::models::room::RoomId;
+use crate::models::status::{ActiveStatus, UserStatus};
+use crate::models::user::UserId;
+use crate::state::AppState;
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+// How often expired statuses are cleared and announced
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+// Current status signals of users, per room. Each instance keeps its own,
+// following the statuses set on other instances through the event bus.
+#[derive(Default)]
+pub struct StatusRegistry {
+    rooms: DashMap<RoomId, HashMap<UserId, RegisteredStatus>>,
+}
+
+#[derive(Clone)]
+struct RegisteredStatus {
+    active: ActiveStatus,
+    // Set on this instance, which announces when it expires
+    local: bool,
+}
+
+impl StatusRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Set or refresh a user's status
+    pub fn set(&self, room_id: &RoomId, user_id: &UserId, status: UserStatus) -> ActiveStatus {
+        let active = ActiveStatus {
+            status,
+            expires_at: OffsetDateTime::now_utc() + status.ttl(),
+        };
+
+        self.insert(room_id, user_id, active.clone(), true);
+
+        active
+    }
+
+    fn insert(&self, room_id: &RoomId, user_id: &UserId, active: ActiveStatus, local: bool) {
+        self.rooms
+            .entry(room_id.clone())
+            .or_default()
+            .insert(user_id.clone(), RegisteredStatus { active, local });
+    }
+
+    // Follow a change made on another instance, so every instance reports the
+    // same statuses. That instance announces the status expiring.
+    pub fn apply_remote(&self, room_id: &RoomId, event: &RoomEvent) {
+        match event {
+            RoomEvent::UserStatusChanged(payload) => {
+                let user_id = UserId(payload.user_id);
+                match &payload.status {
+                    Some(active) => self.insert(room_id, &user_id, active.clone(), false),
+                    None => {
+                        self.clear(room_id, &user_id);
+                    }
+                }
+            }
+            RoomEvent::UserLeft(payload) => {
+                self.clear(room_id, &UserId(payload.user_id));
+            }
+            RoomEvent::RoomClosed(_) => self.remove_room(room_id),
+            _ => {}
+        }
+    }
+
+    // Clear a user's status, returning true if they had one
+    pub fn clear(&self, room_id: &RoomId, user_id: &UserId) -> bool {
+        let Some(mut statuses) = self.rooms.get_mut(room_id) else {
+            return false;
+        };
+
+        let cleared = statuses.remove(user_id).is_some();
+        drop(statuses);
+
+        self.rooms
+            .remove_if(room_id, |_, statuses| statuses.is_empty());
+
+        cleared
+    }
+
+    // Unexpired statuses of everyone in the room
+    pub fn statuses(&self, room_id: &RoomId) -> HashMap<UserId, ActiveStatus> {
+        let now = OffsetDateTime::now_utc();
+
+        self.rooms
+            .get(room_id)
+            .map(|statuses| {
+                statuses
+                    .iter()
+                    .filter(|(_, registered)| registered.active.expires_at > now)
+                    .map(|(user_id, registered)| (user_id.clone(), registered.active.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Forget every status of a room that no longer exists
+    pub fn remove_room(&self, room_id: &RoomId) {
+        self.rooms.remove(room_id);
+    }
+
+    // Remove expired statuses, returning whose of those set on this instance
+    // they were
+    fn take_expired(&self) -> Vec<(RoomId, UserId)> {
+        let now = OffsetDateTime::now_utc();
+        let mut expired = Vec::new();
+
+        for mut entry in self.rooms.iter_mut() {
+            let room_id = entry.key().clone();
+            entry.value_mut().retain(|user_id, registered| {
+                if registered.active.expires_at <= now {
+                    if registered.local {
+                        expired.push((room_id.clone(), user_id.clone()));
+                    }
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        self.rooms.retain(|_, statuses| !statuses.is_empty());
+
+        expired
+    }
+}
+
+// Background task announcing statuses as they expire
+pub async fn sweep_expired(state: Arc<AppState>) {
+    let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+
+        for (room_id, user_id) in state.statuses.take_expired() {
+            state
+                .publish(
+                    &room_id,
+                    RoomEvent::UserStatusChanged(UserStatusChangedPayload {
+                        user_id: user_id.0,
+                        status: None,
+                    }),
+                )
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatusRegistry;
+    use crate::events::{RoomEvent, UserStatusChangedPayload};
+    use crate::models::room::RoomId;
+    use crate::models::status::{ActiveStatus, UserStatus};
+    use crate::models::user::UserId;
+    use std::time::Duration;
+    use time::OffsetDateTime;
+
+    fn expired(status: UserStatus) -> ActiveStatus {
+        ActiveStatus {
+            status,
+            expires_at: OffsetDateTime::now_utc() - Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn statuses_expire() {
+        let registry = StatusRegistry::new();
+        let room_id = RoomId::new();
+        let (thinking, away) = (UserId::new(), UserId::new());
+
+        registry.set(&room_id, &away, UserStatus::Away);
+        registry.insert(&room_id, &thinking, expired(UserStatus::Thinking), true);
+
+        // Expired statuses aren't reported, even before they are swept
+        let statuses = registry.statuses(&room_id);
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[&away].status, UserStatus::Away);
+
+        assert_eq!(registry.take_expired(), vec![(room_id.clone(), thinking)]);
+        assert!(registry.take_expired().is_empty());
+        assert_eq!(registry.statuses(&room_id).len(), 1);
+    }
+
+    #[test]
+    fn remote_statuses_expire_without_being_announced() {
+        let registry = StatusRegistry::new();
+        let room_id = RoomId::new();
+        let user_id = UserId::new();
+
+        registry.apply_remote(
+            &room_id,
+            &RoomEvent::UserStatusChanged(UserStatusChangedPayload {
+                user_id: user_id.0,
+                status: Some(expired(UserStatus::HandRaised)),
+            }),
+        );
+
+        // The instance that set it announces the expiry
+        assert!(registry.take_expired().is_empty());
+        assert!(registry.rooms.is_empty());
+    }
+}
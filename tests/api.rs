@@ -0,0 +1,680 @@
+// End-to-end tests of the HTTP and WebSocket API, run against every storage
+// backend. Set TEST_POSTGRES_URL to include Postgres.
+mod common;
+
+use common::{Backend, TestServer, WebSocket, next_event_of, send_event};
+use futures::StreamExt;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::{Error, Message};
+
+on_every_backend!(
+    voting_round,
+    only_members_can_vote_or_leave,
+    owner_leaving_archives_room,
+    events_are_logged_with_their_change,
+    websocket_receives_sequenced_events,
+    audit_log_records_changes,
+    export_and_import_with_fresh_ids,
+    legacy_routes_keep_their_contract,
+    invalid_requests_name_the_field,
+    chat_and_reactions_are_rate_limited,
+    websocket_negotiates_messagepack,
+    single_session_closes_older_connections,
+    status_signals_reach_the_room,
+    metrics_count_requests_and_votes,
+    readiness_checks_the_database,
+);
+
+async fn voting_round(server: TestServer) {
+    let (room, owner_id) = server.create_room("Sprint 1").await;
+    let room_id = room["id"].as_str().unwrap();
+    assert_eq!(room["state"], "voting");
+    assert_eq!(room["roundId"], 1);
+
+    let alice = server.join(room_id, "Alice").await;
+    let bob = server.join(room_id, "Bob").await;
+
+    assert_eq!(server.vote(room_id, &alice, "5").await.0, StatusCode::OK);
+    assert_eq!(server.vote(room_id, &bob, "13").await.0, StatusCode::OK);
+    // Changing a vote keeps when it was first cast
+    assert_eq!(server.vote(room_id, &bob, "hidden").await.0, StatusCode::OK);
+
+    let room = server.room(room_id).await;
+    assert_eq!(room["votes"][&alice]["vote"], "five");
+    assert_eq!(room["votes"][&bob]["vote"], "hidden");
+    assert_ne!(
+        room["votes"][&bob]["castAt"],
+        room["votes"][&bob]["updatedAt"]
+    );
+
+    let (status, body) = server
+        .post(
+            &format!("/v1/rooms/{}/reveal", room_id),
+            json!({ "userId": owner_id }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "reveal: {}", body);
+    assert_eq!(server.room(room_id).await["state"], "revealed");
+
+    let (status, body) = server.vote(room_id, &alice, "8").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["errorCode"], "VOTE_NOT_ALLOWED_WHEN_REVEALED");
+
+    let (status, body) = server
+        .post(
+            &format!("/v1/rooms/{}/reset", room_id),
+            json!({ "userId": owner_id }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "reset: {}", body);
+
+    let room = server.room(room_id).await;
+    assert_eq!(room["state"], "voting");
+    assert_eq!(room["roundId"], 2);
+    assert_eq!(room["votes"], json!({}));
+}
+
+async fn only_members_can_vote_or_leave(server: TestServer) {
+    let (room, owner_id) = server.create_room("Ours").await;
+    let room_id = room["id"].as_str().unwrap();
+    let (other_room, outsider) = server.create_room("Theirs").await;
+    let other_room_id = other_room["id"].as_str().unwrap();
+
+    let (status, body) = server.vote(room_id, &outsider, "3").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"]["errorCode"], "USER_NOT_IN_ROOM");
+
+    let (status, body) = server
+        .post(
+            &format!("/v1/rooms/{}/reveal", room_id),
+            json!({ "userId": outsider }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "reveal: {}", body);
+
+    // Leaving through another room's path leaves the user where they are
+    let (status, _) = server
+        .post(
+            &format!("/v1/rooms/{}/leave/{}", room_id, outsider),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let other_room = server.room(other_room_id).await;
+    assert!(other_room["users"].get(&outsider).is_some());
+    assert!(server.room(room_id).await["users"].get(&owner_id).is_some());
+}
+
+async fn owner_leaving_archives_room(server: TestServer) {
+    let (room, owner_id) = server.create_room("Short lived").await;
+    let room_id = room["id"].as_str().unwrap();
+
+    let (status, _) = server
+        .post(
+            &format!("/v1/rooms/{}/leave/{}", room_id, owner_id),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let room = server.room(room_id).await;
+    assert!(room["archivedAt"].is_string(), "room: {}", room);
+    assert_eq!(room["ownerId"], owner_id.as_str());
+
+    let (status, body) = server
+        .post(
+            &format!("/v1/rooms/{}/join", room_id),
+            json!({ "name": "Late" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["errorCode"], "ROOM_ARCHIVED");
+
+    let (status, body) = server
+        .post(
+            &format!("/v1/rooms/{}/reopen", room_id),
+            json!({ "userId": owner_id }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "reopen: {}", body);
+    assert!(body["archivedAt"].is_null());
+    server.join(room_id, "Late").await;
+}
+
+async fn events_are_logged_with_their_change(server: TestServer) {
+    let (room, owner_id) = server.create_room("Logged").await;
+    let room_id = room["id"].as_str().unwrap();
+    let alice = server.join(room_id, "Alice").await;
+    let check_path = format!("/v1/rooms/{}/history/check", room_id);
+    let (_, report) = server.get(&check_path).await;
+    let joined_seq = report["seq"].as_i64().unwrap();
+
+    server.vote(room_id, &alice, "2").await;
+    server
+        .post(
+            &format!("/v1/rooms/{}/reveal", room_id),
+            json!({ "userId": owner_id }),
+        )
+        .await;
+
+    let (status, report) = server.get(&check_path).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["consistent"], true, "report: {}", report);
+
+    // A rejected change leaves nothing in the log
+    let seq = report["seq"].as_i64().unwrap();
+    server.vote(room_id, &alice, "3").await;
+    let (_, report) = server.get(&check_path).await;
+    assert_eq!(report["seq"], seq);
+
+    // Before the vote, Alice had joined but not voted
+    let (status, history) = server
+        .get(&format!("/v1/rooms/{}/history?seq={}", room_id, joined_seq))
+        .await;
+    assert_eq!(status, StatusCode::OK, "history: {}", history);
+    assert_eq!(history["room"]["state"], "voting");
+    assert!(history["room"]["users"].get(&alice).is_some());
+    assert_eq!(history["room"]["votes"], json!({}));
+}
+
+async fn websocket_receives_sequenced_events(server: TestServer) {
+    let (room, owner_id) = server.create_room("Live").await;
+    let room_id = room["id"].as_str().unwrap();
+    let mut socket = server.connect(room_id, &owner_id).await;
+
+    let alice = server.join(room_id, "Alice").await;
+    let joined = next_event_of(&mut socket, "userJoined").await;
+    assert_eq!(joined["payload"]["id"], alice.as_str());
+    let joined_seq = joined["seq"].as_i64().expect("logged events carry seq");
+
+    server.vote(room_id, &alice, "8").await;
+    let voted = next_event_of(&mut socket, "voteSubmitted").await;
+    assert_eq!(voted["payload"]["userId"], alice.as_str());
+    assert_eq!(voted["seq"], joined_seq + 1);
+
+    // Reconnecting with the last seq seen replays what came after it
+    let mut socket = server
+        .connect_with(room_id, &alice, &format!("?since={}", joined_seq))
+        .await;
+    let replayed = next_event_of(&mut socket, "voteSubmitted").await;
+    assert_eq!(replayed["seq"], joined_seq + 1);
+}
+
+async fn audit_log_records_changes(server: TestServer) {
+    let (room, owner_id) = server.create_room("Audited").await;
+    let room_id = room["id"].as_str().unwrap();
+    let alice = server.join(room_id, "Alice").await;
+    server.vote(room_id, &alice, "1").await;
+
+    let (status, page) = server
+        .get(&format!("/v1/rooms/{}/audit?userId={}", room_id, owner_id))
+        .await;
+    assert_eq!(status, StatusCode::OK, "audit: {}", page);
+    let actions: Vec<_> = page["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, ["voteSubmitted", "userJoined", "roomCreated"]);
+
+    // Only the owner can read it
+    let (status, _) = server
+        .get(&format!("/v1/rooms/{}/audit?userId={}", room_id, alice))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Filtering by time compares instants, whatever the offset
+    let (status, page) = server
+        .get(&format!(
+            "/v1/rooms/{}/audit?userId={}&since=2000-01-01T05:00:00%2B05:00",
+            room_id, owner_id
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["events"].as_array().unwrap().len(), 3);
+}
+
+async fn export_and_import_with_fresh_ids(server: TestServer) {
+    // A name that looks like an ID is kept as it is
+    let name = "0b6e2a8e-6c43-4c2b-9a5e-1f1d2c3b4a59";
+    let (room, owner_id) = server.create_room(name).await;
+    let room_id = room["id"].as_str().unwrap();
+    server.vote(room_id, &owner_id, "8").await;
+    server
+        .post(
+            &format!("/v1/rooms/{}/reset", room_id),
+            json!({ "userId": owner_id }),
+        )
+        .await;
+    server.vote(room_id, &owner_id, "21").await;
+
+    let (status, archive) = server
+        .admin_get(&format!("/v1/admin/rooms/{}/export", room_id))
+        .await;
+    assert_eq!(status, StatusCode::OK, "export: {}", archive);
+    let votes = archive["rooms"][0]["votes"].clone();
+    assert_eq!(votes[0]["roundId"], 1, "votes: {}", votes);
+    assert_eq!(votes[0]["vote"], "eight");
+    assert_eq!(votes[1]["roundId"], 2);
+    assert_eq!(votes[1]["vote"], "twentyOne");
+
+    // The room still exists, so importing it as it is conflicts
+    let (status, _) = server
+        .admin_post("/v1/admin/rooms/import", archive.clone())
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, report) = server
+        .admin_post("/v1/admin/rooms/import?remapIds=true", archive)
+        .await;
+    assert_eq!(status, StatusCode::OK, "import: {}", report);
+    let copy_id = report["rooms"][0]["roomId"].as_str().unwrap();
+    assert_ne!(copy_id, room_id);
+
+    let copy = server.room(copy_id).await;
+    assert_eq!(copy["name"], name);
+    let copy_owner = copy["ownerId"].as_str().unwrap();
+    assert_ne!(copy_owner, owner_id);
+    assert_eq!(copy["votes"][copy_owner]["vote"], "twentyOne");
+
+    // Earlier rounds come along too
+    let (status, copy_archive) = server
+        .admin_get(&format!("/v1/admin/rooms/{}/export", copy_id))
+        .await;
+    assert_eq!(status, StatusCode::OK, "export: {}", copy_archive);
+    let copy_votes = &copy_archive["rooms"][0]["votes"];
+    assert_eq!(copy_votes.as_array().unwrap().len(), 2);
+    for (copied, original) in copy_votes
+        .as_array()
+        .unwrap()
+        .iter()
+        .zip(votes.as_array().unwrap())
+    {
+        assert_eq!(copied["userId"], copy_owner);
+        assert_eq!(copied["roundId"], original["roundId"]);
+        assert_eq!(copied["vote"], original["vote"]);
+        assert_eq!(copied["castAt"], original["castAt"]);
+    }
+
+    let (_, report) = server
+        .get(&format!("/v1/rooms/{}/history/check", copy_id))
+        .await;
+    assert_eq!(report["consistent"], true, "report: {}", report);
+}
+
+async fn legacy_routes_keep_their_contract(server: TestServer) {
+    let (room, owner_id) = server.create_room("Old client").await;
+    let room_id = room["id"].as_str().unwrap();
+
+    let response = server
+        .client()
+        .post(server.url(&format!("/rooms/{}/vote", room_id)))
+        .json(&json!({ "user_id": owner_id, "vote": { "value": "21" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["deprecation"], "true");
+    assert_eq!(
+        response.headers()["link"],
+        format!("</v1/rooms/{}/vote>; rel=\"successor-version\"", room_id).as_str()
+    );
+
+    // Rooms and users come back exactly as they did before /v1
+    let (status, room) = server.get(&format!("/rooms/{}", room_id)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        room,
+        json!({
+            "id": room_id,
+            "name": "Old client",
+            "state": "Voting",
+            "users": {
+                &owner_id: { "id": owner_id, "name": "Owner", "isObserver": false },
+            },
+            "votes": { &owner_id: "TwentyOne" },
+            "ownerId": owner_id,
+        })
+    );
+
+    let (status, user) = server
+        .post(
+            &format!("/rooms/{}/join", room_id),
+            json!({ "name": "Alice" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        user,
+        json!({ "id": user["id"], "name": "Alice", "isObserver": false })
+    );
+}
+
+async fn invalid_requests_name_the_field(server: TestServer) {
+    let (room, _) = server.create_room("Strict").await;
+    let room_id = room["id"].as_str().unwrap();
+
+    let (status, body) = server
+        .post(
+            &format!("/v1/rooms/{}/vote", room_id),
+            json!({ "value": 5 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["errorCode"], "INVALID_BODY");
+    let fields: Vec<_> = body["error"]["details"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|detail| detail["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["value"]);
+
+    let (status, body) = server.get("/v1/rooms/not-a-room").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["details"][0]["field"], "roomId");
+
+    let response = server
+        .client()
+        .post(server.url("/v1/rooms"))
+        .body("{}")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+async fn chat_and_reactions_are_rate_limited(server: TestServer) {
+    let (room, owner_id) = server.create_room("Chatty").await;
+    let room_id = room["id"].as_str().unwrap();
+    let alice = server.join(room_id, "Alice").await;
+    let chat_path = format!("/v1/rooms/{}/chat", room_id);
+    let reactions_path = format!("/v1/rooms/{}/reactions", room_id);
+
+    // 5 chat messages per user every 10 seconds
+    for i in 0..5 {
+        let (status, body) = server
+            .post(
+                &chat_path,
+                json!({ "userId": owner_id, "text": format!("Message {}", i) }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "chat: {}", body);
+    }
+    let (status, body) = server
+        .post(
+            &chat_path,
+            json!({ "userId": owner_id, "text": "One too many" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["error"]["errorCode"], "RATE_LIMITED");
+
+    // Each user has their own limit, and rejected messages aren't kept
+    let (status, _) = server
+        .post(&chat_path, json!({ "userId": alice, "text": "My turn" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, history) = server.get(&chat_path).await;
+    assert_eq!(history.as_array().unwrap().len(), 6);
+
+    // 10 reactions per user every 10 seconds
+    for _ in 0..10 {
+        let (status, body) = server
+            .post(
+                &reactions_path,
+                json!({ "userId": owner_id, "emoji": "🎉" }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "reaction: {}", body);
+    }
+    let (status, body) = server
+        .post(
+            &reactions_path,
+            json!({ "userId": owner_id, "emoji": "🎉" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["error"]["errorCode"], "RATE_LIMITED");
+}
+
+async fn websocket_negotiates_messagepack(server: TestServer) {
+    let (room, owner_id) = server.create_room("Compact").await;
+    let room_id = room["id"].as_str().unwrap();
+
+    // Offered as a subprotocol, MessagePack is picked over JSON
+    let mut request = server
+        .ws_url(room_id, &owner_id, "")
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        "sec-websocket-protocol",
+        "pointing-poker.v1.msgpack, pointing-poker.v1"
+            .parse()
+            .unwrap(),
+    );
+    let (mut socket, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+    assert_eq!(
+        response.headers()["sec-websocket-protocol"],
+        "pointing-poker.v1.msgpack"
+    );
+
+    // Events keep their JSON shape, sent as binary frames
+    let welcome = next_binary_event(&mut socket).await;
+    assert_eq!(welcome["eventType"], "welcome");
+    assert_eq!(welcome["payload"]["userId"], owner_id.as_str());
+    server.vote(room_id, &owner_id, "5").await;
+    let voted = next_binary_event_of(&mut socket, "voteSubmitted").await;
+    assert!(voted["seq"].is_u64(), "event: {}", voted);
+
+    // Or asked for in the query string
+    let url = server.ws_url(room_id, &owner_id, "?encoding=msgpack");
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    assert_eq!(next_binary_event(&mut socket).await["eventType"], "welcome");
+
+    // Offering only unknown versions fails the handshake
+    let mut request = server
+        .ws_url(room_id, &owner_id, "")
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        "sec-websocket-protocol",
+        "pointing-poker.v99".parse().unwrap(),
+    );
+    match tokio_tungstenite::connect_async(request).await {
+        Err(Error::Http(response)) => assert_eq!(response.status(), StatusCode::BAD_REQUEST),
+        other => panic!(
+            "expected the handshake to fail, got {:?}",
+            other.map(|_| ())
+        ),
+    }
+}
+
+// The next MessagePack event of the given type, skipping presence and status signals
+async fn next_binary_event_of(socket: &mut WebSocket, event_type: &str) -> Value {
+    loop {
+        let event = next_binary_event(socket).await;
+        if event["eventType"] == event_type {
+            return event;
+        }
+    }
+}
+
+// The next MessagePack event on the socket, as JSON
+async fn next_binary_event(socket: &mut WebSocket) -> Value {
+    let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+        .await
+        .expect("timed out waiting for an event")
+        .expect("WebSocket closed")
+        .expect("WebSocket error");
+
+    match message {
+        Message::Binary(bytes) => rmp_serde::from_slice(&bytes).expect("event is MessagePack"),
+        other => panic!("expected a binary frame, got {:?}", other),
+    }
+}
+
+async fn single_session_closes_older_connections(server: TestServer) {
+    let (room, owner_id) = server.create_room("Tabs").await;
+    let room_id = room["id"].as_str().unwrap();
+
+    // By default every tab stays connected, and the user counts as online once
+    let mut first = server.connect(room_id, &owner_id).await;
+    let mut second = server.connect(room_id, &owner_id).await;
+    let room = server.room(room_id).await;
+    assert_eq!(room["onlineUserIds"], json!([owner_id]));
+    server.vote(room_id, &owner_id, "3").await;
+    next_event_of(&mut first, "voteSubmitted").await;
+    next_event_of(&mut second, "voteSubmitted").await;
+
+    let (status, body) = server
+        .patch(
+            &format!("/v1/rooms/{}/settings", room_id),
+            json!({ "userId": owner_id, "sessionMode": "singleSession" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "settings: {}", body);
+
+    // A new connection now closes the older ones
+    let mut third = server.connect(room_id, &owner_id).await;
+    assert_eq!(next_close(&mut first).await.code, 4001.into());
+    assert_eq!(next_close(&mut second).await.code, 4001.into());
+    server.vote(room_id, &owner_id, "5").await;
+    next_event_of(&mut third, "voteSubmitted").await;
+    assert_eq!(
+        server.room(room_id).await["onlineUserIds"],
+        json!([owner_id])
+    );
+}
+
+// The close frame the server ends the connection with, skipping any events
+async fn next_close(socket: &mut WebSocket) -> CloseFrame {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("timed out waiting for the connection to close")
+            .expect("WebSocket closed without a close frame")
+            .expect("WebSocket error");
+
+        if let Message::Close(frame) = message {
+            return frame.expect("close frame");
+        }
+    }
+}
+
+async fn status_signals_reach_the_room(server: TestServer) {
+    let (room, owner_id) = server.create_room("Signals").await;
+    let room_id = room["id"].as_str().unwrap();
+    let alice = server.join(room_id, "Alice").await;
+    let mut owner_socket = server.connect(room_id, &owner_id).await;
+    let mut alice_socket = server.connect(room_id, &alice).await;
+
+    send_event(
+        &mut alice_socket,
+        json!({ "eventType": "setStatus", "payload": { "status": "thinking" } }),
+    )
+    .await;
+    let event = next_event_of(&mut owner_socket, "userStatusChanged").await;
+    assert_eq!(event["payload"]["userId"], alice.as_str());
+    assert_eq!(event["payload"]["status"]["status"], "thinking");
+    assert!(event.get("seq").is_none(), "status signals aren't logged");
+
+    // Thinking lasts a minute unless refreshed
+    let expires_at = event["payload"]["status"]["expiresAt"].as_str().unwrap();
+    let lasts = OffsetDateTime::parse(expires_at, &Rfc3339).unwrap() - OffsetDateTime::now_utc();
+    assert!(lasts > time::Duration::seconds(50) && lasts <= time::Duration::seconds(60));
+
+    let room = server.room(room_id).await;
+    assert_eq!(room["userStatuses"][&alice]["status"], "thinking");
+
+    send_event(
+        &mut alice_socket,
+        json!({ "eventType": "setStatus", "payload": { "status": null } }),
+    )
+    .await;
+    let event = next_event_of(&mut owner_socket, "userStatusChanged").await;
+    assert!(event["payload"]["status"].is_null(), "event: {}", event);
+    assert_eq!(server.room(room_id).await["userStatuses"], json!({}));
+}
+
+async fn metrics_count_requests_and_votes(server: TestServer) {
+    let (room, owner_id) = server.create_room("Measured").await;
+    let room_id = room["id"].as_str().unwrap();
+    let _socket = server.connect(room_id, &owner_id).await;
+    server.vote(room_id, &owner_id, "8").await;
+    server.vote(room_id, &owner_id, "13").await;
+    server
+        .post(
+            &format!("/v1/rooms/{}/reveal", room_id),
+            json!({ "userId": owner_id }),
+        )
+        .await;
+
+    let (status, metrics) = server.get("/metrics").await;
+    assert_eq!(status, StatusCode::OK);
+    let metrics = metrics.as_str().expect("metrics are text");
+
+    // Requests are counted by route pattern rather than by path
+    assert_eq!(
+        metric(
+            metrics,
+            r#"pointing_poker_http_requests_total{method="POST",route="/v1/rooms/{room_id}/vote",status="200"}"#
+        ),
+        2.0
+    );
+    assert_eq!(metric(metrics, "pointing_poker_votes_total"), 2.0);
+    assert_eq!(metric(metrics, "pointing_poker_reveals_total"), 1.0);
+    assert_eq!(metric(metrics, "pointing_poker_websocket_connections"), 1.0);
+    assert_eq!(metric(metrics, "pointing_poker_active_rooms"), 1.0);
+    assert!(
+        metric(
+            metrics,
+            r#"pointing_poker_db_query_duration_seconds_count{method="begin"}"#
+        ) >= 3.0
+    );
+}
+
+// Value of the sample with exactly this name and labels
+fn metric(metrics: &str, sample: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(sample)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("no {} in:\n{}", sample, metrics))
+        .parse()
+        .expect("sample value")
+}
+
+async fn readiness_checks_the_database(server: TestServer) {
+    let (status, live) = server.get("/health/live").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(live["status"], "ok");
+
+    let (room, owner_id) = server.create_room("Ready").await;
+    let _socket = server
+        .connect(room["id"].as_str().unwrap(), &owner_id)
+        .await;
+
+    let (status, ready) = server.get("/health/ready").await;
+    assert_eq!(status, StatusCode::OK, "ready: {}", ready);
+    assert_eq!(ready["status"], "ready");
+    assert_eq!(ready["shuttingDown"], false);
+    assert_eq!(ready["database"]["ok"], true);
+    assert_eq!(ready["websocketConnections"], 1);
+    assert_eq!(ready["build"]["name"], "pointing-poker-api");
+
+    // Only storage with migrations has a schema version
+    let schema_version = &ready["database"]["schemaVersion"];
+    match server.backend() {
+        Backend::Memory => assert!(schema_version.is_null(), "ready: {}", ready),
+        Backend::Sqlite | Backend::Postgres => {
+            assert!(schema_version.as_i64().unwrap() > 0, "ready: {}", ready)
+        }
+    }
+}
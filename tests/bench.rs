@@ -0,0 +1,91 @@
+// Request latency in a 50-user room, on each backend. These are benchmarks
+// rather than checks, so they're ignored by default. Run them against a
+// release build to see numbers worth comparing:
+//
+//   cargo test --release --test bench -- --ignored --nocapture --test-threads 1
+mod common;
+
+use common::{Backend, TestServer};
+use reqwest::StatusCode;
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+const ROOM_SIZE: usize = 50;
+const SAMPLES: usize = 500;
+const CARDS: &[&str] = &["1", "2", "3", "5", "8"];
+
+#[tokio::test]
+#[ignore = "benchmark"]
+async fn memory_room_with_50_users() {
+    bench(Backend::Memory).await;
+}
+
+#[tokio::test]
+#[ignore = "benchmark"]
+async fn sqlite_room_with_50_users() {
+    bench(Backend::Sqlite).await;
+}
+
+#[tokio::test]
+#[ignore = "benchmark"]
+async fn postgres_room_with_50_users() {
+    bench(Backend::Postgres).await;
+}
+
+async fn bench(backend: Backend) {
+    let server = TestServer::start(backend).await;
+
+    let (room, owner_id) = server.create_room("Bench").await;
+    let room_id = room["id"].as_str().unwrap();
+    let mut users = Vec::with_capacity(ROOM_SIZE - 1);
+    for i in 1..ROOM_SIZE {
+        users.push(server.join(room_id, &format!("User {}", i)).await);
+    }
+
+    let room_path = format!("/v1/rooms/{}", room_id);
+    let mut timings = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        let start = Instant::now();
+        let (status, _) = server.get(&room_path).await;
+        timings.push(start.elapsed());
+        assert_eq!(status, StatusCode::OK);
+    }
+    report(backend, "get room", timings);
+
+    let mut timings = Vec::with_capacity(SAMPLES);
+    for i in 0..SAMPLES {
+        let user_id = &users[i % users.len()];
+        let start = Instant::now();
+        let (status, _) = server.vote(room_id, user_id, CARDS[i % CARDS.len()]).await;
+        timings.push(start.elapsed());
+        assert_eq!(status, StatusCode::OK);
+    }
+    report(backend, "vote", timings);
+
+    let reveal_path = format!("/v1/rooms/{}/reveal", room_id);
+    let reset_path = format!("/v1/rooms/{}/reset", room_id);
+    let owner = json!({ "userId": owner_id });
+    let mut timings = Vec::with_capacity(SAMPLES / 5);
+    for _ in 0..SAMPLES / 5 {
+        let start = Instant::now();
+        let (revealed, _) = server.post(&reveal_path, owner.clone()).await;
+        let (reset, _) = server.post(&reset_path, owner.clone()).await;
+        timings.push(start.elapsed());
+        assert_eq!((revealed, reset), (StatusCode::OK, StatusCode::OK));
+    }
+    report(backend, "reveal and reset", timings);
+}
+
+// Print the percentiles of the request timings
+fn report(backend: Backend, name: &str, mut timings: Vec<Duration>) {
+    timings.sort();
+    let percentile = |p: usize| timings[(timings.len() * p / 100).min(timings.len() - 1)];
+    println!(
+        "{:?} {:<16} p50 {:>8.2?}  p95 {:>8.2?}  p99 {:>8.2?}",
+        backend,
+        name,
+        percentile(50),
+        percentile(95),
+        percentile(99)
+    );
+}
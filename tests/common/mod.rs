@@ -0,0 +1,381 @@
+// Harness shared by the integration tests. Each test starts the server binary
+// on a free port against one storage backend and talks to it over HTTP and
+// WebSockets, like a client would.
+#![allow(dead_code)]
+
+use futures::{SinkExt, StreamExt};
+use reqwest::{Client, StatusCode};
+use serde_json::{Value, json};
+use std::fs::{self, File};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+// Environment variable with the Postgres database to test against. The
+// Postgres tests are ignored when it isn't set at build time, see build.rs.
+pub const POSTGRES_URL_VAR: &str = "TEST_POSTGRES_URL";
+
+// Bearer token the admin routes are started with
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
+// How long the server gets to start answering
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Memory,
+    Sqlite,
+    Postgres,
+}
+
+// Runs `$test(server)` once per backend, each in a module named after it so
+// failures say which backend broke, e.g. `sqlite::voting_round`
+#[macro_export]
+macro_rules! on_every_backend {
+    ($($test:ident),* $(,)?) => {
+        $crate::on_backend!(memory, Backend::Memory, $($test),*);
+        $crate::on_backend!(sqlite, Backend::Sqlite, $($test),*);
+        $crate::on_backend!(postgres, Backend::Postgres, $($test),*);
+    };
+}
+
+// Runs `$test(server)` on one backend, optionally with extra environment
+// variables, e.g. `on_backend!(sqlite, Backend::Sqlite, env = VARS, test)`
+#[macro_export]
+macro_rules! on_backend {
+    ($module:ident, Backend::$backend:ident, env = $env:expr, $($test:ident),* $(,)?) => {
+        mod $module {
+            use super::*;
+
+            $(
+                $crate::backend_test!($backend, $env, $test);
+            )*
+        }
+    };
+    ($module:ident, Backend::$backend:ident, $($test:ident),* $(,)?) => {
+        $crate::on_backend!($module, Backend::$backend, env = &[], $($test),*);
+    };
+}
+
+// One test of `on_backend!`. Postgres tests are ignored unless there's a
+// database to run them against.
+#[macro_export]
+macro_rules! backend_test {
+    (Postgres, $env:expr, $test:ident) => {
+        #[tokio::test]
+        #[cfg_attr(not(postgres_tests), ignore = "set TEST_POSTGRES_URL to run")]
+        async fn $test() {
+            super::$test(TestServer::start_with(Backend::Postgres, $env).await).await;
+        }
+    };
+    ($backend:ident, $env:expr, $test:ident) => {
+        #[tokio::test]
+        async fn $test() {
+            super::$test(TestServer::start_with(Backend::$backend, $env).await).await;
+        }
+    };
+}
+
+// A running server, killed when dropped
+pub struct TestServer {
+    process: Child,
+    base_url: String,
+    ws_url: String,
+    dir: PathBuf,
+    client: Client,
+    // What it was started with, for starting siblings
+    backend: Backend,
+    database_url: String,
+    env: Vec<(String, String)>,
+}
+
+impl TestServer {
+    // Start a server on the backend
+    pub async fn start(backend: Backend) -> Self {
+        Self::start_with(backend, &[]).await
+    }
+
+    // Start a server with extra environment variables. Postgres fails without
+    // a test database configured.
+    pub async fn start_with(backend: Backend, env: &[(&str, &str)]) -> Self {
+        let dir = test_dir();
+        let database_url = match backend {
+            Backend::Memory => "memory".to_string(),
+            Backend::Sqlite => format!("sqlite:{}", dir.join("test.db").display()),
+            Backend::Postgres => std::env::var(POSTGRES_URL_VAR)
+                .unwrap_or_else(|_| panic!("{} isn't set", POSTGRES_URL_VAR)),
+        };
+        let env = env
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        Self::spawn(backend, dir, database_url, env).await
+    }
+
+    // Start another instance on the same database and environment
+    pub async fn start_sibling(&self) -> Self {
+        Self::spawn(
+            self.backend,
+            test_dir(),
+            self.database_url.clone(),
+            self.env.clone(),
+        )
+        .await
+    }
+
+    async fn spawn(
+        backend: Backend,
+        dir: PathBuf,
+        database_url: String,
+        env: Vec<(String, String)>,
+    ) -> Self {
+        let port = free_port();
+        let log = File::create(dir.join("server.log")).expect("create server log");
+        let process = Command::new(env!("CARGO_BIN_EXE_pointing-poker-api"))
+            .current_dir(&dir)
+            .env("PORT", port.to_string())
+            .env("DATABASE_URL", &database_url)
+            .env("ADMIN_TOKEN", ADMIN_TOKEN)
+            .env("SHUTDOWN_DRAIN_PERIOD_SECS", "0")
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .stdout(log.try_clone().expect("clone server log"))
+            .stderr(log)
+            .spawn()
+            .expect("start server");
+
+        let server = Self {
+            process,
+            base_url: format!("http://127.0.0.1:{}", port),
+            ws_url: format!("ws://127.0.0.1:{}", port),
+            dir,
+            client: Client::new(),
+            backend,
+            database_url,
+            env,
+        };
+        server.wait_until_live().await;
+
+        server
+    }
+
+    async fn wait_until_live(&self) {
+        let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+        while tokio::time::Instant::now() < deadline {
+            if let Ok(response) = self.client.get(self.url("/health")).send().await
+                && response.status().is_success()
+            {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("Server didn't start, log:\n{}", self.log());
+    }
+
+    // Send SIGTERM, as a deploy would, to start a graceful shutdown
+    pub fn terminate(&self) {
+        let status = Command::new("kill")
+            .arg("-TERM")
+            .arg(self.process.id().to_string())
+            .status()
+            .expect("send SIGTERM");
+        assert!(status.success(), "kill failed");
+    }
+
+    // Wait for the server to exit on its own
+    pub async fn wait_for_exit(&mut self) -> ExitStatus {
+        let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+        while tokio::time::Instant::now() < deadline {
+            if let Some(status) = self.process.try_wait().expect("check server process") {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("Server didn't exit, log:\n{}", self.log());
+    }
+
+    // Everything the server has logged so far
+    pub fn log(&self) -> String {
+        fs::read_to_string(self.dir.join("server.log")).unwrap_or_default()
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub async fn get(&self, path: &str) -> (StatusCode, Value) {
+        read(self.client.get(self.url(path)).send().await).await
+    }
+
+    pub async fn post(&self, path: &str, body: Value) -> (StatusCode, Value) {
+        read(self.client.post(self.url(path)).json(&body).send().await).await
+    }
+
+    pub async fn patch(&self, path: &str, body: Value) -> (StatusCode, Value) {
+        read(self.client.patch(self.url(path)).json(&body).send().await).await
+    }
+
+    pub async fn admin_get(&self, path: &str) -> (StatusCode, Value) {
+        let request = self.client.get(self.url(path)).bearer_auth(ADMIN_TOKEN);
+        read(request.send().await).await
+    }
+
+    pub async fn admin_post(&self, path: &str, body: Value) -> (StatusCode, Value) {
+        let request = self
+            .client
+            .post(self.url(path))
+            .bearer_auth(ADMIN_TOKEN)
+            .json(&body);
+        read(request.send().await).await
+    }
+
+    // Create a room with an owner, returning the room and the owner's ID
+    pub async fn create_room(&self, name: &str) -> (Value, String) {
+        let (status, room) = self
+            .post("/v1/rooms", json!({ "name": name, "creatorName": "Owner" }))
+            .await;
+        assert_eq!(status, StatusCode::OK, "create room: {}", room);
+        let owner_id = room["ownerId"].as_str().expect("owner ID").to_string();
+
+        (room, owner_id)
+    }
+
+    // Join the room, returning the new user's ID
+    pub async fn join(&self, room_id: &str, name: &str) -> String {
+        let (status, user) = self
+            .post(
+                &format!("/v1/rooms/{}/join", room_id),
+                json!({ "name": name }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "join room: {}", user);
+
+        user["id"].as_str().expect("user ID").to_string()
+    }
+
+    pub async fn vote(&self, room_id: &str, user_id: &str, value: &str) -> (StatusCode, Value) {
+        self.post(
+            &format!("/v1/rooms/{}/vote", room_id),
+            json!({ "userId": user_id, "value": value }),
+        )
+        .await
+    }
+
+    pub async fn room(&self, room_id: &str) -> Value {
+        let (status, room) = self.get(&format!("/v1/rooms/{}", room_id)).await;
+        assert_eq!(status, StatusCode::OK, "get room: {}", room);
+        room
+    }
+
+    // Open the user's WebSocket and skip past the welcome event
+    pub async fn connect(&self, room_id: &str, user_id: &str) -> WebSocket {
+        self.connect_with(room_id, user_id, "").await
+    }
+
+    // URL of the user's WebSocket with a query string, e.g. "?since=3"
+    pub fn ws_url(&self, room_id: &str, user_id: &str, query: &str) -> String {
+        format!(
+            "{}/v1/ws/rooms/{}/users/{}{}",
+            self.ws_url, room_id, user_id, query
+        )
+    }
+
+    // Open the user's WebSocket with a query string, e.g. "?since=3"
+    pub async fn connect_with(&self, room_id: &str, user_id: &str, query: &str) -> WebSocket {
+        let url = self.ws_url(room_id, user_id, query);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .expect("connect WebSocket");
+
+        let welcome = next_event(&mut socket).await;
+        assert_eq!(welcome["eventType"], "welcome", "first event: {}", welcome);
+
+        socket
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// The next JSON event on the socket, failing the test if none arrives
+pub async fn next_event(socket: &mut WebSocket) -> Value {
+    let deadline = Duration::from_secs(5);
+    loop {
+        let message = tokio::time::timeout(deadline, socket.next())
+            .await
+            .expect("timed out waiting for an event")
+            .expect("WebSocket closed")
+            .expect("WebSocket error");
+
+        if let Message::Text(text) = message {
+            return serde_json::from_str(&text).expect("event is JSON");
+        }
+    }
+}
+
+// Send a client event, e.g. a chat message or a status signal
+pub async fn send_event(socket: &mut WebSocket, event: Value) {
+    socket
+        .send(Message::Text(event.to_string().into()))
+        .await
+        .expect("send event");
+}
+
+// The next event of the given type, skipping presence and status signals
+pub async fn next_event_of(socket: &mut WebSocket, event_type: &str) -> Value {
+    loop {
+        let event = next_event(socket).await;
+        if event["eventType"] == event_type {
+            return event;
+        }
+    }
+}
+
+async fn read(response: reqwest::Result<reqwest::Response>) -> (StatusCode, Value) {
+    let response = response.expect("send request");
+    let status = response.status();
+    let text = response.text().await.expect("read response body");
+    let body = if text.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(&text).unwrap_or(Value::String(text))
+    };
+
+    (status, body)
+}
+
+// A fresh directory for a server's working files
+fn test_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pointing-poker-test-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).expect("create test directory");
+    dir
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .expect("find a free port")
+}
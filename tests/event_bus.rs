@@ -0,0 +1,87 @@
+// Two server instances sharing a database through EVENT_BUS=database. The
+// in-memory backend has nothing to share, so only the SQL backends run these.
+mod common;
+
+use common::{Backend, TestServer, next_event_of, send_event};
+use futures::future::join_all;
+use reqwest::StatusCode;
+use serde_json::json;
+use std::collections::HashSet;
+
+const SHARED_BUS: &[(&str, &str)] = &[
+    ("EVENT_BUS", "database"),
+    ("EVENT_BUS_POLL_INTERVAL_MS", "20"),
+];
+
+on_backend!(
+    sqlite,
+    Backend::Sqlite,
+    env = SHARED_BUS,
+    events_reach_websockets_on_other_instances,
+    statuses_are_shared_between_instances,
+);
+on_backend!(
+    postgres,
+    Backend::Postgres,
+    env = SHARED_BUS,
+    events_reach_websockets_on_other_instances,
+    statuses_are_shared_between_instances,
+);
+
+async fn events_reach_websockets_on_other_instances(first: TestServer) {
+    let second = first.start_sibling().await;
+
+    let (room, owner_id) = first.create_room("Shared").await;
+    let room_id = room["id"].as_str().unwrap();
+    let mut socket = second.connect(room_id, &owner_id).await;
+
+    let names: Vec<_> = (0..20).map(|i| format!("User {}", i)).collect();
+    let users = join_all(names.iter().map(|name| first.join(room_id, name))).await;
+
+    // Votes written concurrently can commit out of id order, and none may be
+    // lost on the way to the other instance
+    let results = join_all(users.iter().map(|user| first.vote(room_id, user, "3"))).await;
+    assert!(results.iter().all(|(status, _)| *status == StatusCode::OK));
+
+    let mut voters = HashSet::new();
+    while voters.len() < users.len() {
+        let event = next_event_of(&mut socket, "voteSubmitted").await;
+        assert!(event["seq"].is_i64(), "event: {}", event);
+        voters.insert(event["payload"]["userId"].as_str().unwrap().to_string());
+    }
+
+    // The other instance dropped its cached copy of the room
+    let room = second.room(room_id).await;
+    assert_eq!(room["votes"].as_object().unwrap().len(), users.len());
+}
+
+async fn statuses_are_shared_between_instances(first: TestServer) {
+    let second = first.start_sibling().await;
+
+    let (room, owner_id) = first.create_room("Shared").await;
+    let room_id = room["id"].as_str().unwrap();
+    let alice = first.join(room_id, "Alice").await;
+    let mut owner_socket = first.connect(room_id, &owner_id).await;
+    let mut alice_socket = second.connect(room_id, &alice).await;
+
+    send_event(
+        &mut owner_socket,
+        json!({ "eventType": "setStatus", "payload": { "status": "handRaised" } }),
+    )
+    .await;
+    let event = next_event_of(&mut alice_socket, "userStatusChanged").await;
+    assert_eq!(event["payload"]["status"]["status"], "handRaised");
+
+    // The other instance reports the status it heard about
+    let room = second.room(room_id).await;
+    assert_eq!(room["userStatuses"][&owner_id]["status"], "handRaised");
+
+    send_event(
+        &mut owner_socket,
+        json!({ "eventType": "setStatus", "payload": { "status": null } }),
+    )
+    .await;
+    let event = next_event_of(&mut alice_socket, "userStatusChanged").await;
+    assert!(event["payload"]["status"].is_null(), "event: {}", event);
+    assert_eq!(second.room(room_id).await["userStatuses"], json!({}));
+}
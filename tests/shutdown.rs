@@ -0,0 +1,101 @@
+// Graceful shutdown on SIGTERM. The harness normally gives servers no time to
+// drain, these give them a little so the shutdown can be watched.
+mod common;
+
+use common::{Backend, TestServer, next_event_of};
+use futures::StreamExt;
+use reqwest::StatusCode;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::{Error, Message};
+
+const SLOW_SHUTDOWN: &[(&str, &str)] = &[
+    ("SHUTDOWN_READINESS_DELAY_SECS", "1"),
+    ("SHUTDOWN_DRAIN_PERIOD_SECS", "5"),
+];
+
+on_backend!(
+    memory,
+    Backend::Memory,
+    env = SLOW_SHUTDOWN,
+    websockets_are_told_to_reconnect,
+    readiness_fails_while_shutting_down,
+);
+on_backend!(
+    sqlite,
+    Backend::Sqlite,
+    env = SLOW_SHUTDOWN,
+    websockets_are_told_to_reconnect,
+    readiness_fails_while_shutting_down,
+);
+on_backend!(
+    postgres,
+    Backend::Postgres,
+    env = SLOW_SHUTDOWN,
+    websockets_are_told_to_reconnect,
+    readiness_fails_while_shutting_down,
+);
+
+async fn websockets_are_told_to_reconnect(mut server: TestServer) {
+    let (room, owner_id) = server.create_room("Restarting").await;
+    let room_id = room["id"].as_str().unwrap();
+    let mut socket = server.connect(room_id, &owner_id).await;
+
+    server.terminate();
+
+    // A notice saying when to come back, then a close as restarting
+    let notice = next_event_of(&mut socket, "serverShuttingDown").await;
+    assert_eq!(notice["payload"]["reconnectAfterMs"], 2000);
+    let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+        .await
+        .expect("timed out waiting for the close")
+        .expect("WebSocket closed without a close frame")
+        .expect("WebSocket error");
+    match message {
+        Message::Close(Some(frame)) => assert_eq!(frame.code, 1012.into()),
+        other => panic!("expected a close frame, got {:?}", other),
+    }
+
+    // New connections are turned away while requests are still served
+    let url = server.ws_url(room_id, &owner_id, "");
+    match tokio_tungstenite::connect_async(url).await {
+        Err(Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE)
+        }
+        other => panic!(
+            "expected the handshake to fail, got {:?}",
+            other.map(|_| ())
+        ),
+    }
+
+    let status = server.wait_for_exit().await;
+    assert!(status.success(), "exit: {}, log:\n{}", status, server.log());
+}
+
+async fn readiness_fails_while_shutting_down(mut server: TestServer) {
+    let (status, _) = server.get("/health/ready").await;
+    assert_eq!(status, StatusCode::OK);
+
+    server.terminate();
+
+    // Requests are still served during the readiness delay, but load
+    // balancers are told to stop sending them
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+    let ready = loop {
+        let (status, ready) = server.get("/health/ready").await;
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            break ready;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "still ready: {}",
+            ready
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    };
+    assert_eq!(ready["status"], "notReady");
+    assert_eq!(ready["shuttingDown"], true);
+    assert_eq!(ready["database"]["ok"], true);
+
+    let status = server.wait_for_exit().await;
+    assert!(status.success(), "exit: {}, log:\n{}", status, server.log());
+}
@@ -0,0 +1,52 @@
+// SQLite under concurrent writes through a small connection pool, where every
+// request has to wait for the single writer rather than fail with SQLITE_BUSY
+mod common;
+
+use common::{Backend, TestServer};
+use futures::future::join_all;
+use reqwest::StatusCode;
+
+const USERS: usize = 60;
+const VOTES_PER_USER: usize = 5;
+const CARDS: &[&str] = &["1", "2", "3", "5", "8"];
+
+#[tokio::test]
+async fn concurrent_votes_through_a_small_pool() {
+    let server =
+        TestServer::start_with(Backend::Sqlite, &[("DATABASE_MAX_CONNECTIONS", "2")]).await;
+
+    let (room, _) = server.create_room("Busy").await;
+    let room_id = room["id"].as_str().unwrap();
+
+    let names: Vec<_> = (0..USERS).map(|i| format!("User {}", i)).collect();
+    let users = join_all(names.iter().map(|name| server.join(room_id, name))).await;
+
+    // 300 votes at once, each user changing theirs a few times
+    let votes = users
+        .iter()
+        .flat_map(|user_id| {
+            CARDS
+                .iter()
+                .take(VOTES_PER_USER)
+                .map(move |card| (user_id, card))
+        })
+        .map(|(user_id, card)| server.vote(room_id, user_id, card));
+    let results = join_all(votes).await;
+
+    let failures: Vec<_> = results
+        .iter()
+        .filter(|(status, _)| *status != StatusCode::OK)
+        .collect();
+    assert!(failures.is_empty(), "failed votes: {:?}", failures);
+
+    let room = server.room(room_id).await;
+    assert_eq!(room["users"].as_object().unwrap().len(), USERS + 1);
+    assert_eq!(room["votes"].as_object().unwrap().len(), USERS);
+
+    // Every vote and join made it into the event log with its change
+    let (_, report) = server
+        .get(&format!("/v1/rooms/{}/history/check", room_id))
+        .await;
+    assert_eq!(report["consistent"], true, "report: {}", report);
+    assert!(report["seq"].as_i64().unwrap() >= (USERS * (VOTES_PER_USER + 1)) as i64);
+}